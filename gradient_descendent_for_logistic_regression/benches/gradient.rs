@@ -0,0 +1,63 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ml_example::model::EstimationModel;
+use ml_example::{parallel, training};
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+fn get_random_training_set(
+    row_count: usize,
+    column_count: usize,
+) -> (DMatrix<f64>, MatrixXx1<bool>) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let features_set = DMatrix::from_fn(row_count, column_count, |_, _| rng.gen_range(-1.0..1.0));
+    let target_set = MatrixXx1::from_fn(row_count, |_, _| rng.gen_bool(0.5));
+    (features_set, target_set)
+}
+
+fn bench_gradient(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_gradient");
+    for row_count in [10_000, 100_000, 1_000_000] {
+        let (features_set, target_set) = get_random_training_set(row_count, 8);
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_element(8, 0.5),
+            b: 0.5,
+        };
+        group.bench_with_input(BenchmarkId::new("serial", row_count), &row_count, |b, _| {
+            b.iter(|| training::get_serial_gradient(&features_set, &target_set, &estimation_model))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("parallel", row_count),
+            &row_count,
+            |b, _| b.iter(|| parallel::get_gradient(&features_set, &target_set, &estimation_model)),
+        );
+    }
+    group.finish();
+}
+
+fn bench_cost(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_cost_of_rows_1");
+    for row_count in [10_000, 100_000, 1_000_000] {
+        let (features_set, target_set) = get_random_training_set(row_count, 8);
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_element(8, 0.5),
+            b: 0.5,
+        };
+        group.bench_with_input(BenchmarkId::new("serial", row_count), &row_count, |b, _| {
+            b.iter(|| training::get_cost_of_rows_1(&features_set, &target_set, &estimation_model))
+        });
+        group.bench_with_input(
+            BenchmarkId::new("parallel", row_count),
+            &row_count,
+            |b, _| {
+                b.iter(|| {
+                    parallel::get_cost_of_rows_1(&features_set, &target_set, &estimation_model)
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gradient, bench_cost);
+criterion_main!(benches);
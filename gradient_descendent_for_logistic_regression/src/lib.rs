@@ -0,0 +1,15 @@
+pub mod autodiff;
+pub mod data_loader;
+pub mod gradient_check;
+pub mod metrics;
+pub mod model;
+pub mod naive_bayes;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod plot;
+pub mod report;
+pub mod softmax;
+#[cfg(feature = "sparse")]
+pub mod sparse;
+pub mod svm;
+pub mod training;
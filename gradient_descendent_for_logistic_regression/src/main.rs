@@ -1,13 +1,18 @@
-mod data_loader;
-mod model;
-mod plot;
-mod training;
-
-use crate::data_loader::load_training_set;
-use crate::model::EstimationModel;
-use crate::plot::draw_costs_plot;
-use crate::training::{estimate, gradient_descent};
-use nalgebra::RowDVector;
+use ml_example::data_loader::load_training_set;
+use ml_example::metrics::{
+    get_area_under_curve, get_confusion_matrix, get_precision_recall_curve, get_roc_curve,
+};
+use ml_example::model::EstimationModel;
+use ml_example::naive_bayes::{GaussianNaiveBayes, NaiveBayes, NaiveBayesParameters};
+use ml_example::plot::{
+    draw_confusion_matrix, draw_confusion_matrix_on, draw_costs_plot, draw_costs_plot_on,
+    draw_decision_boundary, draw_precision_recall_curve, draw_roc_curve, draw_roc_curve_on,
+    render_svg, CostScale, PlotOutput,
+};
+use ml_example::report::TrainingReport;
+use ml_example::svm::{HingeLoss, LinearSvm, SvmParameters};
+use ml_example::training::{estimate, get_estimates, gradient_descent};
+use nalgebra::{DMatrix, RowDVector};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (features_set, target_set) = load_training_set()?;
@@ -36,7 +41,164 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
     }
 
-    draw_costs_plot("plot/training.png", learning_rate, &costs)?;
+    draw_costs_plot(
+        &PlotOutput::new("plot/training.png", (640, 480)),
+        learning_rate,
+        &costs,
+        CostScale::Linear,
+    )?;
+
+    let estimates = get_estimates(&features_set, &result);
+    let positive_rate =
+        target_set.iter().filter(|target| **target).count() as f64 / target_set.len() as f64;
+    let roc_curve = get_roc_curve(&estimates, &target_set);
+    let confusion_matrix = get_confusion_matrix(&estimates, &target_set, 0.5);
+    draw_roc_curve(
+        &PlotOutput::new("plot/roc_curve.png", (640, 640)),
+        &roc_curve,
+    )?;
+    draw_precision_recall_curve(
+        &PlotOutput::new("plot/precision_recall_curve.png", (640, 640)),
+        &get_precision_recall_curve(&estimates, &target_set),
+        positive_rate,
+    )?;
+    draw_confusion_matrix(
+        &PlotOutput::new("plot/confusion_matrix.png", (640, 640)),
+        &confusion_matrix,
+    )?;
+
+    // Gaussian naive Bayes as a baseline, fitted in batches of 5 rows.
+    let mut naive_bayes =
+        GaussianNaiveBayes::new(features_set.ncols(), NaiveBayesParameters::gaussian())?;
+    for start in (0..features_set.nrows()).step_by(5) {
+        let count = 5.min(features_set.nrows() - start);
+        naive_bayes.partial_fit(
+            &features_set.rows(start, count).into_owned(),
+            &target_set.rows(start, count).into_owned(),
+        )?;
+    }
+    let naive_bayes_estimates = naive_bayes.predict_proba(&features_set);
+    let naive_bayes_confusion_matrix =
+        get_confusion_matrix(&naive_bayes_estimates, &target_set, 0.5);
+    let naive_bayes_auc = get_area_under_curve(&get_roc_curve(&naive_bayes_estimates, &target_set));
+    println!(
+        "logistic regression accuracy: {} ROC AUC: {}, Gaussian naive Bayes accuracy: {} ROC AUC: {}",
+        confusion_matrix.get_accuracy(),
+        get_area_under_curve(&roc_curve),
+        naive_bayes_confusion_matrix.get_accuracy(),
+        naive_bayes_auc
+    );
+
+    // Logistic regression and a linear SVM on the same split: every 4th row
+    // is held out.
+    let (test_rows, training_rows): (Vec<usize>, Vec<usize>) =
+        (0..features_set.nrows()).partition(|row| row % 4 == 0);
+    let training_features_set = features_set.select_rows(&training_rows);
+    let training_target_set = target_set.select_rows(&training_rows);
+    let test_features_set = features_set.select_rows(&test_rows);
+    let test_target_set = target_set.select_rows(&test_rows);
+    let (split_result, _) = gradient_descent(
+        &training_features_set,
+        &training_target_set,
+        &initial,
+        learning_rate,
+        iteration_count,
+    );
+    // The SVM is trained on features standardized with the training rows.
+    let mean = training_features_set.row_mean();
+    let std_deviation = training_features_set.row_variance().map(f64::sqrt);
+    let standardize = |features_set: &DMatrix<f64>| {
+        let mut standardized_set = features_set.clone();
+        for mut row in standardized_set.row_iter_mut() {
+            row -= &mean;
+            row.component_div_assign(&std_deviation);
+        }
+        standardized_set
+    };
+    let (mut svm, _) = LinearSvm::fit(
+        &standardize(&training_features_set),
+        &training_target_set,
+        &SvmParameters {
+            loss: HingeLoss::Hinge,
+            regularization_strength: 1.0e-3,
+            epoch_count: 200,
+            batch_size: 4,
+            seed: 0,
+        },
+    )?;
+    svm.calibrate(&standardize(&training_features_set), &training_target_set)?;
+    let svm_estimates = svm.predict_proba(&standardize(&test_features_set))?;
+    let svm_test_accuracy =
+        get_confusion_matrix(&svm_estimates, &test_target_set, 0.5).get_accuracy();
+    println!(
+        "test accuracy of logistic regression: {}, linear SVM: {}",
+        get_confusion_matrix(
+            &get_estimates(&test_features_set, &split_result),
+            &test_target_set,
+            0.5
+        )
+        .get_accuracy(),
+        svm_test_accuracy
+    );
+
+    // A second feature unrelated to the target, so the decision boundary is
+    // close to vertical.
+    let two_features_set = DMatrix::from_fn(features_set.nrows(), 2, |row, column| {
+        if column == 0 {
+            features_set[(row, 0)]
+        } else {
+            ((row * 7) % 20) as f64
+        }
+    });
+    let (two_features_result, _) = gradient_descent(
+        &two_features_set,
+        &target_set,
+        &EstimationModel {
+            parameters: RowDVector::zeros(2),
+            b: 0.0,
+        },
+        learning_rate,
+        iteration_count,
+    );
+    draw_decision_boundary(
+        &PlotOutput::new("plot/decision_boundary.png", (640, 640)),
+        &two_features_set,
+        &target_set,
+        &two_features_result,
+    )?;
+
+    let mut report = TrainingReport::new("Logistic regression");
+    report.add_hyperparameter("learning rate", learning_rate);
+    report.add_hyperparameter("iteration count", iteration_count);
+    report.add_metric("final cost", costs[costs.len() - 1]);
+    report.add_metric("accuracy", confusion_matrix.get_accuracy());
+    report.add_metric("f1 score", confusion_matrix.get_f1_score());
+    report.add_metric(
+        "Gaussian naive Bayes accuracy",
+        naive_bayes_confusion_matrix.get_accuracy(),
+    );
+    report.add_metric("Gaussian naive Bayes ROC AUC", naive_bayes_auc);
+    report.add_metric("linear SVM test accuracy", svm_test_accuracy);
+    report.add_dataset_statistic("row count", target_set.len());
+    report.add_dataset_statistic("feature count", features_set.ncols());
+    report.add_dataset_statistic("positive rate", positive_rate);
+    report.add_chart(
+        "Training cost",
+        render_svg((640, 480), |root| {
+            draw_costs_plot_on(root, learning_rate, &costs, CostScale::Linear)
+        })?,
+    );
+    report.add_chart(
+        "ROC curve",
+        render_svg((480, 480), |root| draw_roc_curve_on(root, &roc_curve))?,
+    );
+    report.add_chart(
+        "Confusion matrix",
+        render_svg((480, 480), |root| {
+            draw_confusion_matrix_on(root, &confusion_matrix)
+        })?,
+    );
+    report.save("plot/report.html")?;
 
     Ok(())
 }
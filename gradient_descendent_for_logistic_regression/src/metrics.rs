@@ -0,0 +1,201 @@
+use nalgebra::MatrixXx1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConfusionMatrix {
+    pub true_positive: usize,
+    pub false_positive: usize,
+    pub true_negative: usize,
+    pub false_negative: usize,
+}
+
+impl ConfusionMatrix {
+    pub fn get_accuracy(&self) -> f64 {
+        (self.true_positive + self.true_negative) as f64
+            / (self.true_positive + self.false_positive + self.true_negative + self.false_negative)
+                as f64
+    }
+
+    /// 1.0 when nothing was predicted positive.
+    pub fn get_precision(&self) -> f64 {
+        if self.true_positive + self.false_positive == 0 {
+            return 1.0;
+        }
+        self.true_positive as f64 / (self.true_positive + self.false_positive) as f64
+    }
+
+    /// 0.0 when there is no positive row.
+    pub fn get_recall(&self) -> f64 {
+        if self.true_positive + self.false_negative == 0 {
+            return 0.0;
+        }
+        self.true_positive as f64 / (self.true_positive + self.false_negative) as f64
+    }
+
+    pub fn get_f1_score(&self) -> f64 {
+        let precision = self.get_precision();
+        let recall = self.get_recall();
+        if precision + recall == 0.0 {
+            return 0.0;
+        }
+        2.0 * precision * recall / (precision + recall)
+    }
+}
+
+/// Rows with an estimated probability of at least `threshold` are predicted
+/// positive.
+pub fn get_confusion_matrix(
+    estimates: &[f64],
+    target_set: &MatrixXx1<bool>,
+    threshold: f64,
+) -> ConfusionMatrix {
+    let mut confusion_matrix = ConfusionMatrix::default();
+    for (estimated, target) in estimates.iter().zip(target_set.iter()) {
+        match (*estimated >= threshold, *target) {
+            (true, true) => confusion_matrix.true_positive += 1,
+            (true, false) => confusion_matrix.false_positive += 1,
+            (false, false) => confusion_matrix.true_negative += 1,
+            (false, true) => confusion_matrix.false_negative += 1,
+        }
+    }
+    confusion_matrix
+}
+
+/// Confusion matrices for every distinct estimate used as the threshold, from
+/// the highest to the lowest, starting with nothing predicted positive.
+fn get_threshold_sweep(estimates: &[f64], target_set: &MatrixXx1<bool>) -> Vec<ConfusionMatrix> {
+    let mut rows = estimates
+        .iter()
+        .cloned()
+        .zip(target_set.iter().cloned())
+        .collect::<Vec<_>>();
+    rows.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let positive_count = rows.iter().filter(|(_, target)| *target).count();
+    let mut confusion_matrix = ConfusionMatrix {
+        true_positive: 0,
+        false_positive: 0,
+        true_negative: rows.len() - positive_count,
+        false_negative: positive_count,
+    };
+    let mut sweep = vec![confusion_matrix];
+    for (index, (estimated, target)) in rows.iter().enumerate() {
+        if *target {
+            confusion_matrix.true_positive += 1;
+            confusion_matrix.false_negative -= 1;
+        } else {
+            confusion_matrix.false_positive += 1;
+            confusion_matrix.true_negative -= 1;
+        }
+        // Rows with the same estimate cross the threshold together.
+        if !matches!(rows.get(index + 1), Some((next, _)) if next == estimated) {
+            sweep.push(confusion_matrix);
+        }
+    }
+    sweep
+}
+
+/// `(false positive rate, true positive rate)` points from (0, 0) to (1, 1).
+pub fn get_roc_curve(estimates: &[f64], target_set: &MatrixXx1<bool>) -> Vec<(f64, f64)> {
+    get_threshold_sweep(estimates, target_set)
+        .iter()
+        .map(|confusion_matrix| {
+            let negative_count = confusion_matrix.false_positive + confusion_matrix.true_negative;
+            let false_positive_rate = if negative_count == 0 {
+                0.0
+            } else {
+                confusion_matrix.false_positive as f64 / negative_count as f64
+            };
+            (false_positive_rate, confusion_matrix.get_recall())
+        })
+        .collect()
+}
+
+/// `(recall, precision)` points in order of increasing recall.
+pub fn get_precision_recall_curve(
+    estimates: &[f64],
+    target_set: &MatrixXx1<bool>,
+) -> Vec<(f64, f64)> {
+    get_threshold_sweep(estimates, target_set)
+        .iter()
+        .map(|confusion_matrix| {
+            (
+                confusion_matrix.get_recall(),
+                confusion_matrix.get_precision(),
+            )
+        })
+        .collect()
+}
+
+/// Area under a curve given as points sorted by x, using the trapezoidal
+/// rule.
+pub fn get_area_under_curve(points: &[(f64, f64)]) -> f64 {
+    points
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0) * (pair[0].1 + pair[1].1) / 2.0)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confusion_matrix_counts_estimates_at_the_threshold_as_positive() {
+        let estimates = [0.9, 0.6, 0.5, 0.4, 0.1];
+        let target_set = MatrixXx1::from_vec(vec![true, false, true, true, false]);
+        let confusion_matrix = get_confusion_matrix(&estimates, &target_set, 0.5);
+        assert_eq!(
+            confusion_matrix,
+            ConfusionMatrix {
+                true_positive: 2,
+                false_positive: 1,
+                true_negative: 1,
+                false_negative: 1,
+            }
+        );
+        approx::assert_relative_eq!(confusion_matrix.get_accuracy(), 3.0 / 5.0);
+        approx::assert_relative_eq!(confusion_matrix.get_precision(), 2.0 / 3.0);
+        approx::assert_relative_eq!(confusion_matrix.get_recall(), 2.0 / 3.0);
+        approx::assert_relative_eq!(confusion_matrix.get_f1_score(), 2.0 / 3.0);
+    }
+
+    #[test]
+    fn tied_estimates_cross_the_threshold_together() {
+        let estimates = [0.8, 0.8, 0.5, 0.2];
+        let target_set = MatrixXx1::from_vec(vec![true, false, true, false]);
+        let roc_curve = get_roc_curve(&estimates, &target_set);
+        assert_eq!(
+            roc_curve,
+            vec![(0.0, 0.0), (0.5, 0.5), (0.5, 1.0), (1.0, 1.0)]
+        );
+        // 3 of the 4 positive-negative pairs are ranked correctly and the tie
+        // counts half.
+        approx::assert_relative_eq!(get_area_under_curve(&roc_curve), 0.625);
+        assert_eq!(
+            get_precision_recall_curve(&estimates, &target_set),
+            vec![(0.0, 1.0), (0.5, 0.5), (1.0, 2.0 / 3.0), (1.0, 0.5)]
+        );
+    }
+
+    #[test]
+    fn single_class_targets_do_not_divide_by_zero() {
+        let estimates = [0.7, 0.3];
+        let positive_target_set = MatrixXx1::from_vec(vec![true, true]);
+        let roc_curve = get_roc_curve(&estimates, &positive_target_set);
+        assert_eq!(roc_curve, vec![(0.0, 0.0), (0.0, 0.5), (0.0, 1.0)]);
+        assert_eq!(get_area_under_curve(&roc_curve), 0.0);
+
+        let negative_target_set = MatrixXx1::from_vec(vec![false, false]);
+        let roc_curve = get_roc_curve(&estimates, &negative_target_set);
+        assert_eq!(roc_curve, vec![(0.0, 0.0), (0.5, 0.0), (1.0, 0.0)]);
+        let confusion_matrix = get_confusion_matrix(&estimates, &negative_target_set, 0.5);
+        assert_eq!(confusion_matrix.get_accuracy(), 0.5);
+        assert_eq!(confusion_matrix.get_precision(), 0.0);
+        assert_eq!(confusion_matrix.get_recall(), 0.0);
+        assert_eq!(confusion_matrix.get_f1_score(), 0.0);
+        assert_eq!(
+            get_confusion_matrix(&estimates, &negative_target_set, 1.0).get_precision(),
+            1.0
+        );
+    }
+}
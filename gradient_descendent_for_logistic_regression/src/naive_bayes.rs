@@ -0,0 +1,512 @@
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+
+/// Settings shared by the naive Bayes classifiers.
+#[derive(Debug, Clone, Copy)]
+pub struct NaiveBayesParameters {
+    /// Laplace pseudo-count added to every feature count of the multinomial
+    /// and Bernoulli models. For the Gaussian model, the share of the largest
+    /// class variance added to every variance.
+    pub smoothing: f64,
+    /// Prior probabilities of `false` and `true`. `None` uses the class
+    /// frequencies of the rows seen so far.
+    pub priors: Option<[f64; 2]>,
+}
+
+impl NaiveBayesParameters {
+    pub fn gaussian() -> NaiveBayesParameters {
+        NaiveBayesParameters {
+            smoothing: 1.0e-9,
+            priors: None,
+        }
+    }
+
+    pub fn laplace() -> NaiveBayesParameters {
+        NaiveBayesParameters {
+            smoothing: 1.0,
+            priors: None,
+        }
+    }
+}
+
+/// Predictions shared by the naive Bayes classifiers. Probabilities are
+/// normalized in log space so that very unlikely rows do not underflow.
+pub trait NaiveBayes {
+    /// `ln P(class) + ln P(features | class)` for `false` and `true`, up to a
+    /// constant shared by both.
+    fn get_joint_log_likelihood(&self, features: &RowDVector<f64>) -> [f64; 2];
+
+    /// `ln P(class | features)` for `false` and `true`, one pair per row.
+    fn predict_log_proba(&self, features_set: &DMatrix<f64>) -> Vec<[f64; 2]> {
+        features_set
+            .row_iter()
+            .map(|features| {
+                let joint = self.get_joint_log_likelihood(&RowDVector::from(features));
+                let largest = joint[0].max(joint[1]);
+                let normalizer =
+                    largest + ((joint[0] - largest).exp() + (joint[1] - largest).exp()).ln();
+                [joint[0] - normalizer, joint[1] - normalizer]
+            })
+            .collect()
+    }
+
+    /// Probability of `true` for every row, as `training::get_estimates`
+    /// returns for the logistic model, so that both share the `metrics`.
+    fn predict_proba(&self, features_set: &DMatrix<f64>) -> Vec<f64> {
+        self.predict_log_proba(features_set)
+            .iter()
+            .map(|log_probabilities| log_probabilities[1].exp())
+            .collect()
+    }
+
+    fn predict(&self, features_set: &DMatrix<f64>) -> Vec<bool> {
+        self.predict_log_proba(features_set)
+            .iter()
+            .map(|log_probabilities| log_probabilities[1] >= log_probabilities[0])
+            .collect()
+    }
+}
+
+fn check_training_set(
+    features_set: &DMatrix<f64>,
+    target_set: &MatrixXx1<bool>,
+    feature_count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if features_set.nrows() != target_set.nrows() {
+        return Err(format!(
+            "{} feature rows but {} targets",
+            features_set.nrows(),
+            target_set.nrows()
+        )
+        .into());
+    }
+    if features_set.ncols() != feature_count {
+        return Err(format!(
+            "expected {} features but got {}",
+            feature_count,
+            features_set.ncols()
+        )
+        .into());
+    }
+    if features_set.iter().any(|feature| !feature.is_finite()) {
+        return Err("features must be finite".into());
+    }
+    Ok(())
+}
+
+fn check_parameters(parameters: &NaiveBayesParameters) -> Result<(), Box<dyn std::error::Error>> {
+    if parameters.smoothing < 0.0 {
+        return Err("smoothing must not be negative".into());
+    }
+    if let Some(priors) = parameters.priors {
+        if priors.iter().any(|prior| *prior < 0.0) || (priors[0] + priors[1] - 1.0).abs() > 1.0e-9 {
+            return Err("priors must be non-negative and add up to 1".into());
+        }
+    }
+    Ok(())
+}
+
+/// `ln P(class)`, from the fixed priors or from the class counts.
+fn get_log_prior(class_counts: &[f64; 2], priors: Option<[f64; 2]>, class: usize) -> f64 {
+    match priors {
+        Some(priors) => priors[class].ln(),
+        None => (class_counts[class] / (class_counts[0] + class_counts[1])).ln(),
+    }
+}
+
+/// Features are independent normal distributions within each class.
+#[derive(Debug, Clone)]
+pub struct GaussianNaiveBayes {
+    pub parameters: NaiveBayesParameters,
+    /// Rows seen of `false` and `true`.
+    pub class_counts: [f64; 2],
+    pub means: [RowDVector<f64>; 2],
+    /// Sums of squared deviations from `means`, kept instead of the variances
+    /// so that `partial_fit` can merge batches exactly.
+    squared_deviations: [RowDVector<f64>; 2],
+}
+
+impl GaussianNaiveBayes {
+    pub fn new(
+        feature_count: usize,
+        parameters: NaiveBayesParameters,
+    ) -> Result<GaussianNaiveBayes, Box<dyn std::error::Error>> {
+        check_parameters(&parameters)?;
+        Ok(GaussianNaiveBayes {
+            parameters,
+            class_counts: [0.0; 2],
+            means: [
+                RowDVector::zeros(feature_count),
+                RowDVector::zeros(feature_count),
+            ],
+            squared_deviations: [
+                RowDVector::zeros(feature_count),
+                RowDVector::zeros(feature_count),
+            ],
+        })
+    }
+
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+        parameters: NaiveBayesParameters,
+    ) -> Result<GaussianNaiveBayes, Box<dyn std::error::Error>> {
+        let mut model = GaussianNaiveBayes::new(features_set.ncols(), parameters)?;
+        model.partial_fit(features_set, target_set)?;
+        Ok(model)
+    }
+
+    /// Updates the statistics with another batch of rows. Fitting batch by
+    /// batch gives the same model as fitting all rows at once.
+    pub fn partial_fit(
+        &mut self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        check_training_set(features_set, target_set, self.means[0].len())?;
+        for class in 0..2 {
+            let rows = features_set
+                .row_iter()
+                .zip(target_set.iter())
+                .filter(|(_, target)| **target as usize == class)
+                .map(|(features, _)| RowDVector::from(features))
+                .collect::<Vec<_>>();
+            if rows.is_empty() {
+                continue;
+            }
+            let batch_count = rows.len() as f64;
+            let batch_mean = rows
+                .iter()
+                .fold(RowDVector::zeros(self.means[class].len()), |sum, row| {
+                    sum + row
+                })
+                / batch_count;
+            let batch_squared_deviation = rows
+                .iter()
+                .fold(RowDVector::zeros(self.means[class].len()), |sum, row| {
+                    sum + (row - &batch_mean).map(|deviation| deviation.powi(2))
+                });
+
+            // Chan et al.'s update for merging the statistics of two sets.
+            let count = self.class_counts[class];
+            let total_count = count + batch_count;
+            let delta = &batch_mean - &self.means[class];
+            self.squared_deviations[class] += batch_squared_deviation
+                + delta.map(|difference| difference.powi(2)) * (count * batch_count / total_count);
+            self.means[class] += delta * (batch_count / total_count);
+            self.class_counts[class] = total_count;
+        }
+        Ok(())
+    }
+
+    /// Variance of every feature within each class, plus the smoothing.
+    pub fn get_variances(&self) -> [RowDVector<f64>; 2] {
+        let variances = [0, 1].map(|class| {
+            if self.class_counts[class] == 0.0 {
+                RowDVector::zeros(self.means[class].len())
+            } else {
+                &self.squared_deviations[class] / self.class_counts[class]
+            }
+        });
+        let epsilon = self.parameters.smoothing * variances[0].max().max(variances[1].max());
+        variances.map(|variance| variance.add_scalar(epsilon))
+    }
+}
+
+impl NaiveBayes for GaussianNaiveBayes {
+    fn get_joint_log_likelihood(&self, features: &RowDVector<f64>) -> [f64; 2] {
+        let variances = self.get_variances();
+        [0, 1].map(|class| {
+            if self.class_counts[class] == 0.0 {
+                return f64::NEG_INFINITY;
+            }
+            get_log_prior(&self.class_counts, self.parameters.priors, class)
+                + features
+                    .iter()
+                    .zip(self.means[class].iter())
+                    .zip(variances[class].iter())
+                    .map(|((feature, mean), variance)| {
+                        -0.5 * ((2.0 * std::f64::consts::PI * variance).ln()
+                            + (feature - mean).powi(2) / variance)
+                    })
+                    .sum::<f64>()
+        })
+    }
+}
+
+/// Features are non-negative counts drawn from one multinomial distribution
+/// per class.
+#[derive(Debug, Clone)]
+pub struct MultinomialNaiveBayes {
+    pub parameters: NaiveBayesParameters,
+    pub class_counts: [f64; 2],
+    /// Sum of every feature over the rows of each class.
+    pub feature_counts: [RowDVector<f64>; 2],
+}
+
+impl MultinomialNaiveBayes {
+    pub fn new(
+        feature_count: usize,
+        parameters: NaiveBayesParameters,
+    ) -> Result<MultinomialNaiveBayes, Box<dyn std::error::Error>> {
+        check_parameters(&parameters)?;
+        Ok(MultinomialNaiveBayes {
+            parameters,
+            class_counts: [0.0; 2],
+            feature_counts: [
+                RowDVector::zeros(feature_count),
+                RowDVector::zeros(feature_count),
+            ],
+        })
+    }
+
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+        parameters: NaiveBayesParameters,
+    ) -> Result<MultinomialNaiveBayes, Box<dyn std::error::Error>> {
+        let mut model = MultinomialNaiveBayes::new(features_set.ncols(), parameters)?;
+        model.partial_fit(features_set, target_set)?;
+        Ok(model)
+    }
+
+    pub fn partial_fit(
+        &mut self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        check_training_set(features_set, target_set, self.feature_counts[0].len())?;
+        if features_set.iter().any(|feature| *feature < 0.0) {
+            return Err("multinomial naive Bayes needs non-negative counts".into());
+        }
+        for (features, target) in features_set.row_iter().zip(target_set.iter()) {
+            let class = *target as usize;
+            self.class_counts[class] += 1.0;
+            self.feature_counts[class] += features;
+        }
+        Ok(())
+    }
+
+    /// `ln P(feature | class)` with Laplace smoothing.
+    pub fn get_feature_log_probabilities(&self) -> [RowDVector<f64>; 2] {
+        let smoothing = self.parameters.smoothing;
+        [0, 1].map(|class| {
+            let total = self.feature_counts[class].sum()
+                + smoothing * self.feature_counts[class].len() as f64;
+            self.feature_counts[class].map(|count| ((count + smoothing) / total).ln())
+        })
+    }
+}
+
+impl NaiveBayes for MultinomialNaiveBayes {
+    fn get_joint_log_likelihood(&self, features: &RowDVector<f64>) -> [f64; 2] {
+        let feature_log_probabilities = self.get_feature_log_probabilities();
+        [0, 1].map(|class| {
+            if self.class_counts[class] == 0.0 {
+                return f64::NEG_INFINITY;
+            }
+            get_log_prior(&self.class_counts, self.parameters.priors, class)
+                + features
+                    .iter()
+                    .zip(feature_log_probabilities[class].iter())
+                    // A feature that never occurs adds nothing, even when
+                    // its unsmoothed log probability is -inf.
+                    .filter(|(feature, _)| **feature > 0.0)
+                    .map(|(feature, log_probability)| feature * log_probability)
+                    .sum::<f64>()
+        })
+    }
+}
+
+/// Features are independent binary variables within each class. A feature
+/// is present when it is greater than 0, and absent features count against
+/// a class as well.
+#[derive(Debug, Clone)]
+pub struct BernoulliNaiveBayes {
+    pub parameters: NaiveBayesParameters,
+    pub class_counts: [f64; 2],
+    /// Number of rows of each class in which every feature is present.
+    pub feature_counts: [RowDVector<f64>; 2],
+}
+
+impl BernoulliNaiveBayes {
+    pub fn new(
+        feature_count: usize,
+        parameters: NaiveBayesParameters,
+    ) -> Result<BernoulliNaiveBayes, Box<dyn std::error::Error>> {
+        check_parameters(&parameters)?;
+        Ok(BernoulliNaiveBayes {
+            parameters,
+            class_counts: [0.0; 2],
+            feature_counts: [
+                RowDVector::zeros(feature_count),
+                RowDVector::zeros(feature_count),
+            ],
+        })
+    }
+
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+        parameters: NaiveBayesParameters,
+    ) -> Result<BernoulliNaiveBayes, Box<dyn std::error::Error>> {
+        let mut model = BernoulliNaiveBayes::new(features_set.ncols(), parameters)?;
+        model.partial_fit(features_set, target_set)?;
+        Ok(model)
+    }
+
+    pub fn partial_fit(
+        &mut self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        check_training_set(features_set, target_set, self.feature_counts[0].len())?;
+        for (features, target) in features_set.row_iter().zip(target_set.iter()) {
+            let class = *target as usize;
+            self.class_counts[class] += 1.0;
+            self.feature_counts[class] +=
+                features.map(|feature| if feature > 0.0 { 1.0 } else { 0.0 });
+        }
+        Ok(())
+    }
+
+    /// `P(feature present | class)` with Laplace smoothing.
+    pub fn get_feature_probabilities(&self) -> [RowDVector<f64>; 2] {
+        let smoothing = self.parameters.smoothing;
+        [0, 1].map(|class| {
+            self.feature_counts[class]
+                .map(|count| (count + smoothing) / (self.class_counts[class] + 2.0 * smoothing))
+        })
+    }
+}
+
+impl NaiveBayes for BernoulliNaiveBayes {
+    fn get_joint_log_likelihood(&self, features: &RowDVector<f64>) -> [f64; 2] {
+        let feature_probabilities = self.get_feature_probabilities();
+        [0, 1].map(|class| {
+            if self.class_counts[class] == 0.0 {
+                return f64::NEG_INFINITY;
+            }
+            get_log_prior(&self.class_counts, self.parameters.priors, class)
+                + features
+                    .iter()
+                    .zip(feature_probabilities[class].iter())
+                    .map(|(feature, probability)| {
+                        if *feature > 0.0 {
+                            probability.ln()
+                        } else {
+                            (1.0 - probability).ln()
+                        }
+                    })
+                    .sum::<f64>()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_loader::load_training_set;
+    use crate::metrics::get_confusion_matrix;
+
+    #[test]
+    fn gaussian_separates_synthetic_training_set() {
+        let (features_set, target_set) = load_training_set().unwrap();
+        let model =
+            GaussianNaiveBayes::fit(&features_set, &target_set, NaiveBayesParameters::gaussian())
+                .unwrap();
+        let estimates = model.predict_proba(&features_set);
+        assert_eq!(
+            get_confusion_matrix(&estimates, &target_set, 0.5).get_accuracy(),
+            1.0
+        );
+        // Far from both classes, the probabilities stay finite in log space.
+        let far = DMatrix::from_row_slice(2, 1, &[-1.0e6, 1.0e6]);
+        assert_eq!(model.predict(&far), vec![false, true]);
+        for log_probabilities in model.predict_log_proba(&far) {
+            assert!(log_probabilities.iter().all(|value| !value.is_nan()));
+        }
+    }
+
+    #[test]
+    fn gaussian_partial_fit_matches_fit() {
+        let features_set = DMatrix::from_row_slice(
+            7,
+            2,
+            &[
+                1.0, 2.0, 1.5, 1.0, 3.0, -1.0, 0.5, 0.0, 4.0, 2.5, 2.0, 2.0, -1.0, 3.0,
+            ],
+        );
+        let target_set = MatrixXx1::from_vec(vec![true, false, true, false, true, true, false]);
+        let model =
+            GaussianNaiveBayes::fit(&features_set, &target_set, NaiveBayesParameters::gaussian())
+                .unwrap();
+        let mut incremental = GaussianNaiveBayes::new(2, NaiveBayesParameters::gaussian()).unwrap();
+        for (start, count) in [(0, 1), (1, 4), (5, 2)] {
+            incremental
+                .partial_fit(
+                    &features_set.rows(start, count).into_owned(),
+                    &target_set.rows(start, count).into_owned(),
+                )
+                .unwrap();
+        }
+        assert_eq!(incremental.class_counts, model.class_counts);
+        for class in 0..2 {
+            approx::assert_relative_eq!(
+                incremental.means[class],
+                model.means[class],
+                max_relative = 1.0e-12
+            );
+            approx::assert_relative_eq!(
+                incremental.get_variances()[class],
+                model.get_variances()[class],
+                max_relative = 1.0e-12
+            );
+        }
+    }
+
+    #[test]
+    fn multinomial_matches_hand_computed_probabilities() {
+        let features_set = DMatrix::from_row_slice(3, 2, &[2.0, 0.0, 1.0, 1.0, 0.0, 3.0]);
+        let target_set = MatrixXx1::from_vec(vec![true, true, false]);
+        let mut model =
+            MultinomialNaiveBayes::fit(&features_set, &target_set, NaiveBayesParameters::laplace())
+                .unwrap();
+        // true: counts (3, 1), smoothed (4, 2) / 6. false: (0, 3) -> (1, 4) / 5.
+        let row = DMatrix::from_row_slice(1, 2, &[1.0, 1.0]);
+        let joint_true = (2.0_f64 / 3.0).ln() + (4.0_f64 / 6.0).ln() + (2.0_f64 / 6.0).ln();
+        let joint_false = (1.0_f64 / 3.0).ln() + (1.0_f64 / 5.0).ln() + (4.0_f64 / 5.0).ln();
+        approx::assert_relative_eq!(
+            model.predict_proba(&row)[0],
+            joint_true.exp() / (joint_true.exp() + joint_false.exp()),
+            max_relative = 1.0e-12
+        );
+        assert!(model
+            .partial_fit(
+                &DMatrix::from_row_slice(1, 2, &[-1.0, 0.0]),
+                &target_set.rows(0, 1).into_owned()
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn bernoulli_uses_priors_and_absent_features() {
+        let features_set = DMatrix::from_row_slice(4, 2, &[1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        let target_set = MatrixXx1::from_vec(vec![true, true, false, false]);
+        let model =
+            BernoulliNaiveBayes::fit(&features_set, &target_set, NaiveBayesParameters::laplace())
+                .unwrap();
+        let rows = DMatrix::from_row_slice(2, 2, &[1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(model.predict(&rows), vec![true, false]);
+
+        let skewed = BernoulliNaiveBayes::fit(
+            &features_set,
+            &target_set,
+            NaiveBayesParameters {
+                smoothing: 1.0,
+                priors: Some([0.001, 0.999]),
+            },
+        )
+        .unwrap();
+        assert_eq!(skewed.predict(&rows), vec![true, true]);
+    }
+}
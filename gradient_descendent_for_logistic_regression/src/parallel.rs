@@ -0,0 +1,78 @@
+use crate::model::{EstimationModel, Gradient};
+pub use crate::training::CHUNK_ROW_COUNT;
+use crate::training::{
+    get_chunk_cost, get_chunk_gradient, get_chunk_ranges, get_mean_gradient, FeaturesSet,
+};
+use nalgebra::MatrixXx1;
+use rayon::prelude::*;
+
+/// `training::get_cost_of_rows_1` with the chunks summed on the rayon
+/// thread pool.
+pub fn get_cost_of_rows_1(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> f64 {
+    let row_count = features_set.get_row_count();
+    let partial_costs = get_chunk_ranges(row_count)
+        .into_par_iter()
+        .map(|range| get_chunk_cost(features_set, target_set, estimation_model, range))
+        .collect::<Vec<f64>>();
+    // Reduce in chunk order on a single thread to keep the result deterministic.
+    let total_count = partial_costs.iter().sum::<f64>();
+    total_count / row_count as f64
+}
+
+/// `training::get_serial_gradient` with the chunks summed on the rayon
+/// thread pool.
+pub fn get_gradient(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> Gradient {
+    let partial_gradients = get_chunk_ranges(features_set.get_row_count())
+        .into_par_iter()
+        .map(|range| get_chunk_gradient(features_set, target_set, estimation_model, range))
+        .collect::<Vec<Gradient>>();
+    // Reduce in chunk order on a single thread to keep the result deterministic.
+    get_mean_gradient(
+        partial_gradients,
+        estimation_model.parameters.len(),
+        features_set.get_row_count(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::training::{get_cost_of_rows_1 as get_serial_cost, get_serial_gradient};
+    use nalgebra::{DMatrix, RowDVector};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn matches_serial_path_bit_for_bit() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_vec(vec![0.3, -1.2]),
+            b: 0.1,
+        };
+        for row_count in [1, 1023, 1024, 1025, 3000] {
+            let features_set =
+                DMatrix::<f64>::from_fn(row_count, 2, |_, _| rng.gen_range(-3.0..3.0));
+            let target_set = MatrixXx1::from_fn(row_count, |_, _| rng.gen_bool(0.5));
+            let serial_gradient =
+                get_serial_gradient(&features_set, &target_set, &estimation_model);
+            let parallel_gradient = get_gradient(&features_set, &target_set, &estimation_model);
+            assert_eq!(
+                serial_gradient.parameters.map(f64::to_bits),
+                parallel_gradient.parameters.map(f64::to_bits)
+            );
+            assert_eq!(serial_gradient.b.to_bits(), parallel_gradient.b.to_bits());
+            assert_eq!(
+                get_serial_cost(&features_set, &target_set, &estimation_model).to_bits(),
+                get_cost_of_rows_1(&features_set, &target_set, &estimation_model).to_bits()
+            );
+        }
+    }
+}
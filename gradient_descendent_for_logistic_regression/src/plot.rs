@@ -1,37 +1,570 @@
+use crate::metrics::{get_area_under_curve, ConfusionMatrix};
+use crate::model::EstimationModel;
+use crate::training::estimate;
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use plotters::coord::ranged1d::ValueFormatter;
+use plotters::coord::types::RangedCoordusize;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotFormat {
+    Png,
+    Svg,
+}
+
+/// Where and how a plot is written. Every `draw_*` function takes one and has
+/// a `draw_*_on` counterpart drawing on any plotters backend.
+#[derive(Debug, Clone)]
+pub struct PlotOutput {
+    pub filename: String,
+    pub format: PlotFormat,
+    pub size: (u32, u32),
+}
+
+impl PlotOutput {
+    /// The format follows the extension: `.svg` is written as SVG, anything
+    /// else as PNG.
+    pub fn new(filename: &str, size: (u32, u32)) -> PlotOutput {
+        let format = if filename.to_lowercase().ends_with(".svg") {
+            PlotFormat::Svg
+        } else {
+            PlotFormat::Png
+        };
+        PlotOutput {
+            filename: filename.to_string(),
+            format,
+            size,
+        }
+    }
+}
+
+macro_rules! draw_on {
+    ($output:expr, $draw:ident($($argument:expr),*)) => {
+        match $output.format {
+            PlotFormat::Png => $draw(
+                BitMapBackend::new(&$output.filename, $output.size).into_drawing_area()
+                $(, $argument)*
+            ),
+            PlotFormat::Svg => $draw(
+                SVGBackend::new(&$output.filename, $output.size).into_drawing_area()
+                $(, $argument)*
+            ),
+        }
+    };
+}
+
+/// Draws into an in-memory SVG document, e.g. to embed it in a report.
+pub fn render_svg(
+    size: (u32, u32),
+    draw: impl FnOnce(DrawingArea<SVGBackend, Shift>) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut svg = String::new();
+    draw(SVGBackend::with_string(&mut svg, size).into_drawing_area())?;
+    Ok(svg)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostScale {
+    Linear,
+    /// Useful when the cost falls by orders of magnitude. Only positive costs
+    /// are drawn.
+    Log,
+}
+
 pub fn draw_costs_plot(
-    filename: &str,
+    output: &PlotOutput,
     learning_rate: f64,
     costs: &[f64],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_costs_plot_on(learning_rate, costs, scale))
+}
+
+pub fn draw_costs_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    learning_rate: f64,
+    costs: &[f64],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    draw_costs_series_on(
+        root,
+        &format!("Learning Rate: {}", learning_rate),
+        &[("cost", costs)],
+        scale,
+    )
+}
+
+/// Overlays several cost histories on one chart, e.g. one per learning rate.
+pub fn draw_costs_comparison_plot(
+    output: &PlotOutput,
+    series: &[(&str, &[f64])],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_costs_comparison_plot_on(series, scale))
+}
+
+pub fn draw_costs_comparison_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    series: &[(&str, &[f64])],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    draw_costs_series_on(root, "Cost comparison", series, scale)
+}
+
+/// NaN and infinite costs, and non-positive ones on a log scale, are left out
+/// of both the axis range and the lines.
+fn draw_costs_series_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    caption: &str,
+    series: &[(&str, &[f64])],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let iteration_count = series
+        .iter()
+        .map(|(_, costs)| costs.len())
+        .max()
+        .unwrap_or(0);
+    if iteration_count == 0 {
+        return Err("no cost to plot".into());
+    }
+    let is_drawable = |cost: &f64| cost.is_finite() && (scale == CostScale::Linear || *cost > 0.0);
+    let drawable_costs = series
+        .iter()
+        .flat_map(|(_, costs)| costs.iter())
+        .filter(|cost| is_drawable(cost))
+        .collect::<Vec<_>>();
+    if drawable_costs.is_empty() {
+        return Err(match scale {
+            CostScale::Linear => "no finite cost to plot",
+            CostScale::Log => "no positive finite cost to plot on a log scale",
+        }
+        .into());
+    }
+
+    root.fill(&WHITE)?;
+    let mut chart_builder = ChartBuilder::on(&root);
+    chart_builder
+        .margin(20)
+        .caption(caption, ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(60);
+    match scale {
+        CostScale::Linear => {
+            let chart = chart_builder
+                .build_cartesian_2d(0..iteration_count, get_range(drawable_costs.into_iter()))?;
+            draw_costs_series(chart, series, is_drawable)?;
+        }
+        CostScale::Log => {
+            let (min, max) = drawable_costs
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), cost| {
+                    (min.min(**cost), max.max(**cost))
+                });
+            let chart = chart_builder
+                .build_cartesian_2d(0..iteration_count, ((min * 0.8)..(max * 1.25)).log_scale())?;
+            draw_costs_series(chart, series, is_drawable)?;
+        }
+    }
+    root.present()?;
+    Ok(())
+}
+
+fn draw_costs_series<'a, DB, Y>(
+    mut chart: ChartContext<'a, DB, Cartesian2d<RangedCoordusize, Y>>,
+    series: &[(&str, &[f64])],
+    is_drawable: impl Fn(&f64) -> bool,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
+    chart
+        .configure_mesh()
+        .x_desc("iteration")
+        .y_desc("cost of function")
+        .draw()?;
+    for (index, (label, costs)) in series.iter().enumerate() {
+        let color = if series.len() == 1 {
+            RED.to_rgba()
+        } else {
+            Palette99::pick(index).to_rgba()
+        };
+        chart
+            .draw_series(LineSeries::new(
+                costs
+                    .iter()
+                    .cloned()
+                    .enumerate()
+                    .filter(|(_, cost)| is_drawable(cost)),
+                color,
+            ))?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    if series.len() > 1 {
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+    }
+    Ok(())
+}
+
+/// Range covering every finite value with 5% padding on both sides.
+fn get_range<'a>(values: impl Iterator<Item = &'a f64>) -> std::ops::Range<f64> {
+    let (min, max) = values
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(*value), max.max(*value))
+        });
+    if min > max {
+        return 0.0..1.0;
+    }
+    let padding = if max > min {
+        (max - min) * 0.05
+    } else {
+        min.abs().max(1.0) * 0.05
+    };
+    (min - padding)..(max + padding)
+}
+
+pub fn draw_learning_curves(
+    output: &PlotOutput,
+    training_costs: &[f64],
+    validation_costs: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(
+        output,
+        draw_learning_curves_on(training_costs, validation_costs)
+    )
+}
+
+pub fn draw_learning_curves_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    training_costs: &[f64],
+    validation_costs: &[f64],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Learning curves", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(
+            0..training_costs.len().max(validation_costs.len()),
+            get_range(training_costs.iter().chain(validation_costs.iter())),
+        )?;
+    chart
+        .configure_mesh()
+        .x_desc("iteration")
+        .y_desc("cost of function")
+        .draw()?;
+    chart
+        .draw_series(LineSeries::new(
+            training_costs.iter().cloned().enumerate(),
+            &RED,
+        ))?
+        .label("training")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+    chart
+        .draw_series(LineSeries::new(
+            validation_costs.iter().cloned().enumerate(),
+            &BLUE,
+        ))?
+        .label("validation")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    root.present()?;
+    Ok(())
+}
+
+fn draw_curve_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    caption: &str,
+    x_desc: &str,
+    y_desc: &str,
+    points: &[(f64, f64)],
+    baseline: Vec<(f64, f64)>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption(caption, ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(0.0..1.0, 0.0..1.05)?;
+    chart
+        .configure_mesh()
+        .x_desc(x_desc)
+        .y_desc(y_desc)
+        .draw()?;
+    chart.draw_series(LineSeries::new(baseline, BLACK.mix(0.3)))?;
+    chart.draw_series(LineSeries::new(points.iter().cloned(), &RED))?;
+    root.present()?;
+    Ok(())
+}
+
+pub fn draw_roc_curve(
+    output: &PlotOutput,
+    points: &[(f64, f64)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_roc_curve_on(points))
+}
+
+pub fn draw_roc_curve_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    points: &[(f64, f64)],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    draw_curve_plot_on(
+        root,
+        &format!("ROC curve (AUC: {:.3})", get_area_under_curve(points)),
+        "false positive rate",
+        "true positive rate",
+        points,
+        vec![(0.0, 0.0), (1.0, 1.0)],
+    )
+}
+
+/// `positive_rate` is the share of positive rows, the precision of a random
+/// classifier.
+pub fn draw_precision_recall_curve(
+    output: &PlotOutput,
+    points: &[(f64, f64)],
+    positive_rate: f64,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Plot
-    let root = BitMapBackend::new(filename, (640, 480)).into_drawing_area();
+    draw_on!(
+        output,
+        draw_precision_recall_curve_on(points, positive_rate)
+    )
+}
+
+pub fn draw_precision_recall_curve_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    points: &[(f64, f64)],
+    positive_rate: f64,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    draw_curve_plot_on(
+        root,
+        &format!(
+            "Precision-recall curve (AUC: {:.3})",
+            get_area_under_curve(points)
+        ),
+        "recall",
+        "precision",
+        points,
+        vec![(0.0, positive_rate), (1.0, positive_rate)],
+    )
+}
+
+pub fn draw_confusion_matrix(
+    output: &PlotOutput,
+    confusion_matrix: &ConfusionMatrix,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_confusion_matrix_on(confusion_matrix))
+}
+
+pub fn draw_confusion_matrix_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    confusion_matrix: &ConfusionMatrix,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
     root.fill(&WHITE)?;
     let mut chart = ChartBuilder::on(&root)
         .margin(20)
         .caption(
-            format!("Learning Rate: {}", learning_rate),
+            format!(
+                "Confusion matrix (accuracy: {:.3})",
+                confusion_matrix.get_accuracy()
+            ),
             ("sans-serif", 20),
         )
-        .set_label_area_size(LabelAreaPosition::Left, 60)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d((0..1).into_segmented(), (0..1).into_segmented())?;
+    let get_label = |value: &SegmentValue<i32>| match value {
+        SegmentValue::CenterOf(value) => format!("{}", *value == 1),
+        _ => String::new(),
+    };
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_label_formatter(&get_label)
+        .y_label_formatter(&get_label)
+        .x_desc("estimated")
+        .y_desc("target")
+        .draw()?;
+
+    let cells = [
+        (0, 0, confusion_matrix.true_negative),
+        (1, 0, confusion_matrix.false_positive),
+        (0, 1, confusion_matrix.false_negative),
+        (1, 1, confusion_matrix.true_positive),
+    ];
+    let max_count = cells
+        .iter()
+        .map(|(_, _, count)| *count)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    chart.draw_series(cells.iter().map(|(x, y, count)| {
+        Rectangle::new(
+            [
+                (SegmentValue::Exact(*x), SegmentValue::Exact(*y)),
+                (SegmentValue::Exact(x + 1), SegmentValue::Exact(y + 1)),
+            ],
+            BLUE.mix(0.1 + 0.8 * *count as f64 / max_count as f64)
+                .filled(),
+        )
+    }))?;
+    chart.draw_series(cells.iter().map(|(x, y, count)| {
+        Text::new(
+            count.to_string(),
+            (SegmentValue::CenterOf(*x), SegmentValue::CenterOf(*y)),
+            ("sans-serif", 30).into_font().color(&BLACK),
+        )
+    }))?;
+    root.present()?;
+    Ok(())
+}
+
+/// Shades the estimated probability over the plane of two features and marks
+/// the 0.5 boundary. Only models with exactly two features can be drawn.
+pub fn draw_decision_boundary(
+    output: &PlotOutput,
+    features_set: &DMatrix<f64>,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(
+        output,
+        draw_decision_boundary_on(features_set, target_set, estimation_model)
+    )
+}
+
+pub fn draw_decision_boundary_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    features_set: &DMatrix<f64>,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    if features_set.ncols() != 2 || estimation_model.parameters.len() != 2 {
+        return Err("decision boundary can only be drawn for two features".into());
+    }
+    let x_range = get_range(features_set.column(0).iter());
+    let y_range = get_range(features_set.column(1).iter());
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Decision boundary", ("sans-serif", 20))
         .x_label_area_size(30)
         .y_label_area_size(50)
-        .build_cartesian_2d(0..costs.len(), 0.0..(costs[0] * 1.2))?;
+        .build_cartesian_2d(x_range.clone(), y_range.clone())?;
     chart
         .configure_mesh()
-        .x_desc("iteration")
-        .y_desc("cost of function")
+        .x_desc("feature 0")
+        .y_desc("feature 1")
+        .draw()?;
+
+    let grid_count = 100;
+    let x_step = (x_range.end - x_range.start) / grid_count as f64;
+    let y_step = (y_range.end - y_range.start) / grid_count as f64;
+    chart.draw_series((0..grid_count * grid_count).map(|index| {
+        let x = x_range.start + (index % grid_count) as f64 * x_step;
+        let y = y_range.start + (index / grid_count) as f64 * y_step;
+        let probability = estimate(
+            &RowDVector::from_vec(vec![x + x_step / 2.0, y + y_step / 2.0]),
+            estimation_model,
+        );
+        let color = if probability >= 0.5 { &RED } else { &BLUE };
+        Rectangle::new(
+            [(x, y), (x + x_step, y + y_step)],
+            color.mix(0.4 * (probability - 0.5).abs()).filled(),
+        )
+    }))?;
+
+    // w0 * x + w1 * y + b = 0, solved for the coordinate with the larger
+    // weight so that steep and vertical lines are drawn along their whole
+    // length. There is no line when both weights are 0.
+    let (w0, w1) = (
+        estimation_model.parameters[0],
+        estimation_model.parameters[1],
+    );
+    let boundary = if w0 == 0.0 && w1 == 0.0 {
+        Vec::new()
+    } else if w1.abs() >= w0.abs() {
+        (0..=grid_count)
+            .map(|index| x_range.start + index as f64 * x_step)
+            .map(|x| (x, -(w0 * x + estimation_model.b) / w1))
+            .filter(|(_, y)| y_range.contains(y))
+            .collect::<Vec<_>>()
+    } else {
+        (0..=grid_count)
+            .map(|index| y_range.start + index as f64 * y_step)
+            .map(|y| (-(w1 * y + estimation_model.b) / w0, y))
+            .filter(|(x, _)| x_range.contains(x))
+            .collect::<Vec<_>>()
+    };
+    chart.draw_series(LineSeries::new(boundary, BLACK.stroke_width(2)))?;
+
+    chart
+        .draw_series(
+            features_set
+                .row_iter()
+                .zip(target_set.iter())
+                .filter(|(_, target)| **target)
+                .map(|(features, _)| Circle::new((features[0], features[1]), 3, RED.filled())),
+        )?
+        .label("true")
+        .legend(|(x, y)| Circle::new((x + 10, y), 3, RED.filled()));
+    chart
+        .draw_series(
+            features_set
+                .row_iter()
+                .zip(target_set.iter())
+                .filter(|(_, target)| !**target)
+                .map(|(features, _)| Circle::new((features[0], features[1]), 3, BLUE.filled())),
+        )?
+        .label("false")
+        .legend(|(x, y)| Circle::new((x + 10, y), 3, BLUE.filled()));
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
         .draw()?;
-    chart.draw_series(LineSeries::new(
-        costs
-            .iter()
-            .enumerate()
-            .map(|(index, cost)| (index, cost.clone())),
-        &RED,
-    ))?;
-    chart.configure_series_labels().draw()?;
     root.present()?;
     Ok(())
 }
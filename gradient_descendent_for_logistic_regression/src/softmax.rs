@@ -0,0 +1,204 @@
+use crate::model::{EstimationModel, Gradient};
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+
+/// Probabilities `exp(z_k) / Σ exp(z_j)` of a row of logits. The largest
+/// logit is subtracted first so that large logits do not overflow.
+pub fn get_softmax(logits: &[f64]) -> Vec<f64> {
+    let max_logit = logits.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let exponentials = logits
+        .iter()
+        .map(|logit| (logit - max_logit).exp())
+        .collect::<Vec<_>>();
+    let total = exponentials.iter().sum::<f64>();
+    exponentials
+        .iter()
+        .map(|exponential| exponential / total)
+        .collect()
+}
+
+/// Multinomial logistic regression: one linear model per class, whose
+/// estimates are the logits of the softmax.
+#[derive(Debug, Clone)]
+pub struct SoftmaxModel {
+    pub estimation_models: Vec<EstimationModel>,
+}
+
+impl SoftmaxModel {
+    pub fn new(class_count: usize, feature_count: usize) -> SoftmaxModel {
+        SoftmaxModel {
+            estimation_models: vec![
+                EstimationModel {
+                    parameters: RowDVector::zeros(feature_count),
+                    b: 0.0,
+                };
+                class_count
+            ],
+        }
+    }
+
+    fn get_logits(&self, features: &RowDVector<f64>) -> Vec<f64> {
+        self.estimation_models
+            .iter()
+            .map(|estimation_model| estimation_model.parameters.dot(features) + estimation_model.b)
+            .collect()
+    }
+
+    /// One row per row of `features_set`, one column per class.
+    pub fn predict_proba(&self, features_set: &DMatrix<f64>) -> DMatrix<f64> {
+        let mut probabilities = DMatrix::zeros(features_set.nrows(), self.estimation_models.len());
+        for (row, features) in features_set.row_iter().enumerate() {
+            let row_probabilities = get_softmax(&self.get_logits(&RowDVector::from(features)));
+            for (class, probability) in row_probabilities.into_iter().enumerate() {
+                probabilities[(row, class)] = probability;
+            }
+        }
+        probabilities
+    }
+
+    /// The class with the largest probability.
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> Vec<usize> {
+        self.predict_proba(features_set)
+            .row_iter()
+            .map(|probabilities| probabilities.transpose().argmax().0)
+            .collect()
+    }
+
+    /// Mean cross-entropy `-ln p_y`, computed as `logsumexp(z) - z_y`.
+    pub fn get_cost(&self, features_set: &DMatrix<f64>, label_set: &MatrixXx1<usize>) -> f64 {
+        let total_loss = features_set
+            .row_iter()
+            .zip(label_set.iter())
+            .map(|(features, label)| {
+                let logits = self.get_logits(&RowDVector::from(features));
+                let max_logit = logits.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                let log_total = logits
+                    .iter()
+                    .map(|logit| (logit - max_logit).exp())
+                    .sum::<f64>()
+                    .ln();
+                max_logit + log_total - logits[*label]
+            })
+            .sum::<f64>();
+        total_loss / features_set.nrows() as f64
+    }
+
+    /// Gradient of `get_cost` for every class model. The logit of class `k`
+    /// changes the loss of a row by `p_k - [y = k]`.
+    pub fn get_gradient(
+        &self,
+        features_set: &DMatrix<f64>,
+        label_set: &MatrixXx1<usize>,
+    ) -> Vec<Gradient> {
+        let row_count = features_set.nrows() as f64;
+        let mut gradients = self
+            .estimation_models
+            .iter()
+            .map(|estimation_model| Gradient {
+                parameters: RowDVector::zeros(estimation_model.parameters.len()),
+                b: 0.0,
+            })
+            .collect::<Vec<_>>();
+        for (features, label) in features_set.row_iter().zip(label_set.iter()) {
+            let features = RowDVector::from(features);
+            let probabilities = get_softmax(&self.get_logits(&features));
+            for (class, (gradient, probability)) in
+                gradients.iter_mut().zip(probabilities).enumerate()
+            {
+                let error = if class == *label {
+                    probability - 1.0
+                } else {
+                    probability
+                } / row_count;
+                gradient.parameters += error * &features;
+                gradient.b += error;
+            }
+        }
+        gradients
+    }
+
+    /// Full-batch gradient descent from the zero model. Labels are
+    /// `0..class_count`, where `class_count` is one more than the largest
+    /// label. Returns the cost before every iteration.
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        label_set: &MatrixXx1<usize>,
+        learning_rate: f64,
+        iteration_count: usize,
+    ) -> Result<(SoftmaxModel, Vec<f64>), Box<dyn std::error::Error>> {
+        if features_set.nrows() == 0 || features_set.nrows() != label_set.nrows() {
+            return Err(format!(
+                "{} feature rows but {} labels",
+                features_set.nrows(),
+                label_set.nrows()
+            )
+            .into());
+        }
+        let class_count = label_set.iter().max().ok_or("no training rows")? + 1;
+        let mut model = SoftmaxModel::new(class_count, features_set.ncols());
+        let mut costs = Vec::new();
+        for _ in 0..iteration_count {
+            costs.push(model.get_cost(features_set, label_set));
+            let gradients = model.get_gradient(features_set, label_set);
+            for (estimation_model, gradient) in model.estimation_models.iter_mut().zip(gradients) {
+                estimation_model.parameters -= learning_rate * gradient.parameters;
+                estimation_model.b -= learning_rate * gradient.b;
+            }
+        }
+        Ok((model, costs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn softmax_is_stable_for_large_logits() {
+        let probabilities = get_softmax(&[1000.0, 1000.0, -1000.0]);
+        approx::assert_relative_eq!(probabilities[0], 0.5);
+        approx::assert_relative_eq!(probabilities[1], 0.5);
+        assert_eq!(probabilities[2], 0.0);
+    }
+
+    #[test]
+    fn separates_three_clusters() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let centers = [(0.0, 3.0), (3.0, -2.0), (-3.0, -2.0)];
+        let rows = (0..90)
+            .map(|row| {
+                let (x, y) = centers[row % 3];
+                (
+                    x + rng.gen_range(-1.0..1.0),
+                    y + rng.gen_range(-1.0..1.0),
+                    row % 3,
+                )
+            })
+            .collect::<Vec<_>>();
+        let features_set = DMatrix::from_fn(rows.len(), 2, |row, column| match column {
+            0 => rows[row].0,
+            _ => rows[row].1,
+        });
+        let label_set = MatrixXx1::from_iterator(rows.len(), rows.iter().map(|row| row.2));
+
+        let (model, costs) = SoftmaxModel::fit(&features_set, &label_set, 0.5, 200).unwrap();
+
+        assert_eq!(model.predict(&features_set), label_set.as_slice());
+        // The zero model gives every class 1/3.
+        approx::assert_relative_eq!(costs[0], 3.0_f64.ln(), max_relative = 1.0e-12);
+        assert!(costs.windows(2).all(|pair| pair[1] <= pair[0]));
+        for probabilities in model.predict_proba(&features_set).row_iter() {
+            approx::assert_relative_eq!(probabilities.sum(), 1.0, max_relative = 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn fit_rejects_mismatched_labels() {
+        let features_set = DMatrix::zeros(3, 2);
+        assert!(
+            SoftmaxModel::fit(&features_set, &MatrixXx1::from_vec(vec![0, 1]), 0.1, 1).is_err()
+        );
+        assert!(SoftmaxModel::fit(&DMatrix::zeros(0, 2), &MatrixXx1::zeros(0), 0.1, 1).is_err());
+    }
+}
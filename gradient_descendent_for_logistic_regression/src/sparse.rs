@@ -0,0 +1,96 @@
+//! CSR sparse feature sets, for one-hot encoded or other mostly-zero columns.
+//! `CsrMatrix` implements `training::FeaturesSet`, so `get_estimates`,
+//! `get_cost`, `get_gradient` and `gradient_descent` accept it in place of a
+//! `DMatrix`. Only the stored entries of each row are visited. Dense rows are
+//! summed in column order too and adding a zero product leaves a sum
+//! unchanged, so the dense and sparse paths give identical results.
+
+use crate::model::EstimationModel;
+use crate::training::{sigmoid, FeaturesSet};
+use nalgebra::{DMatrix, RowDVector};
+use nalgebra_sparse::CsrMatrix;
+
+pub fn to_sparse_features_set(features_set: &DMatrix<f64>) -> CsrMatrix<f64> {
+    CsrMatrix::from(features_set)
+}
+
+impl FeaturesSet for CsrMatrix<f64> {
+    fn get_row_count(&self) -> usize {
+        self.nrows()
+    }
+
+    fn estimate_row(&self, row: usize, estimation_model: &EstimationModel) -> f64 {
+        let features = self.row(row);
+        sigmoid(
+            features
+                .col_indices()
+                .iter()
+                .zip(features.values())
+                .map(|(column, feature)| estimation_model.parameters[*column] * feature)
+                .sum::<f64>()
+                + estimation_model.b,
+        )
+    }
+
+    fn add_scaled_row(&self, row: usize, scale: f64, parameter_gradients: &mut RowDVector<f64>) {
+        let features = self.row(row);
+        for (column, feature) in features.col_indices().iter().zip(features.values()) {
+            parameter_gradients[*column] += scale * feature;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::training::{get_cost, get_estimates, get_gradient, gradient_descent};
+    use nalgebra::MatrixXx1;
+
+    #[test]
+    fn sparse_training_matches_dense_training() {
+        // One-hot columns of three categories next to a numeric column.
+        let features_set = DMatrix::from_row_slice(
+            6,
+            4,
+            &[
+                [0.5, 1.0, 0.0, 0.0],
+                [-1.5, 0.0, 1.0, 0.0],
+                [2.0, 0.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0, 1.0],
+                [-0.5, 0.0, 1.0, 0.0],
+            ]
+            .concat(),
+        );
+        let target_set = MatrixXx1::from_vec(vec![true, false, true, true, false, false]);
+        let sparse_features_set = to_sparse_features_set(&features_set);
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_vec(vec![0.3, -0.2, 1.1, 0.7]),
+            b: 0.4,
+        };
+        assert_eq!(
+            get_estimates(&sparse_features_set, &estimation_model),
+            get_estimates(&features_set, &estimation_model)
+        );
+        assert_eq!(
+            get_cost(&sparse_features_set, &target_set, &estimation_model),
+            get_cost(&features_set, &target_set, &estimation_model)
+        );
+        let sparse_gradient = get_gradient(&sparse_features_set, &target_set, &estimation_model);
+        let dense_gradient = get_gradient(&features_set, &target_set, &estimation_model);
+        assert_eq!(sparse_gradient.parameters, dense_gradient.parameters);
+        assert_eq!(sparse_gradient.b, dense_gradient.b);
+
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(4),
+            b: 0.0,
+        };
+        let (sparse_result, sparse_costs) =
+            gradient_descent(&sparse_features_set, &target_set, &initial, 0.5, 200);
+        let (dense_result, dense_costs) =
+            gradient_descent(&features_set, &target_set, &initial, 0.5, 200);
+        assert_eq!(sparse_result.parameters, dense_result.parameters);
+        assert_eq!(sparse_result.b, dense_result.b);
+        assert_eq!(sparse_costs, dense_costs);
+    }
+}
@@ -0,0 +1,504 @@
+use crate::model::{EstimationModel, Gradient};
+use crate::training::{estimate, gradient_descent};
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+/// Loss of a row with target `y` in {-1, 1} and decision value `f`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HingeLoss {
+    /// `max(0, 1 - y f)`
+    Hinge,
+    /// `max(0, 1 - y f)²`, differentiable and more sensitive to outliers.
+    SquaredHinge,
+}
+
+impl HingeLoss {
+    pub fn get_loss(self, decision_value: f64, target: f64) -> f64 {
+        let margin = (1.0 - target * decision_value).max(0.0);
+        match self {
+            HingeLoss::Hinge => margin,
+            HingeLoss::SquaredHinge => margin.powi(2),
+        }
+    }
+
+    /// A subgradient of `get_loss` with respect to the decision value.
+    pub fn get_subgradient(self, decision_value: f64, target: f64) -> f64 {
+        let margin = 1.0 - target * decision_value;
+        if margin <= 0.0 {
+            return 0.0;
+        }
+        match self {
+            HingeLoss::Hinge => -target,
+            HingeLoss::SquaredHinge => -2.0 * margin * target,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SvmParameters {
+    pub loss: HingeLoss,
+    /// `λ` of the `λ / 2 (|w|² + b²)` regularization term. The Pegasos step
+    /// size of the `t`-th update is `1 / (λ t)`.
+    pub regularization_strength: f64,
+    /// Passes over the training set.
+    pub epoch_count: usize,
+    pub batch_size: usize,
+    /// Seeds the order of the rows in every epoch.
+    pub seed: u64,
+}
+
+fn get_sign(target: &bool) -> f64 {
+    match target {
+        true => 1.0,
+        false => -1.0,
+    }
+}
+
+pub fn get_decision_value(features: &RowDVector<f64>, estimation_model: &EstimationModel) -> f64 {
+    estimation_model.parameters.dot(features) + estimation_model.b
+}
+
+/// `λ / 2 (|w|² + b²)` plus the mean loss, the objective Pegasos minimizes.
+pub fn get_svm_cost(
+    features_set: &DMatrix<f64>,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+    parameters: &SvmParameters,
+) -> f64 {
+    let mean_loss = features_set
+        .row_iter()
+        .zip(target_set.iter())
+        .map(|(features, target)| {
+            parameters.loss.get_loss(
+                get_decision_value(&RowDVector::from(features), estimation_model),
+                get_sign(target),
+            )
+        })
+        .sum::<f64>()
+        / features_set.nrows() as f64;
+    parameters.regularization_strength / 2.0
+        * (estimation_model.parameters.norm_squared() + estimation_model.b.powi(2))
+        + mean_loss
+}
+
+/// A subgradient of `get_svm_cost` over `rows`, the step direction of one
+/// Pegasos update on a batch.
+pub fn get_svm_subgradient(
+    features_set: &DMatrix<f64>,
+    target_set: &MatrixXx1<bool>,
+    rows: &[usize],
+    estimation_model: &EstimationModel,
+    parameters: &SvmParameters,
+) -> Gradient {
+    let mut subgradient = Gradient {
+        parameters: &estimation_model.parameters * parameters.regularization_strength,
+        b: estimation_model.b * parameters.regularization_strength,
+    };
+    for row in rows {
+        let features = RowDVector::from(features_set.row(*row));
+        let row_subgradient = parameters.loss.get_subgradient(
+            get_decision_value(&features, estimation_model),
+            get_sign(&target_set[*row]),
+        ) / rows.len() as f64;
+        subgradient.parameters += row_subgradient * features;
+        subgradient.b += row_subgradient;
+    }
+    subgradient
+}
+
+/// Binary linear support vector machine. The decision value is the signed
+/// distance-like score `w · x + b`; `true` is predicted when it is at least 0.
+#[derive(Debug, Clone)]
+pub struct LinearSvm {
+    pub estimation_model: EstimationModel,
+    /// Platt scaling: a one-feature logistic model of the decision value,
+    /// set by `calibrate`.
+    pub calibration: Option<EstimationModel>,
+}
+
+impl LinearSvm {
+    /// Pegasos stochastic subgradient descent. `b` is regularized like an
+    /// extra feature that is always 1, which keeps the early steps of size
+    /// `1 / λ` from throwing it far away. Standardize the features first; on
+    /// features of very different scales the steps converge slowly. Returns
+    /// the cost after every epoch, of the averaged model once averaging has
+    /// started.
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+        parameters: &SvmParameters,
+    ) -> Result<(LinearSvm, Vec<f64>), Box<dyn std::error::Error>> {
+        if features_set.nrows() == 0 || features_set.nrows() != target_set.nrows() {
+            return Err(format!(
+                "{} feature rows but {} targets",
+                features_set.nrows(),
+                target_set.nrows()
+            )
+            .into());
+        }
+        if parameters.regularization_strength <= 0.0 {
+            return Err("regularization strength must be positive".into());
+        }
+        if parameters.batch_size == 0 {
+            return Err("batch size must be positive".into());
+        }
+        let lambda = parameters.regularization_strength;
+        // The optimum costs no more than w = 0, b = 0, whose cost is 1.
+        let radius = (2.0 / lambda).sqrt();
+        let mut rng = StdRng::seed_from_u64(parameters.seed);
+        let mut rows = (0..features_set.nrows()).collect::<Vec<_>>();
+        let mut estimation_model = EstimationModel {
+            parameters: RowDVector::zeros(features_set.ncols()),
+            b: 0.0,
+        };
+        // Pegasos returns the average of the iterates of the last half of the
+        // updates, which is far less noisy than the last iterate.
+        let total_step_count = parameters.epoch_count * rows.chunks(parameters.batch_size).len();
+        let mut average_model = estimation_model.clone();
+        let mut average_count = 0;
+        let mut costs = Vec::new();
+        let mut step = 0;
+        for _ in 0..parameters.epoch_count {
+            rows.shuffle(&mut rng);
+            for batch in rows.chunks(parameters.batch_size) {
+                step += 1;
+                let learning_rate = 1.0 / (lambda * step as f64);
+                let subgradient = get_svm_subgradient(
+                    features_set,
+                    target_set,
+                    batch,
+                    &estimation_model,
+                    parameters,
+                );
+                estimation_model.parameters -= learning_rate * subgradient.parameters;
+                estimation_model.b -= learning_rate * subgradient.b;
+
+                let norm = (estimation_model.parameters.norm_squared()
+                    + estimation_model.b.powi(2))
+                .sqrt();
+                if norm > radius {
+                    estimation_model.parameters *= radius / norm;
+                    estimation_model.b *= radius / norm;
+                }
+                if 2 * step > total_step_count {
+                    average_count += 1;
+                    let weight = 1.0 / average_count as f64;
+                    average_model.parameters = (1.0 - weight) * &average_model.parameters
+                        + weight * &estimation_model.parameters;
+                    average_model.b =
+                        (1.0 - weight) * average_model.b + weight * estimation_model.b;
+                }
+            }
+            costs.push(get_svm_cost(
+                features_set,
+                target_set,
+                if average_count > 0 {
+                    &average_model
+                } else {
+                    &estimation_model
+                },
+                parameters,
+            ));
+        }
+        if average_count == 0 {
+            average_model = estimation_model;
+        }
+        Ok((
+            LinearSvm {
+                estimation_model: average_model,
+                calibration: None,
+            },
+            costs,
+        ))
+    }
+
+    pub fn get_decision_values(&self, features_set: &DMatrix<f64>) -> Vec<f64> {
+        features_set
+            .row_iter()
+            .map(|features| get_decision_value(&RowDVector::from(features), &self.estimation_model))
+            .collect()
+    }
+
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> Vec<bool> {
+        self.get_decision_values(features_set)
+            .iter()
+            .map(|decision_value| *decision_value >= 0.0)
+            .collect()
+    }
+
+    /// Fits the Platt scaling with `training::gradient_descent` on the
+    /// decision values. Use rows the SVM was not trained on, or the
+    /// probabilities come out overconfident.
+    pub fn calibrate(
+        &mut self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if features_set.nrows() == 0 || features_set.nrows() != target_set.nrows() {
+            return Err("calibration needs as many targets as feature rows".into());
+        }
+        let decision_values = DMatrix::from_vec(
+            features_set.nrows(),
+            1,
+            self.get_decision_values(features_set),
+        );
+        // The Hessian of the logistic cost is at most a quarter of
+        // `mean(f²) + 1`, so this learning rate cannot overshoot.
+        let learning_rate =
+            4.0 / (decision_values.norm_squared() / features_set.nrows() as f64 + 1.0);
+        let (calibration, _) = gradient_descent(
+            &decision_values,
+            target_set,
+            &EstimationModel {
+                parameters: RowDVector::zeros(1),
+                b: 0.0,
+            },
+            learning_rate,
+            1000,
+        );
+        self.calibration = Some(calibration);
+        Ok(())
+    }
+
+    /// Calibrated probability of `true` for every row, like
+    /// `training::get_estimates`, so that both models share the `metrics`.
+    pub fn predict_proba(
+        &self,
+        features_set: &DMatrix<f64>,
+    ) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+        let calibration = self
+            .calibration
+            .as_ref()
+            .ok_or("call calibrate before predict_proba")?;
+        Ok(self
+            .get_decision_values(features_set)
+            .iter()
+            .map(|decision_value| {
+                estimate(&RowDVector::from_vec(vec![*decision_value]), calibration)
+            })
+            .collect())
+    }
+}
+
+/// Multiclass SVM with one binary `LinearSvm` per class, each separating its
+/// class from all the others.
+#[derive(Debug, Clone)]
+pub struct OneVsRestSvm {
+    pub classifiers: Vec<LinearSvm>,
+}
+
+fn get_class_targets(label_set: &MatrixXx1<usize>, class: usize) -> MatrixXx1<bool> {
+    label_set.map(|label| label == class)
+}
+
+impl OneVsRestSvm {
+    /// Labels are `0..class_count`, where `class_count` is one more than the
+    /// largest label.
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        label_set: &MatrixXx1<usize>,
+        parameters: &SvmParameters,
+    ) -> Result<OneVsRestSvm, Box<dyn std::error::Error>> {
+        let class_count = label_set.iter().max().ok_or("no training rows")? + 1;
+        let classifiers = (0..class_count)
+            .map(|class| {
+                LinearSvm::fit(
+                    features_set,
+                    &get_class_targets(label_set, class),
+                    parameters,
+                )
+                .map(|(classifier, _)| classifier)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(OneVsRestSvm { classifiers })
+    }
+
+    /// One row per row of `features_set`, one column per class.
+    pub fn get_decision_values(&self, features_set: &DMatrix<f64>) -> DMatrix<f64> {
+        let columns = self
+            .classifiers
+            .iter()
+            .map(|classifier| MatrixXx1::from_vec(classifier.get_decision_values(features_set)))
+            .collect::<Vec<_>>();
+        DMatrix::from_columns(&columns)
+    }
+
+    /// The class with the largest decision value.
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> Vec<usize> {
+        self.get_decision_values(features_set)
+            .row_iter()
+            .map(|decision_values| decision_values.transpose().argmax().0)
+            .collect()
+    }
+
+    pub fn calibrate(
+        &mut self,
+        features_set: &DMatrix<f64>,
+        label_set: &MatrixXx1<usize>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (class, classifier) in self.classifiers.iter_mut().enumerate() {
+            classifier.calibrate(features_set, &get_class_targets(label_set, class))?;
+        }
+        Ok(())
+    }
+
+    /// Calibrated one-vs-rest probabilities, normalized to add up to 1 in
+    /// every row.
+    pub fn predict_proba(
+        &self,
+        features_set: &DMatrix<f64>,
+    ) -> Result<DMatrix<f64>, Box<dyn std::error::Error>> {
+        let columns = self
+            .classifiers
+            .iter()
+            .map(|classifier| {
+                classifier
+                    .predict_proba(features_set)
+                    .map(MatrixXx1::from_vec)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut probabilities = DMatrix::from_columns(&columns);
+        for mut row in probabilities.row_iter_mut() {
+            let total = row.sum();
+            if total > 0.0 {
+                row /= total;
+            }
+        }
+        Ok(probabilities)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_loader::load_training_set;
+    use crate::gradient_check::{check_derivative, check_gradient, EPSILON};
+    use crate::metrics::get_confusion_matrix;
+    use rand::Rng;
+
+    const PARAMETERS: SvmParameters = SvmParameters {
+        loss: HingeLoss::Hinge,
+        regularization_strength: 1.0e-3,
+        epoch_count: 200,
+        batch_size: 4,
+        seed: 5,
+    };
+
+    #[test]
+    fn subgradients_match_central_differences() {
+        for loss in [HingeLoss::Hinge, HingeLoss::SquaredHinge] {
+            for (decision_value, target) in [(0.3, 1.0), (-2.0, 1.0), (0.4, -1.0), (2.5, 1.0)] {
+                let gradient_check = check_derivative(
+                    |decision_value| loss.get_loss(decision_value, target),
+                    |decision_value| loss.get_subgradient(decision_value, target),
+                    decision_value,
+                    EPSILON,
+                );
+                assert!(
+                    gradient_check.get_max_relative_error() < 1.0e-6,
+                    "{:?} {:?}",
+                    loss,
+                    gradient_check
+                );
+            }
+        }
+    }
+
+    /// Away from the kinks at a margin of exactly 1 the subgradient of the
+    /// Pegasos objective is its gradient.
+    #[test]
+    fn batch_subgradient_matches_central_differences() {
+        let features_set = DMatrix::from_fn(20, 3, |row, column| {
+            ((row * 7 + column * 5) % 11) as f64 / 5.0 - 1.0
+        });
+        let target_set = MatrixXx1::from_fn(20, |row, _| (row * 3) % 5 < 2);
+        let rows = (0..20).collect::<Vec<_>>();
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_vec(vec![0.83, -1.27, 0.41]),
+            b: -0.57,
+        };
+        for loss in [HingeLoss::Hinge, HingeLoss::SquaredHinge] {
+            let parameters = SvmParameters {
+                regularization_strength: 0.1,
+                loss,
+                ..PARAMETERS
+            };
+            let gradient_check = check_gradient(
+                |model| get_svm_cost(&features_set, &target_set, model, &parameters),
+                |model| get_svm_subgradient(&features_set, &target_set, &rows, model, &parameters),
+                &estimation_model,
+                EPSILON,
+            );
+            assert!(
+                gradient_check.get_max_relative_error() < 1.0e-6,
+                "{:?} {:?}",
+                loss,
+                gradient_check
+            );
+        }
+    }
+
+    #[test]
+    fn separates_synthetic_training_set() {
+        let (features_set, target_set) = load_training_set().unwrap();
+        // Standardized like the example does before training the SVM.
+        let features_set = features_set.map(|feature| (feature - 9.5) / 5.77);
+        for loss in [HingeLoss::Hinge, HingeLoss::SquaredHinge] {
+            let (mut svm, costs) = LinearSvm::fit(
+                &features_set,
+                &target_set,
+                &SvmParameters { loss, ..PARAMETERS },
+            )
+            .unwrap();
+            let predictions = svm.predict(&features_set);
+            assert!(
+                predictions
+                    .iter()
+                    .zip(target_set.iter())
+                    .all(|(a, b)| a == b),
+                "{:?} {:?}",
+                loss,
+                svm.estimation_model
+            );
+            // w = 0, b = 0 costs 1.
+            assert!(costs[costs.len() - 1] < 0.5, "{:?}", costs);
+
+            svm.calibrate(&features_set, &target_set).unwrap();
+            let probabilities = svm.predict_proba(&features_set).unwrap();
+            assert_eq!(
+                get_confusion_matrix(&probabilities, &target_set, 0.5).get_accuracy(),
+                1.0
+            );
+            assert!(probabilities.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+    }
+
+    #[test]
+    fn one_vs_rest_separates_three_clusters() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let centers = [(0.0, 3.0), (3.0, -2.0), (-3.0, -2.0)];
+        let rows = (0..150)
+            .map(|row| {
+                let (x, y) = centers[row % 3];
+                RowDVector::from_vec(vec![
+                    x + rng.gen_range(-1.0..1.0),
+                    y + rng.gen_range(-1.0..1.0),
+                ])
+            })
+            .collect::<Vec<_>>();
+        let features_set = DMatrix::from_rows(&rows);
+        let label_set = MatrixXx1::from_iterator(150, (0..150).map(|row| row % 3));
+        let mut svm = OneVsRestSvm::fit(&features_set, &label_set, &PARAMETERS).unwrap();
+        assert_eq!(svm.predict(&features_set), label_set.as_slice());
+
+        assert!(svm.predict_proba(&features_set).is_err());
+        svm.calibrate(&features_set, &label_set).unwrap();
+        let probabilities = svm.predict_proba(&features_set).unwrap();
+        for (row, label) in probabilities.row_iter().zip(label_set.iter()) {
+            approx::assert_relative_eq!(row.sum(), 1.0, epsilon = 1.0e-12);
+            assert_eq!(row.transpose().argmax().0, *label);
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use crate::autodiff::{Tape, Tensor};
 use crate::model::{EstimationModel, Gradient};
 use nalgebra::{DMatrix, MatrixXx1, RowDVector};
 
-fn sigmoid(x: f64) -> f64 {
+pub(crate) fn sigmoid(x: f64) -> f64 {
     1.0 / (1.0 + (x * -1.0).exp())
 }
 
@@ -9,12 +10,17 @@ pub fn estimate(features: &RowDVector<f64>, estimation_model: &EstimationModel)
     sigmoid(estimation_model.parameters.dot(&features) + estimation_model.b)
 }
 
-pub fn get_row_loss(
-    features: &RowDVector<f64>,
-    target: &bool,
+pub fn get_estimates(
+    features_set: &impl FeaturesSet,
     estimation_model: &EstimationModel,
-) -> f64 {
-    let estimated = estimate(features, estimation_model);
+) -> Vec<f64> {
+    (0..features_set.get_row_count())
+        .map(|row| features_set.estimate_row(row, estimation_model))
+        .collect()
+}
+
+/// Cross-entropy loss of one estimated probability.
+fn get_loss(estimated: f64, target: &bool) -> f64 {
     let target_f64 = match target {
         true => 1.0,
         false => 0.0,
@@ -22,6 +28,14 @@ pub fn get_row_loss(
     -(target_f64 * estimated.ln()) + -((1.0 - target_f64) * (1.0 - estimated).ln())
 }
 
+pub fn get_row_loss(
+    features: &RowDVector<f64>,
+    target: &bool,
+    estimation_model: &EstimationModel,
+) -> f64 {
+    get_loss(estimate(features, estimation_model), target)
+}
+
 pub fn get_row_error(
     features: &RowDVector<f64>,
     target: &bool,
@@ -34,44 +48,104 @@ pub fn get_row_error(
     estimate(features, estimation_model) - target_f64
 }
 
-fn get_cost_of_rows_1(
-    features_set: &DMatrix<f64>,
+/// Rows of features that the estimates and the cost and gradient functions
+/// visit: a dense `DMatrix`, or with the `sparse` feature a CSR matrix that
+/// only visits the stored entries of each row.
+pub trait FeaturesSet: Sync {
+    fn get_row_count(&self) -> usize;
+
+    /// `estimate` of one row, with the products summed in column order.
+    fn estimate_row(&self, row: usize, estimation_model: &EstimationModel) -> f64;
+
+    /// Adds `scale` times one row to `parameter_gradients`.
+    fn add_scaled_row(&self, row: usize, scale: f64, parameter_gradients: &mut RowDVector<f64>);
+}
+
+impl FeaturesSet for DMatrix<f64> {
+    fn get_row_count(&self) -> usize {
+        self.nrows()
+    }
+
+    /// Not `estimate`: `dot` sums in an unrolled order, and summing in column
+    /// order keeps the result identical to that of a sparse row.
+    fn estimate_row(&self, row: usize, estimation_model: &EstimationModel) -> f64 {
+        sigmoid(
+            self.row(row)
+                .iter()
+                .zip(estimation_model.parameters.iter())
+                .map(|(feature, parameter)| parameter * feature)
+                .sum::<f64>()
+                + estimation_model.b,
+        )
+    }
+
+    fn add_scaled_row(&self, row: usize, scale: f64, parameter_gradients: &mut RowDVector<f64>) {
+        *parameter_gradients += scale * self.row(row);
+    }
+}
+
+/// Rows are summed in chunks of this fixed size and the chunk sums are then
+/// added in chunk order, in the serial as in the parallel path. The parallel
+/// cost and gradient are therefore bit-identical to the serial ones.
+pub const CHUNK_ROW_COUNT: usize = 1024;
+
+pub(crate) fn get_chunk_ranges(row_count: usize) -> Vec<(usize, usize)> {
+    (0..row_count)
+        .step_by(CHUNK_ROW_COUNT)
+        .map(|start| (start, CHUNK_ROW_COUNT.min(row_count - start)))
+        .collect()
+}
+
+/// Sum of the cross-entropy losses of the rows of one chunk.
+pub(crate) fn get_chunk_cost(
+    features_set: &impl FeaturesSet,
     target_set: &MatrixXx1<bool>,
     estimation_model: &EstimationModel,
+    (start, length): (usize, usize),
 ) -> f64 {
-    let row_count = features_set.len() / estimation_model.parameters.len();
-    let total_count = features_set
-        .row_iter()
-        .enumerate()
-        .map(|(index, features)| {
-            get_row_loss(
-                &RowDVector::from(features),
-                &target_set[index],
-                estimation_model,
+    (start..start + length)
+        .map(|row| {
+            get_loss(
+                features_set.estimate_row(row, estimation_model),
+                &target_set[row],
             )
         })
-        .sum::<f64>();
-    total_count / row_count as f64
+        .sum::<f64>()
 }
 
-pub fn get_gradient(
-    features_set: &DMatrix<f64>,
+/// Sum of the gradients of the rows of one chunk.
+pub(crate) fn get_chunk_gradient(
+    features_set: &impl FeaturesSet,
     target_set: &MatrixXx1<bool>,
     estimation_model: &EstimationModel,
+    (start, length): (usize, usize),
 ) -> Gradient {
-    let row_count = features_set.len() / estimation_model.parameters.len();
     let mut parameter_gradients = RowDVector::zeros(estimation_model.parameters.len());
     let mut b_gratient = 0.0;
-
-    for (index, features) in features_set.row_iter().enumerate() {
-        let cost_of_row = get_row_error(
-            &RowDVector::from(features),
-            &target_set[index],
-            estimation_model,
-        );
-        parameter_gradients += cost_of_row * features;
+    for row in start..start + length {
+        let target_f64 = if target_set[row] { 1.0 } else { 0.0 };
+        let cost_of_row = features_set.estimate_row(row, estimation_model) - target_f64;
+        features_set.add_scaled_row(row, cost_of_row, &mut parameter_gradients);
         b_gratient += cost_of_row;
     }
+    Gradient {
+        parameters: parameter_gradients,
+        b: b_gratient,
+    }
+}
+
+/// Adds the chunk gradients in chunk order and averages them over the rows.
+pub(crate) fn get_mean_gradient(
+    partial_gradients: Vec<Gradient>,
+    parameter_count: usize,
+    row_count: usize,
+) -> Gradient {
+    let mut parameter_gradients = RowDVector::zeros(parameter_count);
+    let mut b_gratient = 0.0;
+    for partial_gradient in partial_gradients {
+        parameter_gradients += partial_gradient.parameters;
+        b_gratient += partial_gradient.b;
+    }
     parameter_gradients /= row_count as f64;
     b_gratient /= row_count as f64;
 
@@ -81,9 +155,119 @@ pub fn get_gradient(
     }
 }
 
-pub fn gradient_descent(
+pub fn get_cost_of_rows_1(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> f64 {
+    let row_count = features_set.get_row_count();
+    let partial_costs = get_chunk_ranges(row_count)
+        .into_iter()
+        .map(|range| get_chunk_cost(features_set, target_set, estimation_model, range))
+        .collect::<Vec<f64>>();
+    let total_count = partial_costs.iter().sum::<f64>();
+    total_count / row_count as f64
+}
+
+pub fn get_cost(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> f64 {
+    #[cfg(feature = "parallel")]
+    return crate::parallel::get_cost_of_rows_1(features_set, target_set, estimation_model);
+    #[cfg(not(feature = "parallel"))]
+    get_cost_of_rows_1(features_set, target_set, estimation_model)
+}
+
+pub fn get_gradient(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> Gradient {
+    #[cfg(feature = "parallel")]
+    return crate::parallel::get_gradient(features_set, target_set, estimation_model);
+    #[cfg(not(feature = "parallel"))]
+    get_serial_gradient(features_set, target_set, estimation_model)
+}
+
+pub fn get_serial_gradient(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> Gradient {
+    let partial_gradients = get_chunk_ranges(features_set.get_row_count())
+        .into_iter()
+        .map(|range| get_chunk_gradient(features_set, target_set, estimation_model, range))
+        .collect::<Vec<Gradient>>();
+    get_mean_gradient(
+        partial_gradients,
+        estimation_model.parameters.len(),
+        features_set.get_row_count(),
+    )
+}
+
+/// `get_cost` and `get_gradient` re-expressed on an autodiff tape.
+pub fn get_autodiff_cost_and_gradient(
     features_set: &DMatrix<f64>,
     target_set: &MatrixXx1<bool>,
+    estimation_model: &EstimationModel,
+) -> (f64, Gradient) {
+    let tape = Tape::new();
+    let parameters = tape.var(Tensor::from_column_slice(
+        estimation_model.parameters.len(),
+        1,
+        estimation_model.parameters.as_slice(),
+    ));
+    let b = tape.scalar(estimation_model.b);
+    let targets = tape.var(Tensor::from_iterator(
+        target_set.len(),
+        1,
+        target_set
+            .iter()
+            .map(|target| if *target { 1.0 } else { 0.0 }),
+    ));
+    let one = tape.scalar(1.0);
+    let estimates = (tape.var(features_set.clone()).matmul(parameters) + b).sigmoid();
+    let cost = -(targets.component_mul(estimates.ln())
+        + (one - targets).component_mul((one - estimates).ln()))
+    .mean();
+
+    let gradients = tape.get_gradients(cost);
+    (
+        cost.get_value()[(0, 0)],
+        Gradient {
+            parameters: RowDVector::from_row_slice(gradients.get(parameters).as_slice()),
+            b: gradients.get(b)[(0, 0)],
+        },
+    )
+}
+
+pub fn gradient_descent(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<bool>,
+    initial_estimation_model: &EstimationModel,
+    learning_rate: f64,
+    iteration_count: usize,
+) -> (EstimationModel, Vec<f64>) {
+    gradient_descent_with(
+        |estimation_model| {
+            (
+                get_cost(features_set, target_set, estimation_model),
+                get_gradient(features_set, target_set, estimation_model),
+            )
+        },
+        initial_estimation_model,
+        learning_rate,
+        iteration_count,
+    )
+}
+
+/// `gradient_descent` with any cost and gradient, such as
+/// `get_autodiff_cost_and_gradient` to train on the autodiff tape. Returns the
+/// cost before every iteration.
+pub fn gradient_descent_with(
+    get_cost_and_gradient: impl Fn(&EstimationModel) -> (f64, Gradient),
     initial_estimation_model: &EstimationModel,
     learning_rate: f64,
     iteration_count: usize,
@@ -91,10 +275,8 @@ pub fn gradient_descent(
     let mut costs: Vec<f64> = Vec::new();
     let mut estimation_model = initial_estimation_model.clone();
 
-    for _ in 0..iteration_count.clone() {
-        let gradient = get_gradient(features_set, target_set, &estimation_model);
-        let cost = get_cost_of_rows_1(features_set, target_set, &estimation_model);
-
+    for _ in 0..iteration_count {
+        let (cost, gradient) = get_cost_and_gradient(&estimation_model);
         costs.push(cost);
 
         estimation_model = EstimationModel {
@@ -105,3 +287,122 @@ pub fn gradient_descent(
 
     (estimation_model, costs)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_loader::load_training_set;
+    use crate::metrics::get_confusion_matrix;
+    use proptest::prelude::*;
+
+    #[test]
+    fn autodiff_matches_analytic_cost_and_gradient() {
+        let features_set =
+            DMatrix::from_row_slice(4, 2, &[0.5, -1.0, 1.5, 2.0, -0.3, 0.8, 2.2, -1.7]);
+        let target_set = MatrixXx1::from_vec(vec![true, false, false, true]);
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_vec(vec![0.7, -0.2]),
+            b: 0.4,
+        };
+        let (cost, gradient) =
+            get_autodiff_cost_and_gradient(&features_set, &target_set, &estimation_model);
+        let analytic_gradient = get_serial_gradient(&features_set, &target_set, &estimation_model);
+        approx::assert_relative_eq!(
+            cost,
+            get_cost_of_rows_1(&features_set, &target_set, &estimation_model),
+            max_relative = 1.0e-12
+        );
+        approx::assert_relative_eq!(
+            gradient.parameters,
+            analytic_gradient.parameters,
+            max_relative = 1.0e-12
+        );
+        approx::assert_relative_eq!(gradient.b, analytic_gradient.b, max_relative = 1.0e-12);
+    }
+
+    #[test]
+    fn training_on_the_autodiff_tape_matches_gradient_descent() {
+        let (features_set, target_set) = load_training_set().unwrap();
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(features_set.ncols()),
+            b: 0.0,
+        };
+        let (estimation_model, costs) =
+            gradient_descent(&features_set, &target_set, &initial, 0.01, 100);
+        let (autodiff_estimation_model, autodiff_costs) = gradient_descent_with(
+            |estimation_model| {
+                get_autodiff_cost_and_gradient(&features_set, &target_set, estimation_model)
+            },
+            &initial,
+            0.01,
+            100,
+        );
+
+        approx::assert_relative_eq!(
+            autodiff_estimation_model.parameters,
+            estimation_model.parameters,
+            max_relative = 1.0e-10
+        );
+        approx::assert_relative_eq!(
+            autodiff_estimation_model.b,
+            estimation_model.b,
+            max_relative = 1.0e-10
+        );
+        for (autodiff_cost, cost) in autodiff_costs.iter().zip(costs.iter()) {
+            approx::assert_relative_eq!(autodiff_cost, cost, max_relative = 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn separates_synthetic_training_set() {
+        let (features_set, target_set) = load_training_set().unwrap();
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(1),
+            b: 0.0,
+        };
+        let (result, costs) = gradient_descent(&features_set, &target_set, &initial, 1.0e-1, 1000);
+        let confusion_matrix =
+            get_confusion_matrix(&get_estimates(&features_set, &result), &target_set, 0.5);
+        assert_eq!(
+            confusion_matrix.get_accuracy(),
+            1.0,
+            "{:?}",
+            confusion_matrix
+        );
+        assert!(costs[costs.len() - 1] < costs[0]);
+    }
+
+    fn training_set_strategy() -> impl Strategy<Value = (DMatrix<f64>, MatrixXx1<bool>)> {
+        (2..30_usize, 1..4_usize).prop_flat_map(|(row_count, column_count)| {
+            (
+                prop::collection::vec(-3.0..3.0_f64, row_count * column_count),
+                prop::collection::vec(any::<bool>(), row_count),
+            )
+                .prop_map(move |(features, targets)| {
+                    (
+                        DMatrix::from_row_slice(row_count, column_count, &features),
+                        MatrixXx1::from_vec(targets),
+                    )
+                })
+        })
+    }
+
+    proptest! {
+        /// The Hessian of the logistic cost is at most a quarter of that of
+        /// the squared error, whose trace is bounded by
+        /// `column_count * 9 + 1` for features in [-3, 3].
+        #[test]
+        fn cost_does_not_increase_with_a_small_learning_rate(
+            (features_set, target_set) in training_set_strategy()
+        ) {
+            let initial = EstimationModel {
+                parameters: RowDVector::zeros(features_set.ncols()),
+                b: 0.0,
+            };
+            let (_, costs) = gradient_descent(&features_set, &target_set, &initial, 0.1, 50);
+            for pair in costs.windows(2) {
+                prop_assert!(pair[1] <= pair[0] * (1.0 + 1.0e-12), "{:?}", costs);
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use nalgebra::DMatrix;
+use std::cell::RefCell;
+use std::ops::{Add, Neg, Sub};
+
+pub type Tensor = DMatrix<f64>;
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    Leaf,
+    MatMul(usize, usize),
+    /// Either side may be a 1x1 tensor broadcast to the shape of the other.
+    Add(usize, usize),
+    Sub(usize, usize),
+    ComponentMul(usize, usize),
+    Scale(usize, f64),
+    Neg(usize),
+    Sigmoid(usize),
+    Exp(usize),
+    Ln(usize),
+    Sum(usize),
+    Mean(usize),
+}
+
+#[derive(Debug)]
+struct Node {
+    value: Tensor,
+    operation: Operation,
+}
+
+/// Records every operation on its `Var`s so that `get_gradients` can replay
+/// them backwards. A tape is meant to be built for one evaluation and then
+/// dropped.
+#[derive(Debug, Default)]
+pub struct Tape {
+    nodes: RefCell<Vec<Node>>,
+}
+
+/// A tensor on a tape. Copying a `Var` does not copy its value.
+#[derive(Debug, Clone, Copy)]
+pub struct Var<'t> {
+    tape: &'t Tape,
+    index: usize,
+}
+
+/// Gradients of one scalar output with respect to every `Var` on the tape.
+#[derive(Debug)]
+pub struct Gradients {
+    gradients: Vec<Tensor>,
+}
+
+impl Gradients {
+    pub fn get(&self, var: Var) -> &Tensor {
+        &self.gradients[var.index]
+    }
+}
+
+fn get_broadcast_value(value: &Tensor, shape: (usize, usize)) -> Tensor {
+    if value.shape() == shape {
+        value.clone()
+    } else {
+        Tensor::from_element(shape.0, shape.1, value[(0, 0)])
+    }
+}
+
+/// Sums a gradient back down to the shape of an operand that was broadcast.
+fn get_reduced_gradient(gradient: &Tensor, shape: (usize, usize)) -> Tensor {
+    if gradient.shape() == shape {
+        gradient.clone()
+    } else {
+        Tensor::from_element(shape.0, shape.1, gradient.sum())
+    }
+}
+
+fn get_broadcast_shape(a: &Tensor, b: &Tensor) -> (usize, usize) {
+    match (a.shape(), b.shape()) {
+        (a_shape, b_shape) if a_shape == b_shape => a_shape,
+        ((1, 1), b_shape) => b_shape,
+        (a_shape, (1, 1)) => a_shape,
+        (a_shape, b_shape) => panic!("cannot broadcast {:?} and {:?}", a_shape, b_shape),
+    }
+}
+
+impl Tape {
+    pub fn new() -> Tape {
+        Tape::default()
+    }
+
+    /// An input. Its gradient is available whether or not it is needed.
+    pub fn var(&self, value: Tensor) -> Var<'_> {
+        self.push(value, Operation::Leaf)
+    }
+
+    pub fn scalar(&self, value: f64) -> Var<'_> {
+        self.var(Tensor::from_element(1, 1, value))
+    }
+
+    fn push(&self, value: Tensor, operation: Operation) -> Var<'_> {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(Node { value, operation });
+        Var {
+            tape: self,
+            index: nodes.len() - 1,
+        }
+    }
+
+    fn get_value(&self, index: usize) -> Tensor {
+        self.nodes.borrow()[index].value.clone()
+    }
+
+    /// Reverse-mode sweep from a 1x1 `output`.
+    pub fn get_gradients(&self, output: Var) -> Gradients {
+        let nodes = self.nodes.borrow();
+        assert_eq!(
+            nodes[output.index].value.shape(),
+            (1, 1),
+            "gradients can only be taken of a scalar"
+        );
+        let mut gradients = nodes
+            .iter()
+            .map(|node| Tensor::zeros(node.value.nrows(), node.value.ncols()))
+            .collect::<Vec<_>>();
+        gradients[output.index][(0, 0)] = 1.0;
+
+        for index in (0..=output.index).rev() {
+            let gradient = gradients[index].clone();
+            let value = &nodes[index].value;
+            match nodes[index].operation {
+                Operation::Leaf => {}
+                Operation::MatMul(a, b) => {
+                    gradients[a] += &gradient * nodes[b].value.transpose();
+                    gradients[b] += nodes[a].value.transpose() * &gradient;
+                }
+                Operation::Add(a, b) => {
+                    gradients[a] += get_reduced_gradient(&gradient, nodes[a].value.shape());
+                    gradients[b] += get_reduced_gradient(&gradient, nodes[b].value.shape());
+                }
+                Operation::Sub(a, b) => {
+                    gradients[a] += get_reduced_gradient(&gradient, nodes[a].value.shape());
+                    gradients[b] -= get_reduced_gradient(&gradient, nodes[b].value.shape());
+                }
+                Operation::ComponentMul(a, b) => {
+                    gradients[a] += gradient.component_mul(&nodes[b].value);
+                    gradients[b] += gradient.component_mul(&nodes[a].value);
+                }
+                Operation::Scale(a, factor) => gradients[a] += factor * &gradient,
+                Operation::Neg(a) => gradients[a] -= &gradient,
+                Operation::Sigmoid(a) => {
+                    gradients[a] += gradient.component_mul(&value.map(|s| s * (1.0 - s)));
+                }
+                Operation::Exp(a) => gradients[a] += gradient.component_mul(value),
+                Operation::Ln(a) => gradients[a] += gradient.component_div(&nodes[a].value),
+                Operation::Sum(a) => gradients[a].add_scalar_mut(gradient[(0, 0)]),
+                Operation::Mean(a) => {
+                    let count = nodes[a].value.len() as f64;
+                    gradients[a].add_scalar_mut(gradient[(0, 0)] / count);
+                }
+            }
+        }
+        Gradients { gradients }
+    }
+}
+
+impl<'t> Var<'t> {
+    pub fn get_value(&self) -> Tensor {
+        self.tape.get_value(self.index)
+    }
+
+    fn unary(self, operation: Operation, f: impl Fn(&Tensor) -> Tensor) -> Var<'t> {
+        let value = f(&self.tape.nodes.borrow()[self.index].value);
+        self.tape.push(value, operation)
+    }
+
+    fn binary(
+        self,
+        other: Var<'t>,
+        operation: Operation,
+        f: impl Fn(&Tensor, &Tensor) -> Tensor,
+    ) -> Var<'t> {
+        let value = {
+            let nodes = self.tape.nodes.borrow();
+            f(&nodes[self.index].value, &nodes[other.index].value)
+        };
+        self.tape.push(value, operation)
+    }
+
+    pub fn matmul(self, other: Var<'t>) -> Var<'t> {
+        self.binary(other, Operation::MatMul(self.index, other.index), |a, b| {
+            a * b
+        })
+    }
+
+    /// Element-wise product of two tensors of the same shape.
+    pub fn component_mul(self, other: Var<'t>) -> Var<'t> {
+        self.binary(
+            other,
+            Operation::ComponentMul(self.index, other.index),
+            |a, b| a.component_mul(b),
+        )
+    }
+
+    pub fn scale(self, factor: f64) -> Var<'t> {
+        self.unary(Operation::Scale(self.index, factor), |a| a * factor)
+    }
+
+    pub fn sigmoid(self) -> Var<'t> {
+        self.unary(Operation::Sigmoid(self.index), |a| {
+            a.map(|x| 1.0 / (1.0 + (-x).exp()))
+        })
+    }
+
+    pub fn exp(self) -> Var<'t> {
+        self.unary(Operation::Exp(self.index), |a| a.map(f64::exp))
+    }
+
+    /// Natural logarithm.
+    pub fn ln(self) -> Var<'t> {
+        self.unary(Operation::Ln(self.index), |a| a.map(f64::ln))
+    }
+
+    pub fn sum(self) -> Var<'t> {
+        self.unary(Operation::Sum(self.index), |a| {
+            Tensor::from_element(1, 1, a.sum())
+        })
+    }
+
+    pub fn mean(self) -> Var<'t> {
+        self.unary(Operation::Mean(self.index), |a| {
+            Tensor::from_element(1, 1, a.mean())
+        })
+    }
+}
+
+impl<'t> Add for Var<'t> {
+    type Output = Var<'t>;
+
+    fn add(self, other: Var<'t>) -> Var<'t> {
+        self.binary(other, Operation::Add(self.index, other.index), |a, b| {
+            let shape = get_broadcast_shape(a, b);
+            get_broadcast_value(a, shape) + get_broadcast_value(b, shape)
+        })
+    }
+}
+
+impl<'t> Sub for Var<'t> {
+    type Output = Var<'t>;
+
+    fn sub(self, other: Var<'t>) -> Var<'t> {
+        self.binary(other, Operation::Sub(self.index, other.index), |a, b| {
+            let shape = get_broadcast_shape(a, b);
+            get_broadcast_value(a, shape) - get_broadcast_value(b, shape)
+        })
+    }
+}
+
+impl<'t> Neg for Var<'t> {
+    type Output = Var<'t>;
+
+    fn neg(self) -> Var<'t> {
+        self.unary(Operation::Neg(self.index), |a| -a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reused_var_accumulates_gradients() {
+        let tape = Tape::new();
+        let x = tape.scalar(3.0);
+        let y = x.component_mul(x) + x;
+        let gradients = tape.get_gradients(y);
+        assert_eq!(gradients.get(x)[(0, 0)], 7.0);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::checkpoint::TrainingCheckpoint;
+use crate::model::{EstimationModel, Gradient};
+use crate::training::get_cost;
+use nalgebra::{DMatrix, MatrixXx1};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// What a callback sees of the training run.
+pub struct TrainingState<'a> {
+    /// Zero-based; equals `epoch_count` in `on_train_end` when training ran
+    /// to completion.
+    pub epoch: usize,
+    pub epoch_count: usize,
+    /// Zero-based batch within the epoch.
+    pub batch: usize,
+    pub estimation_model: &'a EstimationModel,
+    /// Gradient of the last update; `None` before the first one.
+    pub gradient: Option<&'a Gradient>,
+    /// Regularized cost on the whole training set, or on the batch in
+    /// `on_batch_end`.
+    pub cost: f64,
+    pub learning_rate: f64,
+    /// The state to resume from; `None` in `on_batch_end`, where an epoch is
+    /// only partly done.
+    pub checkpoint: Option<&'a TrainingCheckpoint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackControl {
+    Continue,
+    /// Ends training after every callback has seen the current event.
+    Stop,
+}
+
+/// Hooks into `training::train_with_callbacks`. Every method does nothing by
+/// default.
+pub trait Callback {
+    fn on_train_begin(&mut self, _state: &TrainingState) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn on_train_end(&mut self, _state: &TrainingState) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        _state: &TrainingState,
+    ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+        Ok(CallbackControl::Continue)
+    }
+
+    fn on_batch_end(
+        &mut self,
+        _state: &TrainingState,
+    ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+        Ok(CallbackControl::Continue)
+    }
+}
+
+/// Prints the cost every `every` epochs and after the last one.
+pub struct ProgressPrinter {
+    pub every: usize,
+}
+
+impl Callback for ProgressPrinter {
+    fn on_train_end(&mut self, state: &TrainingState) -> Result<(), Box<dyn std::error::Error>> {
+        if state.epoch < state.epoch_count {
+            println!(
+                "stopped after epoch {}/{} cost: {}",
+                state.epoch, state.epoch_count, state.cost
+            );
+        }
+        Ok(())
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        state: &TrainingState,
+    ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+        let epoch = state.epoch + 1;
+        let epochs_since_print = epoch % self.every.max(1);
+        if epochs_since_print == 0 || epoch == state.epoch_count {
+            println!("epoch {}/{} cost: {}", epoch, state.epoch_count, state.cost);
+        }
+        Ok(CallbackControl::Continue)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricFormat {
+    Csv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+/// Writes the epoch, cost, learning rate and gradient norm after every epoch.
+pub struct MetricLogger {
+    writer: BufWriter<File>,
+    format: MetricFormat,
+}
+
+impl MetricLogger {
+    pub fn create(
+        file_path: &str,
+        format: MetricFormat,
+    ) -> Result<MetricLogger, Box<dyn std::error::Error>> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        if format == MetricFormat::Csv {
+            writeln!(writer, "epoch,cost,learning_rate,gradient_norm")?;
+        }
+        Ok(MetricLogger { writer, format })
+    }
+}
+
+impl Callback for MetricLogger {
+    fn on_train_end(&mut self, _state: &TrainingState) -> Result<(), Box<dyn std::error::Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        state: &TrainingState,
+    ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+        let gradient_norm = state.gradient.map_or(f64::NAN, |gradient| {
+            (gradient.parameters.norm_squared() + gradient.b.powi(2)).sqrt()
+        });
+        match self.format {
+            MetricFormat::Csv => writeln!(
+                self.writer,
+                "{},{},{},{}",
+                state.epoch + 1,
+                state.cost,
+                state.learning_rate,
+                gradient_norm
+            )?,
+            // NaN and infinity are not valid JSON and are written as null.
+            MetricFormat::Jsonl => writeln!(
+                self.writer,
+                "{}",
+                serde_json::json!({
+                    "epoch": state.epoch + 1,
+                    "cost": state.cost,
+                    "learning_rate": state.learning_rate,
+                    "gradient_norm": gradient_norm,
+                })
+            )?,
+        }
+        Ok(CallbackControl::Continue)
+    }
+}
+
+/// Saves a `TrainingCheckpoint` every `every` epochs, to
+/// `{directory}/checkpoint_{epoch}.json`.
+pub struct Checkpointer {
+    pub directory: String,
+    pub every: usize,
+}
+
+impl Callback for Checkpointer {
+    fn on_train_begin(&mut self, _state: &TrainingState) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.directory)?;
+        Ok(())
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        state: &TrainingState,
+    ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+        let epoch = state.epoch + 1;
+        let epochs_since_checkpoint = epoch % self.every.max(1);
+        if let (0, Some(checkpoint)) = (epochs_since_checkpoint, state.checkpoint) {
+            checkpoint.save(&format!("{}/checkpoint_{:06}.json", self.directory, epoch))?;
+        }
+        Ok(CallbackControl::Continue)
+    }
+}
+
+/// Stops when the monitored cost has not improved by more than `min_delta`
+/// for `patience` epochs, and keeps the best model seen. The training cost is
+/// monitored unless a validation set is given.
+///
+/// The two costs deliberately differ: the training cost is the regularized
+/// objective gradient descent minimizes, so it stops improving when training
+/// has converged, while the validation cost is unregularized because the L2
+/// penalty says nothing about how well the model predicts unseen rows. Don't
+/// compare `get_best_cost` across the two modes.
+pub struct EarlyStopping {
+    pub patience: usize,
+    pub min_delta: f64,
+    validation_set: Option<(DMatrix<f64>, MatrixXx1<f64>)>,
+    best_cost: f64,
+    wait: usize,
+    best_estimation_model: Option<EstimationModel>,
+}
+
+impl EarlyStopping {
+    pub fn new(patience: usize, min_delta: f64) -> EarlyStopping {
+        EarlyStopping {
+            patience,
+            min_delta,
+            validation_set: None,
+            best_cost: f64::INFINITY,
+            wait: 0,
+            best_estimation_model: None,
+        }
+    }
+
+    pub fn with_validation_set(
+        mut self,
+        features_set: DMatrix<f64>,
+        target_set: MatrixXx1<f64>,
+    ) -> EarlyStopping {
+        self.validation_set = Some((features_set, target_set));
+        self
+    }
+
+    pub fn get_best_cost(&self) -> f64 {
+        self.best_cost
+    }
+
+    pub fn get_best_estimation_model(&self) -> Option<&EstimationModel> {
+        self.best_estimation_model.as_ref()
+    }
+}
+
+impl Callback for EarlyStopping {
+    fn on_epoch_end(
+        &mut self,
+        state: &TrainingState,
+    ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+        let cost = match &self.validation_set {
+            Some((features_set, target_set)) => {
+                get_cost(features_set, target_set, state.estimation_model)
+            }
+            None => state.cost,
+        };
+        if cost < self.best_cost - self.min_delta {
+            self.best_cost = cost;
+            self.wait = 0;
+            self.best_estimation_model = Some(state.estimation_model.clone());
+            return Ok(CallbackControl::Continue);
+        }
+        self.wait += 1;
+        if self.wait >= self.patience {
+            Ok(CallbackControl::Stop)
+        } else {
+            Ok(CallbackControl::Continue)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::training::{train_with_callbacks, Hyperparameters};
+    use nalgebra::RowDVector;
+
+    fn get_estimation_model(b: f64) -> EstimationModel {
+        EstimationModel {
+            parameters: RowDVector::from_vec(vec![1.0]),
+            b,
+        }
+    }
+
+    /// Feeds one epoch per cost, with a model whose `b` is the epoch, and
+    /// returns the control of every epoch.
+    fn run_epochs(early_stopping: &mut EarlyStopping, costs: &[f64]) -> Vec<CallbackControl> {
+        costs
+            .iter()
+            .enumerate()
+            .map(|(epoch, cost)| {
+                let estimation_model = get_estimation_model(epoch as f64);
+                early_stopping
+                    .on_epoch_end(&TrainingState {
+                        epoch,
+                        epoch_count: costs.len(),
+                        batch: 0,
+                        estimation_model: &estimation_model,
+                        gradient: None,
+                        cost: *cost,
+                        learning_rate: 0.1,
+                        checkpoint: None,
+                    })
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn early_stopping_waits_patience_epochs_without_improvement() {
+        use CallbackControl::{Continue, Stop};
+        let mut early_stopping = EarlyStopping::new(3, 0.0);
+        assert_eq!(
+            run_epochs(&mut early_stopping, &[5.0, 4.0, 4.0, 3.0, 3.5, 4.0, 3.0]),
+            vec![Continue, Continue, Continue, Continue, Continue, Continue, Stop]
+        );
+        assert_eq!(early_stopping.get_best_cost(), 3.0);
+    }
+
+    #[test]
+    fn early_stopping_ignores_improvements_below_min_delta() {
+        use CallbackControl::{Continue, Stop};
+        let mut early_stopping = EarlyStopping::new(2, 0.5);
+        assert_eq!(
+            run_epochs(&mut early_stopping, &[5.0, 4.6, 4.55]),
+            vec![Continue, Continue, Stop]
+        );
+        assert_eq!(early_stopping.get_best_cost(), 5.0);
+    }
+
+    #[test]
+    fn early_stopping_keeps_the_best_model() {
+        let mut early_stopping = EarlyStopping::new(10, 0.0);
+        run_epochs(&mut early_stopping, &[3.0, 1.0, 2.0, 1.0, 4.0]);
+        assert_eq!(early_stopping.get_best_estimation_model().unwrap().b, 1.0);
+    }
+
+    #[test]
+    fn early_stopping_monitors_the_unregularized_validation_cost() {
+        // Every model estimates x + b, which fits this validation set best at
+        // b = 3, whatever the training cost says.
+        let features_set = DMatrix::from_vec(3, 1, vec![0.0, 1.0, 2.0]);
+        let target_set = MatrixXx1::from_vec(vec![3.0, 4.0, 5.0]);
+        let mut early_stopping =
+            EarlyStopping::new(10, 0.0).with_validation_set(features_set, target_set);
+        run_epochs(&mut early_stopping, &[1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(early_stopping.get_best_estimation_model().unwrap().b, 3.0);
+        assert_eq!(early_stopping.get_best_cost(), 0.0);
+    }
+
+    #[test]
+    fn early_stopping_stops_a_diverging_run() {
+        let features_set = DMatrix::from_vec(4, 1, vec![-1.5, -0.5, 0.5, 1.5]);
+        let target_set = MatrixXx1::from_vec(vec![0.0, 1.0, 2.0, 3.0]);
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(1),
+            b: 0.0,
+        };
+        let mut early_stopping = EarlyStopping::new(3, 0.0);
+        let (_, costs) = train_with_callbacks(
+            &features_set,
+            &target_set,
+            &initial,
+            &Hyperparameters {
+                learning_rate: 5.0,
+                iteration_count: 100,
+                regularization_strength: 0.0,
+                batch_size: None,
+                shuffle_seed: None,
+            },
+            &mut [&mut early_stopping],
+        )
+        .unwrap();
+        // The cost only improves in the first epoch, then grows for the
+        // three epochs of patience.
+        assert_eq!(costs.len(), 4);
+        assert!(costs.windows(2).skip(1).all(|pair| pair[1] > pair[0]));
+        assert_eq!(
+            early_stopping.get_best_estimation_model().unwrap().b,
+            5.0 * 1.5
+        );
+    }
+}
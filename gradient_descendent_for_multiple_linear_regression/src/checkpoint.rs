@@ -0,0 +1,58 @@
+use crate::model::EstimationModel;
+use crate::training::Hyperparameters;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+
+/// Everything `training::resume` needs to continue a run exactly where it
+/// stopped. Checkpoints are taken between epochs. Plain gradient descent
+/// keeps no optimizer moments and uses no learning rate schedule, so the
+/// model, the epoch and the shuffling RNG are the whole optimizer state.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainingCheckpoint {
+    pub hyperparameters: Hyperparameters,
+    pub estimation_model: EstimationModel,
+    /// Number of completed epochs.
+    pub epoch: usize,
+    /// Cost at the start of every completed epoch.
+    pub costs: Vec<f64>,
+    /// Shuffles the rows before every epoch when `shuffle_seed` is set.
+    pub rng: ChaCha8Rng,
+}
+
+impl TrainingCheckpoint {
+    /// The state before the first epoch.
+    pub fn new(
+        initial_estimation_model: &EstimationModel,
+        hyperparameters: &Hyperparameters,
+    ) -> TrainingCheckpoint {
+        TrainingCheckpoint {
+            hyperparameters: *hyperparameters,
+            estimation_model: initial_estimation_model.clone(),
+            epoch: 0,
+            costs: Vec::new(),
+            rng: ChaCha8Rng::seed_from_u64(hyperparameters.shuffle_seed.unwrap_or(0)),
+        }
+    }
+
+    /// JSON cannot hold NaN or infinity, so a diverged run cannot be saved.
+    pub fn save(&self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.costs.iter().all(|cost| cost.is_finite())
+            || !self
+                .estimation_model
+                .parameters
+                .iter()
+                .all(|parameter| parameter.is_finite())
+            || !self.estimation_model.b.is_finite()
+        {
+            return Err("cannot save a checkpoint with non-finite values".into());
+        }
+        serde_json::to_writer(File::create(file_path)?, self)?;
+        Ok(())
+    }
+
+    pub fn load(file_path: &str) -> Result<TrainingCheckpoint, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_reader(File::open(file_path)?)?)
+    }
+}
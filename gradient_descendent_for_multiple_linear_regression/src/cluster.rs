@@ -0,0 +1,490 @@
+use nalgebra::{DMatrix, RowDVector};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KMeansAlgorithm {
+    /// Reassigns every row and recomputes every centroid each iteration.
+    Lloyd,
+    /// Moves the centroids towards `batch_size` random rows each iteration,
+    /// with a per-centroid learning rate of one over its row count so far.
+    MiniBatch { batch_size: usize },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct KMeansParameters {
+    pub cluster_count: usize,
+    pub algorithm: KMeansAlgorithm,
+    /// Independent k-means++ seedings; the run with the lowest inertia wins.
+    pub restart_count: usize,
+    pub max_iteration_count: usize,
+    /// Stops once no centroid moves by more than this squared distance.
+    pub tolerance: f64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KMeans {
+    /// One row per cluster.
+    pub centroids: DMatrix<f64>,
+    /// Sum of squared distances from every training row to its centroid.
+    pub inertia: f64,
+    /// Iterations run by the winning restart.
+    pub iteration_count: usize,
+}
+
+fn get_squared_distance(
+    features_set: &DMatrix<f64>,
+    row: usize,
+    centroids: &DMatrix<f64>,
+    cluster: usize,
+) -> f64 {
+    (0..features_set.ncols())
+        .map(|column| (features_set[(row, column)] - centroids[(cluster, column)]).powi(2))
+        .sum()
+}
+
+/// Nearest centroid and its squared distance.
+fn get_nearest_cluster(
+    features_set: &DMatrix<f64>,
+    row: usize,
+    centroids: &DMatrix<f64>,
+) -> (usize, f64) {
+    (0..centroids.nrows())
+        .map(|cluster| {
+            (
+                cluster,
+                get_squared_distance(features_set, row, centroids, cluster),
+            )
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .expect("at least one centroid")
+}
+
+/// k-means++: the first centroid is a random row, every next one a row drawn
+/// with probability proportional to its squared distance to the nearest
+/// centroid so far.
+fn get_initial_centroids(
+    features_set: &DMatrix<f64>,
+    cluster_count: usize,
+    rng: &mut StdRng,
+) -> DMatrix<f64> {
+    let row_count = features_set.nrows();
+    let mut rows = vec![rng.gen_range(0..row_count)];
+    let mut squared_distances = vec![f64::INFINITY; row_count];
+    while rows.len() < cluster_count {
+        let centroid = features_set.select_rows(&rows[rows.len() - 1..]);
+        for (row, squared_distance) in squared_distances.iter_mut().enumerate() {
+            *squared_distance =
+                squared_distance.min(get_squared_distance(features_set, row, &centroid, 0));
+        }
+        // Every row coincides with a centroid when there are fewer distinct
+        // rows than clusters.
+        let row = match WeightedIndex::new(&squared_distances) {
+            Ok(distribution) => distribution.sample(rng),
+            Err(_) => rng.gen_range(0..row_count),
+        };
+        rows.push(row);
+    }
+    features_set.select_rows(&rows)
+}
+
+fn get_largest_shift(previous: &DMatrix<f64>, centroids: &DMatrix<f64>) -> f64 {
+    previous
+        .row_iter()
+        .zip(centroids.row_iter())
+        .map(|(previous, centroid)| (previous - centroid).norm_squared())
+        .fold(0.0, f64::max)
+}
+
+/// Returns the centroids and the number of iterations run.
+fn run_lloyd(
+    features_set: &DMatrix<f64>,
+    mut centroids: DMatrix<f64>,
+    parameters: &KMeansParameters,
+) -> (DMatrix<f64>, usize) {
+    let cluster_count = centroids.nrows();
+    for iteration in 0..parameters.max_iteration_count {
+        let mut sums = DMatrix::zeros(cluster_count, features_set.ncols());
+        let mut counts = vec![0; cluster_count];
+        let mut squared_distances = Vec::with_capacity(features_set.nrows());
+        for row in 0..features_set.nrows() {
+            let (cluster, squared_distance) = get_nearest_cluster(features_set, row, &centroids);
+            let mut sum = sums.row_mut(cluster);
+            sum += features_set.row(row);
+            counts[cluster] += 1;
+            squared_distances.push((row, squared_distance));
+        }
+        // Empty clusters restart at the rows worst served, a different row
+        // for each so that they do not collapse into one again.
+        squared_distances.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let mut farthest_rows = squared_distances.iter().map(|(row, _)| *row);
+        let previous = centroids.clone();
+        for (cluster, count) in counts.iter().enumerate() {
+            if *count > 0 {
+                centroids.set_row(cluster, &(sums.row(cluster) / *count as f64));
+            } else if let Some(row) = farthest_rows.next() {
+                centroids.set_row(cluster, &features_set.row(row));
+            }
+        }
+        if get_largest_shift(&previous, &centroids) <= parameters.tolerance {
+            return (centroids, iteration + 1);
+        }
+    }
+    (centroids, parameters.max_iteration_count)
+}
+
+fn run_mini_batch(
+    features_set: &DMatrix<f64>,
+    mut centroids: DMatrix<f64>,
+    batch_size: usize,
+    parameters: &KMeansParameters,
+    rng: &mut StdRng,
+) -> (DMatrix<f64>, usize) {
+    let batch_size = batch_size.clamp(1, features_set.nrows());
+    let mut counts = vec![0; centroids.nrows()];
+    for iteration in 0..parameters.max_iteration_count {
+        let previous = centroids.clone();
+        let batch = sample(rng, features_set.nrows(), batch_size).into_vec();
+        let clusters = batch
+            .iter()
+            .map(|row| get_nearest_cluster(features_set, *row, &centroids).0)
+            .collect::<Vec<_>>();
+        for (row, cluster) in batch.iter().zip(clusters) {
+            counts[cluster] += 1;
+            let learning_rate = 1.0 / counts[cluster] as f64;
+            let centroid = centroids.row(cluster) * (1.0 - learning_rate)
+                + features_set.row(*row) * learning_rate;
+            centroids.set_row(cluster, &centroid);
+        }
+        if get_largest_shift(&previous, &centroids) <= parameters.tolerance {
+            return (centroids, iteration + 1);
+        }
+    }
+    (centroids, parameters.max_iteration_count)
+}
+
+impl KMeans {
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        parameters: &KMeansParameters,
+    ) -> Result<KMeans, Box<dyn std::error::Error>> {
+        if parameters.cluster_count == 0 || parameters.cluster_count > features_set.nrows() {
+            return Err(format!(
+                "cluster count must be between 1 and the row count {}",
+                features_set.nrows()
+            )
+            .into());
+        }
+        if features_set.iter().any(|feature| !feature.is_finite()) {
+            return Err("features must be finite; impute missing values first".into());
+        }
+        let mut rng = StdRng::seed_from_u64(parameters.seed);
+        let mut best: Option<KMeans> = None;
+        for _ in 0..parameters.restart_count.max(1) {
+            let initial = get_initial_centroids(features_set, parameters.cluster_count, &mut rng);
+            let (centroids, iteration_count) = match parameters.algorithm {
+                KMeansAlgorithm::Lloyd => run_lloyd(features_set, initial, parameters),
+                KMeansAlgorithm::MiniBatch { batch_size } => {
+                    run_mini_batch(features_set, initial, batch_size, parameters, &mut rng)
+                }
+            };
+            let k_means = KMeans {
+                centroids,
+                inertia: 0.0,
+                iteration_count,
+            };
+            let k_means = KMeans {
+                inertia: k_means.get_inertia(features_set),
+                ..k_means
+            };
+            let is_better = match &best {
+                Some(best) => k_means.inertia < best.inertia,
+                None => true,
+            };
+            if is_better {
+                best = Some(k_means);
+            }
+        }
+        Ok(best.expect("at least one restart"))
+    }
+
+    /// Index of the nearest centroid of every row.
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> Vec<usize> {
+        (0..features_set.nrows())
+            .map(|row| get_nearest_cluster(features_set, row, &self.centroids).0)
+            .collect()
+    }
+
+    pub fn get_inertia(&self, features_set: &DMatrix<f64>) -> f64 {
+        (0..features_set.nrows())
+            .map(|row| get_nearest_cluster(features_set, row, &self.centroids).1)
+            .sum()
+    }
+
+    /// Euclidean distance from every row to every centroid, one column per
+    /// cluster, to be appended to the features of a regression model.
+    pub fn transform(&self, features_set: &DMatrix<f64>) -> DMatrix<f64> {
+        DMatrix::from_fn(
+            features_set.nrows(),
+            self.centroids.nrows(),
+            |row, cluster| get_squared_distance(features_set, row, &self.centroids, cluster).sqrt(),
+        )
+    }
+
+    pub fn transform_features(&self, features: &RowDVector<f64>) -> RowDVector<f64> {
+        RowDVector::from_iterator(
+            self.centroids.nrows(),
+            self.centroids
+                .row_iter()
+                .map(|centroid| (features - centroid).norm()),
+        )
+    }
+}
+
+/// Mean silhouette of the rows: `(b - a) / max(a, b)`, where `a` is the mean
+/// distance to the other rows of the same cluster and `b` the lowest mean
+/// distance to the rows of another cluster. 0 for rows alone in their
+/// cluster and for rows where `a` and `b` are both 0, such as duplicates of
+/// a row in another cluster. Quadratic in the row count; NaN with fewer than
+/// two clusters.
+pub fn get_silhouette_score(
+    features_set: &DMatrix<f64>,
+    labels: &[usize],
+) -> Result<f64, Box<dyn std::error::Error>> {
+    if labels.len() != features_set.nrows() {
+        return Err(format!(
+            "{} feature rows but {} labels",
+            features_set.nrows(),
+            labels.len()
+        )
+        .into());
+    }
+    let cluster_count = labels.iter().max().map_or(0, |label| label + 1);
+    let mut cluster_sizes = vec![0; cluster_count];
+    for label in labels {
+        cluster_sizes[*label] += 1;
+    }
+    if cluster_sizes.iter().filter(|size| **size > 0).count() < 2 {
+        return Ok(f64::NAN);
+    }
+    let silhouettes = (0..features_set.nrows()).map(|row| {
+        let label = labels[row];
+        if cluster_sizes[label] == 1 {
+            return 0.0;
+        }
+        let mut distance_sums = vec![0.0; cluster_count];
+        for other in 0..features_set.nrows() {
+            distance_sums[labels[other]] +=
+                (features_set.row(row) - features_set.row(other)).norm();
+        }
+        let a = distance_sums[label] / (cluster_sizes[label] - 1) as f64;
+        let b = (0..cluster_count)
+            .filter(|cluster| *cluster != label && cluster_sizes[*cluster] > 0)
+            .map(|cluster| distance_sums[cluster] / cluster_sizes[cluster] as f64)
+            .fold(f64::INFINITY, f64::min);
+        if a.max(b) == 0.0 {
+            return 0.0;
+        }
+        (b - a) / a.max(b)
+    });
+    Ok(silhouettes.sum::<f64>() / features_set.nrows() as f64)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ElbowScore {
+    pub cluster_count: usize,
+    pub inertia: f64,
+    pub silhouette_score: f64,
+}
+
+/// Inertia and silhouette score of a fit for every cluster count, for an
+/// elbow plot.
+pub fn get_elbow_scores(
+    features_set: &DMatrix<f64>,
+    cluster_counts: &[usize],
+    parameters: &KMeansParameters,
+) -> Result<Vec<ElbowScore>, Box<dyn std::error::Error>> {
+    cluster_counts
+        .iter()
+        .map(|cluster_count| {
+            let k_means = KMeans::fit(
+                features_set,
+                &KMeansParameters {
+                    cluster_count: *cluster_count,
+                    ..*parameters
+                },
+            )?;
+            let silhouette_score =
+                get_silhouette_score(features_set, &k_means.predict(features_set))?;
+            Ok(ElbowScore {
+                cluster_count: *cluster_count,
+                inertia: k_means.inertia,
+                silhouette_score,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CENTERS: [(f64, f64); 3] = [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)];
+
+    /// 30 rows around each of `CENTERS`.
+    fn get_blobs() -> DMatrix<f64> {
+        let mut rng = StdRng::seed_from_u64(5);
+        DMatrix::from_fn(90, 2, |row, column| {
+            let center = CENTERS[row % 3];
+            let offset = rng.gen_range(-1.0..1.0);
+            if column == 0 {
+                center.0 + offset
+            } else {
+                center.1 + offset
+            }
+        })
+    }
+
+    const PARAMETERS: KMeansParameters = KMeansParameters {
+        cluster_count: 3,
+        algorithm: KMeansAlgorithm::Lloyd,
+        restart_count: 5,
+        max_iteration_count: 100,
+        tolerance: 1.0e-8,
+        seed: 0,
+    };
+
+    fn assert_finds_centers(k_means: &KMeans) {
+        for center in CENTERS {
+            let distance = k_means
+                .centroids
+                .row_iter()
+                .map(|centroid| {
+                    ((centroid[0] - center.0).powi(2) + (centroid[1] - center.1).powi(2)).sqrt()
+                })
+                .fold(f64::INFINITY, f64::min);
+            assert!(distance < 0.5, "{:?}: {}", center, distance);
+        }
+    }
+
+    #[test]
+    fn finds_separated_clusters() {
+        let features_set = get_blobs();
+        for algorithm in [
+            KMeansAlgorithm::Lloyd,
+            KMeansAlgorithm::MiniBatch { batch_size: 16 },
+        ] {
+            let k_means = KMeans::fit(
+                &features_set,
+                &KMeansParameters {
+                    algorithm,
+                    ..PARAMETERS
+                },
+            )
+            .unwrap();
+            assert_finds_centers(&k_means);
+            let labels = k_means.predict(&features_set);
+            for row in 3..90 {
+                assert_eq!(labels[row], labels[row % 3]);
+            }
+            assert!(get_silhouette_score(&features_set, &labels).unwrap() > 0.8);
+        }
+    }
+
+    #[test]
+    fn inertia_falls_with_cluster_count() {
+        let features_set = get_blobs();
+        let scores = get_elbow_scores(&features_set, &[1, 2, 3, 4], &PARAMETERS).unwrap();
+        assert!(scores
+            .windows(2)
+            .all(|pair| pair[1].inertia <= pair[0].inertia));
+        assert!(scores[0].silhouette_score.is_nan());
+        let best = scores[1..]
+            .iter()
+            .max_by(|a, b| a.silhouette_score.total_cmp(&b.silhouette_score))
+            .unwrap();
+        assert_eq!(best.cluster_count, 3);
+    }
+
+    #[test]
+    fn empty_clusters_restart_at_distinct_rows() {
+        let features_set = DMatrix::from_row_slice(5, 1, &[0.0, 1.0, 10.0, 11.0, 20.0]);
+        // Every row is nearest to the first centroid, so two clusters are
+        // empty after the first assignment.
+        let initial = DMatrix::from_row_slice(3, 1, &[0.5, 100.0, 200.0]);
+        let (centroids, _) = run_lloyd(
+            &features_set,
+            initial.clone(),
+            &KMeansParameters {
+                max_iteration_count: 1,
+                ..PARAMETERS
+            },
+        );
+        assert_eq!(centroids, DMatrix::from_row_slice(3, 1, &[8.4, 20.0, 11.0]));
+
+        let (centroids, _) = run_lloyd(&features_set, initial, &PARAMETERS);
+        let k_means = KMeans {
+            centroids,
+            inertia: 0.0,
+            iteration_count: 0,
+        };
+        assert_eq!(k_means.get_inertia(&features_set), 1.0);
+    }
+
+    #[test]
+    fn silhouette_score_validates_labels_and_handles_coincident_clusters() {
+        let features_set = DMatrix::from_row_slice(4, 1, &[1.0, 1.0, 1.0, 1.0]);
+        assert!(get_silhouette_score(&features_set, &[0, 0, 1]).is_err());
+        assert_eq!(
+            get_silhouette_score(&features_set, &[0, 0, 1, 1]).unwrap(),
+            0.0
+        );
+        assert!(get_silhouette_score(&features_set, &[0, 0, 0, 0])
+            .unwrap()
+            .is_nan());
+    }
+
+    #[test]
+    fn transforms_into_centroid_distances() {
+        let features_set = DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 3.0, 4.0]);
+        let k_means = KMeans {
+            centroids: DMatrix::from_row_slice(2, 2, &[0.0, 0.0, 3.0, 0.0]),
+            inertia: 0.0,
+            iteration_count: 0,
+        };
+        assert_eq!(
+            k_means.transform(&features_set),
+            DMatrix::from_row_slice(2, 2, &[0.0, 3.0, 5.0, 4.0])
+        );
+        assert_eq!(
+            k_means.transform_features(&RowDVector::from_vec(vec![3.0, 4.0])),
+            RowDVector::from_vec(vec![5.0, 4.0])
+        );
+    }
+
+    #[test]
+    fn handles_duplicate_rows() {
+        let features_set = DMatrix::from_row_slice(4, 1, &[1.0, 1.0, 1.0, 2.0]);
+        let k_means = KMeans::fit(
+            &features_set,
+            &KMeansParameters {
+                cluster_count: 3,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        assert_eq!(k_means.inertia, 0.0);
+        assert!(KMeans::fit(
+            &features_set,
+            &KMeansParameters {
+                cluster_count: 5,
+                ..PARAMETERS
+            },
+        )
+        .is_err());
+    }
+}
@@ -1,7 +1,12 @@
-use crate::model::SeoulRealEstateTransactionPrice;
-use nalgebra::{DMatrix, MatrixXx1};
+use crate::encoding::{CategoricalEncoder, EncoderKind};
+use crate::model::{CategoricalColumn, ColumnEncoder, SeoulRealEstateTransactionPrice};
+use csv::DeserializeRecordsIntoIter;
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
 use std::fs::File;
 
+/// Loads the records that have a target and no missing features. Other rows,
+/// including ones that fail to parse, are skipped; use `load_records` and an
+/// `Imputer` to keep rows with missing features.
 pub fn load_training_set(
     file_path: &str,
 ) -> Result<(DMatrix<f64>, MatrixXx1<f64>), Box<dyn std::error::Error>> {
@@ -11,21 +16,205 @@ pub fn load_training_set(
 
     for result in rdr.deserialize::<SeoulRealEstateTransactionPrice>() {
         match result {
-            Ok(record) => {
+            Ok(record) if record.물건금액.is_some() && !record.has_missing_features() => {
                 features_vec.push(record.into_features());
                 target_vec.push(record.into_target());
             }
-            Err(_) => {
+            _ => {
                 continue;
             }
         }
     }
-    let features_set = DMatrix::from_vec(
-        features_vec.len(),
-        features_vec[0].len(),
-        features_vec.concat(),
-    );
+    let column_count = features_vec.first().map_or(0, |features| features.len());
+    let features_set =
+        DMatrix::from_row_slice(features_vec.len(), column_count, &features_vec.concat());
     let target_set = MatrixXx1::from_vec(target_vec);
 
     Ok((features_set, target_set))
 }
+
+/// Loads every record that has a target. Missing feature values are kept as
+/// NaN so that they can be imputed.
+pub fn load_records(
+    file_path: &str,
+) -> Result<Vec<SeoulRealEstateTransactionPrice>, Box<dyn std::error::Error>> {
+    let mut rdr = csv::Reader::from_reader(File::open(file_path)?);
+    Ok(rdr
+        .deserialize::<SeoulRealEstateTransactionPrice>()
+        .filter_map(|result| result.ok())
+        .filter(|record| record.물건금액.is_some())
+        .collect())
+}
+
+/// Features, targets and the fitted encoders of each categorical column.
+pub type CategoricalTrainingSet = (DMatrix<f64>, MatrixXx1<f64>, Vec<ColumnEncoder>);
+
+/// Fits one encoder per categorical column on `records` and returns the
+/// training set with the encoded columns appended after the numeric features.
+/// Categories seen fewer than `min_frequency` times are bucketed together.
+pub fn fit_categorical_training_set(
+    records: &[SeoulRealEstateTransactionPrice],
+    columns: &[(CategoricalColumn, EncoderKind)],
+    min_frequency: usize,
+) -> Result<CategoricalTrainingSet, Box<dyn std::error::Error>> {
+    let target_vec = records
+        .iter()
+        .map(|record| record.into_target())
+        .collect::<Vec<f64>>();
+    let mut features_vec = records
+        .iter()
+        .map(|record| record.into_features())
+        .collect::<Vec<Vec<f64>>>();
+    let mut column_encoders = Vec::new();
+
+    for (column, kind) in columns {
+        let categories = records
+            .iter()
+            .map(|record| record.get_category(*column))
+            .collect::<Vec<&str>>();
+        let (encoder, encoded) =
+            CategoricalEncoder::fit_transform(*kind, &categories, &target_vec, min_frequency)?;
+        for (features, encoded_features) in features_vec.iter_mut().zip(encoded) {
+            features.extend(encoded_features);
+        }
+        column_encoders.push(ColumnEncoder {
+            column: *column,
+            encoder,
+        });
+    }
+
+    let column_count = features_vec.first().map_or(0, |features| features.len());
+    let features_set =
+        DMatrix::from_row_slice(features_vec.len(), column_count, &features_vec.concat());
+    let target_set = MatrixXx1::from_vec(target_vec);
+    Ok((features_set, target_set, column_encoders))
+}
+
+/// Reads the training set from `file_path` in batches of `batch_size` rows
+/// without holding the whole file in memory. Every call to `batches` re-opens
+/// the file, so one call is one epoch. Like `load_training_set`, rows without
+/// a target or with missing features are skipped.
+pub struct StreamingTrainingSet {
+    pub file_path: String,
+    pub batch_size: usize,
+}
+
+impl StreamingTrainingSet {
+    pub fn new(file_path: &str, batch_size: usize) -> StreamingTrainingSet {
+        StreamingTrainingSet {
+            file_path: file_path.to_string(),
+            batch_size,
+        }
+    }
+
+    pub fn batches(&self) -> Result<TrainingBatches, Box<dyn std::error::Error>> {
+        if self.batch_size == 0 {
+            return Err("batch size must be positive".into());
+        }
+        let rdr = csv::Reader::from_reader(File::open(&self.file_path)?);
+        Ok(TrainingBatches {
+            records: rdr.into_deserialize(),
+            batch_size: self.batch_size,
+            skipped_row_count: 0,
+        })
+    }
+
+    /// Computes the feature mean and standard deviation in a single pass over
+    /// the file.
+    pub fn get_row_statistics(&self) -> Result<RowStatistics, Box<dyn std::error::Error>> {
+        let mut row_statistics = RowStatistics::new();
+        for (features_set, _) in self.batches()? {
+            for features in features_set.row_iter() {
+                row_statistics.push(&RowDVector::from(features));
+            }
+        }
+        Ok(row_statistics)
+    }
+}
+
+pub struct TrainingBatches {
+    records: DeserializeRecordsIntoIter<File, SeoulRealEstateTransactionPrice>,
+    batch_size: usize,
+    /// Rows skipped so far because they failed to parse, had no target or
+    /// had missing features.
+    pub skipped_row_count: usize,
+}
+
+impl Iterator for TrainingBatches {
+    type Item = (DMatrix<f64>, MatrixXx1<f64>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut features_vec: Vec<f64> = Vec::new();
+        let mut target_vec: Vec<f64> = Vec::with_capacity(self.batch_size);
+
+        while target_vec.len() < self.batch_size {
+            match self.records.next() {
+                Some(Ok(record)) if record.물건금액.is_some() && !record.has_missing_features() =>
+                {
+                    features_vec.extend(record.into_features());
+                    target_vec.push(record.into_target());
+                }
+                Some(_) => {
+                    self.skipped_row_count += 1;
+                }
+                None => {
+                    break;
+                }
+            }
+        }
+        if target_vec.is_empty() {
+            return None;
+        }
+
+        let row_count = target_vec.len();
+        let features_set =
+            DMatrix::from_row_slice(row_count, features_vec.len() / row_count, &features_vec);
+        let target_set = MatrixXx1::from_vec(target_vec);
+        Some((features_set, target_set))
+    }
+}
+
+/// Running feature mean and standard deviation using Welford's algorithm.
+#[derive(Debug, Clone)]
+pub struct RowStatistics {
+    pub count: usize,
+    mean: RowDVector<f64>,
+    m2: RowDVector<f64>,
+}
+
+impl Default for RowStatistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RowStatistics {
+    pub fn new() -> RowStatistics {
+        RowStatistics {
+            count: 0,
+            mean: RowDVector::zeros(0),
+            m2: RowDVector::zeros(0),
+        }
+    }
+
+    pub fn push(&mut self, features: &RowDVector<f64>) {
+        if self.count == 0 {
+            self.mean = RowDVector::zeros(features.len());
+            self.m2 = RowDVector::zeros(features.len());
+        }
+        self.count += 1;
+        let delta = features - &self.mean;
+        self.mean += &delta / self.count as f64;
+        let delta_after = features - &self.mean;
+        self.m2 += delta.component_mul(&delta_after);
+    }
+
+    pub fn get_row_mean(&self) -> RowDVector<f64> {
+        self.mean.clone()
+    }
+
+    /// Population standard deviation, matching `training::get_row_std_deviation`.
+    pub fn get_row_std_deviation(&self) -> RowDVector<f64> {
+        (&self.m2 / self.count as f64).map(|variance| variance.sqrt())
+    }
+}
@@ -0,0 +1,255 @@
+use nalgebra::{DMatrix, RowDVector};
+use serde::{Deserialize, Serialize};
+
+/// How many principal components `Pca::fit` keeps.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ComponentSelection {
+    Count(usize),
+    /// The fewest components whose explained variance ratios add up to at
+    /// least this much.
+    ExplainedVarianceRatio(f64),
+}
+
+/// Principal component analysis from the SVD of the centered training set.
+/// Like the z-score statistics, it is fitted on the training set and stored
+/// in `ModelArtifact`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pca {
+    pub mean: RowDVector<f64>,
+    /// One unit-length component per row, by decreasing variance. Each is
+    /// signed so that its largest entry in absolute value is positive.
+    pub components: DMatrix<f64>,
+    /// Variance along each kept component.
+    pub explained_variances: RowDVector<f64>,
+    /// Share of the total variance along each kept component.
+    pub explained_variance_ratios: RowDVector<f64>,
+    /// Scales every component to unit variance, which decorrelates the
+    /// features and equalizes their scales.
+    pub whiten: bool,
+}
+
+impl Pca {
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        selection: ComponentSelection,
+        whiten: bool,
+    ) -> Result<Pca, Box<dyn std::error::Error>> {
+        if features_set.nrows() < 2 {
+            return Err("PCA needs at least two rows".into());
+        }
+        if features_set.iter().any(|feature| !feature.is_finite()) {
+            return Err("features must be finite; impute missing values first".into());
+        }
+        let mean = features_set.row_mean();
+        let mut centered_set = features_set.clone();
+        for mut row in centered_set.row_iter_mut() {
+            row -= &mean;
+        }
+        let svd = centered_set.svd(false, true);
+        let v_t = svd
+            .v_t
+            .ok_or("SVD did not compute the right singular vectors")?;
+        let mut order = (0..svd.singular_values.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| svd.singular_values[*b].total_cmp(&svd.singular_values[*a]));
+
+        let variances = order
+            .iter()
+            .map(|index| svd.singular_values[*index].powi(2) / (features_set.nrows() - 1) as f64)
+            .collect::<Vec<_>>();
+        let total_variance = variances.iter().sum::<f64>();
+        let ratios = variances
+            .iter()
+            .map(|variance| {
+                if total_variance > 0.0 {
+                    variance / total_variance
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let component_count = match selection {
+            ComponentSelection::Count(count) => {
+                if count == 0 || count > order.len() {
+                    return Err(
+                        format!("component count must be between 1 and {}", order.len()).into(),
+                    );
+                }
+                count
+            }
+            ComponentSelection::ExplainedVarianceRatio(ratio) => {
+                let mut cumulative_ratio = 0.0;
+                ratios
+                    .iter()
+                    .position(|component_ratio| {
+                        cumulative_ratio += component_ratio;
+                        cumulative_ratio >= ratio - 1.0e-12
+                    })
+                    .map_or(order.len(), |position| position + 1)
+            }
+        };
+
+        let mut components = v_t.select_rows(&order[..component_count]);
+        for mut component in components.row_iter_mut() {
+            let largest = component
+                .iter()
+                .cloned()
+                .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+                .unwrap_or(0.0);
+            if largest < 0.0 {
+                component.neg_mut();
+            }
+        }
+        Ok(Pca {
+            mean,
+            components,
+            explained_variances: RowDVector::from_column_slice(&variances[..component_count]),
+            explained_variance_ratios: RowDVector::from_column_slice(&ratios[..component_count]),
+            whiten,
+        })
+    }
+
+    /// Components with no variance are left unscaled.
+    fn get_scales(&self) -> RowDVector<f64> {
+        self.explained_variances.map(|variance| {
+            if self.whiten && variance > 0.0 {
+                variance.sqrt()
+            } else {
+                1.0
+            }
+        })
+    }
+
+    pub fn transform_features(&self, features: &RowDVector<f64>) -> RowDVector<f64> {
+        ((features - &self.mean) * self.components.transpose()).component_div(&self.get_scales())
+    }
+
+    /// One row per row of `features_set`, one column per component.
+    pub fn transform(&self, features_set: &DMatrix<f64>) -> DMatrix<f64> {
+        let mut transformed_set = features_set.clone();
+        for mut row in transformed_set.row_iter_mut() {
+            row -= &self.mean;
+        }
+        let mut transformed_set = transformed_set * self.components.transpose();
+        let scales = self.get_scales();
+        for mut row in transformed_set.row_iter_mut() {
+            row.component_div_assign(&scales);
+        }
+        transformed_set
+    }
+
+    /// Maps component scores back to features. Exact when every component
+    /// was kept; otherwise the projection onto the kept components.
+    pub fn inverse_transform(&self, transformed_set: &DMatrix<f64>) -> DMatrix<f64> {
+        let scales = self.get_scales();
+        let mut scaled_set = transformed_set.clone();
+        for mut row in scaled_set.row_iter_mut() {
+            row.component_mul_assign(&scales);
+        }
+        let mut features_set = scaled_set * &self.components;
+        for mut row in features_set.row_iter_mut() {
+            row += &self.mean;
+        }
+        features_set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Two collinear columns, like 건물면적 and 토지면적, plus a little noise
+    /// and an independent third column.
+    fn get_features_set() -> DMatrix<f64> {
+        let mut rng = StdRng::seed_from_u64(2);
+        let rows = (0..200)
+            .map(|_| {
+                let size = rng.gen_range(20.0..200.0);
+                RowDVector::from_vec(vec![
+                    size,
+                    1.5 * size + rng.gen_range(-2.0..2.0),
+                    rng.gen_range(-5.0..5.0),
+                ])
+            })
+            .collect::<Vec<_>>();
+        DMatrix::from_rows(&rows)
+    }
+
+    fn get_covariance(features_set: &DMatrix<f64>) -> DMatrix<f64> {
+        let mut centered_set = features_set.clone();
+        let mean = features_set.row_mean();
+        for mut row in centered_set.row_iter_mut() {
+            row -= &mean;
+        }
+        centered_set.transpose() * &centered_set / (features_set.nrows() - 1) as f64
+    }
+
+    #[test]
+    fn finds_collinear_direction() {
+        let features_set = get_features_set();
+        let pca = Pca::fit(&features_set, ComponentSelection::Count(3), false).unwrap();
+        let direction = RowDVector::from_vec(vec![1.0, 1.5, 0.0]).normalize();
+        assert!(pca.components.row(0).dot(&direction) > 0.999);
+        assert!(pca.explained_variance_ratios[0] > 0.99);
+        approx::assert_relative_eq!(pca.explained_variance_ratios.sum(), 1.0, epsilon = 1.0e-12);
+        approx::assert_relative_eq!(
+            pca.explained_variances.sum(),
+            get_covariance(&features_set).trace(),
+            max_relative = 1.0e-10
+        );
+    }
+
+    #[test]
+    fn selects_components_by_explained_variance() {
+        let features_set = get_features_set();
+        let one = Pca::fit(
+            &features_set,
+            ComponentSelection::ExplainedVarianceRatio(0.99),
+            false,
+        )
+        .unwrap();
+        let all = Pca::fit(
+            &features_set,
+            ComponentSelection::ExplainedVarianceRatio(1.0),
+            false,
+        )
+        .unwrap();
+        assert_eq!(one.components.nrows(), 1);
+        assert_eq!(all.components.nrows(), 3);
+        assert!(Pca::fit(&features_set, ComponentSelection::Count(4), false).is_err());
+    }
+
+    #[test]
+    fn whitening_gives_identity_covariance() {
+        let features_set = get_features_set();
+        let pca = Pca::fit(&features_set, ComponentSelection::Count(3), true).unwrap();
+        let transformed_set = pca.transform(&features_set);
+        approx::assert_relative_eq!(
+            get_covariance(&transformed_set),
+            DMatrix::identity(3, 3),
+            epsilon = 1.0e-9
+        );
+        approx::assert_relative_eq!(
+            pca.transform_features(&RowDVector::from(features_set.row(7))),
+            RowDVector::from(transformed_set.row(7)),
+            epsilon = 1.0e-12
+        );
+        approx::assert_relative_eq!(
+            pca.inverse_transform(&transformed_set),
+            features_set,
+            max_relative = 1.0e-10
+        );
+    }
+
+    #[test]
+    fn inverse_transform_reconstructs_without_whitening() {
+        let features_set = get_features_set();
+        let pca = Pca::fit(&features_set, ComponentSelection::Count(3), false).unwrap();
+        approx::assert_relative_eq!(
+            pca.inverse_transform(&pca.transform(&features_set)),
+            features_set,
+            max_relative = 1.0e-10
+        );
+    }
+}
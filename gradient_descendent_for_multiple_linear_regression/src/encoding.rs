@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How a categorical column should be encoded. The fitted result is a
+/// `CategoricalEncoder`.
+#[derive(Debug, Clone, Copy)]
+pub enum EncoderKind {
+    OneHot,
+    Ordinal,
+    /// Smoothed mean of the target per category. Training rows are encoded
+    /// with statistics from the other `fold_count - 1` folds so that a row's
+    /// own target never leaks into its feature. `smoothing` must be positive:
+    /// it is what keeps the mean of a category absent from a fold defined.
+    Target {
+        smoothing: f64,
+        fold_count: usize,
+    },
+    Hashing {
+        bucket_count: usize,
+    },
+}
+
+/// A fitted categorical encoder. Categories seen fewer than `min_frequency`
+/// times during fitting are bucketed together with categories never seen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CategoricalEncoder {
+    /// One column per category, plus one shared column for rare categories if
+    /// any were bucketed. Unseen categories are encoded to that column, or to
+    /// all zeros when there is none.
+    OneHot {
+        categories: Vec<String>,
+        has_infrequent_column: bool,
+    },
+    /// `0..categories.len()` for known categories and `categories.len()` for
+    /// rare or unseen ones.
+    Ordinal {
+        categories: Vec<String>,
+    },
+    /// Unseen and rare categories are encoded to `infrequent_mean`.
+    Target {
+        categories: Vec<String>,
+        means: Vec<f64>,
+        infrequent_mean: f64,
+    },
+    Hashing {
+        bucket_count: usize,
+    },
+}
+
+/// A fitted encoder and the encoded training rows.
+pub type FittedEncoder = (CategoricalEncoder, Vec<Vec<f64>>);
+
+fn get_frequent_categories(categories: &[&str], min_frequency: usize) -> Vec<String> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for category in categories {
+        *counts.entry(category).or_insert(0) += 1;
+    }
+    let mut frequent_categories = counts
+        .into_iter()
+        .filter(|(_, count)| *count >= min_frequency)
+        .map(|(category, _)| category.to_string())
+        .collect::<Vec<_>>();
+    frequent_categories.sort();
+    frequent_categories
+}
+
+/// Smoothed per-category target means over the rows selected by `is_used`.
+/// Rare categories share the mean of the whole selection.
+fn get_smoothed_means(
+    categories: &[&str],
+    targets: &[f64],
+    frequent_categories: &[String],
+    smoothing: f64,
+    is_used: impl Fn(usize) -> bool,
+) -> (Vec<f64>, f64) {
+    let mut sums = vec![0.0; frequent_categories.len()];
+    let mut counts = vec![0.0; frequent_categories.len()];
+    let mut total_sum = 0.0;
+    let mut total_count = 0.0;
+    for (index, (category, target)) in categories.iter().zip(targets).enumerate() {
+        if !is_used(index) {
+            continue;
+        }
+        total_sum += target;
+        total_count += 1.0;
+        if let Ok(position) = find_category(frequent_categories, category) {
+            sums[position] += target;
+            counts[position] += 1.0;
+        }
+    }
+    let global_mean = if total_count > 0.0 {
+        total_sum / total_count
+    } else {
+        0.0
+    };
+    let means = sums
+        .iter()
+        .zip(counts.iter())
+        .map(|(sum, count)| (sum + smoothing * global_mean) / (count + smoothing))
+        .collect();
+    (means, global_mean)
+}
+
+fn find_category(categories: &[String], category: &str) -> Result<usize, usize> {
+    categories.binary_search_by(|c| c.as_str().cmp(category))
+}
+
+/// 64-bit FNV-1a. Used instead of `DefaultHasher` so that hashed columns stay
+/// the same across Rust versions and saved models remain valid.
+fn get_stable_hash(category: &str) -> u64 {
+    category.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl CategoricalEncoder {
+    /// Fits an encoder on the training column and returns it along with the
+    /// encoded training rows. `targets` are only read by target encoding,
+    /// which needs one per category.
+    pub fn fit_transform(
+        kind: EncoderKind,
+        categories: &[&str],
+        targets: &[f64],
+        min_frequency: usize,
+    ) -> Result<FittedEncoder, Box<dyn std::error::Error>> {
+        match kind {
+            EncoderKind::Hashing { bucket_count: 0 } => {
+                return Err("hashing needs at least one bucket".into());
+            }
+            EncoderKind::Target { smoothing, .. } if smoothing.is_nan() || smoothing <= 0.0 => {
+                return Err(format!(
+                    "target encoding smoothing must be positive, got {}",
+                    smoothing
+                )
+                .into());
+            }
+            EncoderKind::Target { .. } if targets.len() != categories.len() => {
+                return Err(format!(
+                    "target encoding needs one target per category, got {} targets for {} categories",
+                    targets.len(),
+                    categories.len()
+                )
+                .into());
+            }
+            _ => {}
+        }
+        let frequent_categories = get_frequent_categories(categories, min_frequency);
+        let has_infrequent = categories
+            .iter()
+            .any(|category| find_category(&frequent_categories, category).is_err());
+
+        match kind {
+            EncoderKind::OneHot => {
+                let encoder = CategoricalEncoder::OneHot {
+                    categories: frequent_categories,
+                    has_infrequent_column: has_infrequent,
+                };
+                let encoded = encoder.transform_all(categories);
+                Ok((encoder, encoded))
+            }
+            EncoderKind::Ordinal => {
+                let encoder = CategoricalEncoder::Ordinal {
+                    categories: frequent_categories,
+                };
+                let encoded = encoder.transform_all(categories);
+                Ok((encoder, encoded))
+            }
+            EncoderKind::Target {
+                smoothing,
+                fold_count,
+            } => {
+                let (means, infrequent_mean) = get_smoothed_means(
+                    categories,
+                    targets,
+                    &frequent_categories,
+                    smoothing,
+                    |_| true,
+                );
+                let fold_count = fold_count.max(1);
+                let mut encoded = vec![Vec::new(); categories.len()];
+                for fold in 0..fold_count {
+                    let (fold_means, fold_infrequent_mean) = if fold_count == 1 {
+                        (means.clone(), infrequent_mean)
+                    } else {
+                        get_smoothed_means(
+                            categories,
+                            targets,
+                            &frequent_categories,
+                            smoothing,
+                            |index| index % fold_count != fold,
+                        )
+                    };
+                    let fold_encoder = CategoricalEncoder::Target {
+                        categories: frequent_categories.clone(),
+                        means: fold_means,
+                        infrequent_mean: fold_infrequent_mean,
+                    };
+                    for index in (fold..categories.len()).step_by(fold_count) {
+                        encoded[index] = fold_encoder.transform(categories[index]);
+                    }
+                }
+                let encoder = CategoricalEncoder::Target {
+                    categories: frequent_categories,
+                    means,
+                    infrequent_mean,
+                };
+                Ok((encoder, encoded))
+            }
+            EncoderKind::Hashing { bucket_count } => {
+                let encoder = CategoricalEncoder::Hashing { bucket_count };
+                let encoded = encoder.transform_all(categories);
+                Ok((encoder, encoded))
+            }
+        }
+    }
+
+    pub fn get_column_count(&self) -> usize {
+        match self {
+            CategoricalEncoder::OneHot {
+                categories,
+                has_infrequent_column,
+            } => categories.len() + *has_infrequent_column as usize,
+            CategoricalEncoder::Ordinal { .. } => 1,
+            CategoricalEncoder::Target { .. } => 1,
+            CategoricalEncoder::Hashing { bucket_count } => *bucket_count,
+        }
+    }
+
+    pub fn transform(&self, category: &str) -> Vec<f64> {
+        match self {
+            CategoricalEncoder::OneHot {
+                categories,
+                has_infrequent_column,
+            } => {
+                let mut encoded = vec![0.0; self.get_column_count()];
+                match find_category(categories, category) {
+                    Ok(position) => encoded[position] = 1.0,
+                    Err(_) if *has_infrequent_column => encoded[categories.len()] = 1.0,
+                    Err(_) => {}
+                }
+                encoded
+            }
+            CategoricalEncoder::Ordinal { categories } => {
+                let position = find_category(categories, category).unwrap_or(categories.len());
+                vec![position as f64]
+            }
+            CategoricalEncoder::Target {
+                categories,
+                means,
+                infrequent_mean,
+            } => {
+                let mean = match find_category(categories, category) {
+                    Ok(position) => means[position],
+                    Err(_) => *infrequent_mean,
+                };
+                vec![mean]
+            }
+            CategoricalEncoder::Hashing { bucket_count } => {
+                let mut encoded = vec![0.0; *bucket_count];
+                encoded[(get_stable_hash(category) % *bucket_count as u64) as usize] += 1.0;
+                encoded
+            }
+        }
+    }
+
+    pub fn transform_all(&self, categories: &[&str]) -> Vec<Vec<f64>> {
+        categories
+            .iter()
+            .map(|category| self.transform(category))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_hot_buckets_rare_and_unseen_categories() {
+        let categories = ["강남구", "마포구", "강남구", "노원구", "강남구", "마포구"];
+        let (encoder, encoded) =
+            CategoricalEncoder::fit_transform(EncoderKind::OneHot, &categories, &[], 2).unwrap();
+        assert_eq!(encoder.get_column_count(), 3);
+        assert_eq!(encoded[0], vec![1.0, 0.0, 0.0]);
+        assert_eq!(encoded[1], vec![0.0, 1.0, 0.0]);
+        assert_eq!(encoded[3], vec![0.0, 0.0, 1.0]);
+        assert_eq!(encoder.transform("서초구"), vec![0.0, 0.0, 1.0]);
+
+        let (encoder, _) =
+            CategoricalEncoder::fit_transform(EncoderKind::OneHot, &categories, &[], 1).unwrap();
+        assert_eq!(encoder.get_column_count(), 3);
+        assert_eq!(encoder.transform("서초구"), vec![0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn target_encoding_does_not_leak_a_rows_own_target() {
+        let categories = ["a", "a", "a", "a", "b", "b"];
+        let targets = [1.0, 2.0, 3.0, 4.0, 10.0, 20.0];
+        let kind = EncoderKind::Target {
+            smoothing: 1.0,
+            fold_count: 2,
+        };
+        let (encoder, encoded) =
+            CategoricalEncoder::fit_transform(kind, &categories, &targets, 1).unwrap();
+        // Row 0 is in fold 0 and is encoded with rows 1, 3 and 5: global mean
+        // 26 / 3 and "a" mean (2 + 4 + 26 / 3) / (2 + 1).
+        approx::assert_relative_eq!(encoded[0][0], (6.0 + 26.0 / 3.0) / 3.0);
+        // The fitted encoder uses every row: global mean 40 / 6.
+        approx::assert_relative_eq!(
+            encoder.transform("a")[0],
+            (10.0 + 40.0 / 6.0) / 5.0,
+            max_relative = 1.0e-12
+        );
+        approx::assert_relative_eq!(encoder.transform("c")[0], 40.0 / 6.0);
+
+        let mut changed_targets = targets;
+        changed_targets[0] = 1000.0;
+        let (_, changed_encoded) =
+            CategoricalEncoder::fit_transform(kind, &categories, &changed_targets, 1).unwrap();
+        assert_eq!(changed_encoded[0], encoded[0]);
+        assert_eq!(changed_encoded[2], encoded[2]);
+        assert_ne!(changed_encoded[1], encoded[1]);
+    }
+
+    #[test]
+    fn target_encoding_rejects_bad_smoothing_and_target_counts() {
+        let categories = ["a", "a", "b"];
+        let targets = [1.0, 2.0, 3.0];
+        for smoothing in [0.0, -1.0, f64::NAN] {
+            let kind = EncoderKind::Target {
+                smoothing,
+                fold_count: 2,
+            };
+            assert!(CategoricalEncoder::fit_transform(kind, &categories, &targets, 1).is_err());
+        }
+        let kind = EncoderKind::Target {
+            smoothing: 1.0,
+            fold_count: 2,
+        };
+        assert!(CategoricalEncoder::fit_transform(kind, &categories, &targets[..2], 1).is_err());
+        // "b" only appears in fold 0, so fold 0 is encoded without it.
+        let (_, encoded) =
+            CategoricalEncoder::fit_transform(kind, &categories, &targets, 1).unwrap();
+        assert!(encoded.iter().flatten().all(|feature| feature.is_finite()));
+    }
+
+    #[test]
+    fn hashing_is_stable_and_rejects_zero_buckets() {
+        assert_eq!(get_stable_hash("a"), 0xaf63dc4c8601ec8c);
+        let categories = ["강남구", "마포구", "강남구"];
+        let (encoder, encoded) = CategoricalEncoder::fit_transform(
+            EncoderKind::Hashing { bucket_count: 4 },
+            &categories,
+            &[],
+            1,
+        )
+        .unwrap();
+        assert_eq!(encoder.get_column_count(), 4);
+        for features in &encoded {
+            assert_eq!(features.iter().sum::<f64>(), 1.0);
+        }
+        assert_eq!(encoded[0], encoded[2]);
+        assert_eq!(encoder.transform("서초구"), encoder.transform("서초구"));
+
+        assert!(CategoricalEncoder::fit_transform(
+            EncoderKind::Hashing { bucket_count: 0 },
+            &categories,
+            &[],
+            1,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use crate::tree::{check_training_set, DecisionTree, TreeParameters};
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ForestParameters {
+    pub tree_count: usize,
+    /// Applied to every tree. `max_features` is what decorrelates them,
+    /// e.g. a third of the columns for regression or their square root for
+    /// classification.
+    pub tree_parameters: TreeParameters,
+    /// Seeds the bootstrap samples and the column subsets of every tree.
+    pub seed: u64,
+}
+
+/// Bagged decision trees. Each tree is fitted to a bootstrap sample of the
+/// rows; the forest predicts the mean of their leaf values.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RandomForest {
+    pub trees: Vec<DecisionTree>,
+    /// Error of every row predicted by the trees whose bootstrap sample left
+    /// it out: mean squared error for a regressor, misclassification rate for
+    /// a classifier. `None` when every row was in every sample.
+    pub oob_error: Option<f64>,
+}
+
+impl RandomForest {
+    pub fn fit_regressor(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        parameters: &ForestParameters,
+    ) -> Result<RandomForest, Box<dyn std::error::Error>> {
+        if parameters.tree_parameters.criterion.is_classification() {
+            return Err(format!(
+                "{:?} is a classification criterion",
+                parameters.tree_parameters.criterion
+            )
+            .into());
+        }
+        RandomForest::fit(features_set, target_set.as_slice(), parameters)
+    }
+
+    pub fn fit_classifier(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+        parameters: &ForestParameters,
+    ) -> Result<RandomForest, Box<dyn std::error::Error>> {
+        if !parameters.tree_parameters.criterion.is_classification() {
+            return Err(format!(
+                "{:?} is a regression criterion",
+                parameters.tree_parameters.criterion
+            )
+            .into());
+        }
+        let targets = target_set
+            .iter()
+            .map(|target| if *target { 1.0 } else { 0.0 })
+            .collect::<Vec<_>>();
+        RandomForest::fit(features_set, &targets, parameters)
+    }
+
+    /// Trees are fitted in parallel with the `parallel` feature. Every tree
+    /// draws from its own seed, so the forest is the same either way.
+    fn fit(
+        features_set: &DMatrix<f64>,
+        targets: &[f64],
+        parameters: &ForestParameters,
+    ) -> Result<RandomForest, Box<dyn std::error::Error>> {
+        if parameters.tree_count == 0 {
+            return Err("a forest needs at least one tree".into());
+        }
+        check_training_set(features_set, targets)?;
+        let row_count = features_set.nrows();
+        let mut rng = StdRng::seed_from_u64(parameters.seed);
+        let tree_seeds = (0..parameters.tree_count)
+            .map(|_| rng.gen::<u64>())
+            .collect::<Vec<_>>();
+
+        let fit_tree = |tree_seed: &u64| {
+            let mut rng = StdRng::seed_from_u64(*tree_seed);
+            let rows = (0..row_count)
+                .map(|_| rng.gen_range(0..row_count))
+                .collect::<Vec<_>>();
+            let mut in_bag = vec![false; row_count];
+            for row in &rows {
+                in_bag[*row] = true;
+            }
+            let tree = DecisionTree::fit_rows(
+                features_set,
+                targets,
+                rows,
+                &parameters.tree_parameters,
+                &mut rng,
+            );
+            (tree, in_bag)
+        };
+        #[cfg(feature = "parallel")]
+        let fitted_trees = tree_seeds.par_iter().map(fit_tree).collect::<Vec<_>>();
+        #[cfg(not(feature = "parallel"))]
+        let fitted_trees = tree_seeds.iter().map(fit_tree).collect::<Vec<_>>();
+
+        let mut oob_sums = vec![0.0; row_count];
+        let mut oob_counts = vec![0; row_count];
+        for (tree, in_bag) in &fitted_trees {
+            for (row, features) in features_set.row_iter().enumerate() {
+                if !in_bag[row] {
+                    oob_sums[row] += tree.predict_row(&RowDVector::from(features));
+                    oob_counts[row] += 1;
+                }
+            }
+        }
+        let (oob_estimates, oob_targets): (Vec<f64>, Vec<f64>) = (0..row_count)
+            .filter(|row| oob_counts[*row] > 0)
+            .map(|row| (oob_sums[row] / oob_counts[row] as f64, targets[row]))
+            .unzip();
+        let is_classification = parameters.tree_parameters.criterion.is_classification();
+        let oob_error = if oob_estimates.is_empty() {
+            None
+        } else {
+            Some(get_error(is_classification, &oob_estimates, &oob_targets))
+        };
+
+        Ok(RandomForest {
+            trees: fitted_trees.into_iter().map(|(tree, _)| tree).collect(),
+            oob_error,
+        })
+    }
+
+    fn is_classification(&self) -> bool {
+        self.trees[0].criterion.is_classification()
+    }
+
+    pub fn predict_row(&self, row: &RowDVector<f64>) -> f64 {
+        self.trees
+            .iter()
+            .map(|tree| tree.predict_row(row))
+            .sum::<f64>()
+            / self.trees.len() as f64
+    }
+
+    /// The mean leaf value: the estimate for a regressor, the probability of
+    /// `true` for a classifier.
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> MatrixXx1<f64> {
+        MatrixXx1::from_iterator(
+            features_set.nrows(),
+            features_set
+                .row_iter()
+                .map(|row| self.predict_row(&RowDVector::from(row))),
+        )
+    }
+
+    pub fn predict_class(&self, features_set: &DMatrix<f64>) -> MatrixXx1<bool> {
+        self.predict(features_set)
+            .map(|probability| probability >= 0.5)
+    }
+
+    /// Mean of the trees' impurity-based importances. Cheap, but biased
+    /// towards columns with many distinct values.
+    pub fn get_feature_importances(&self) -> RowDVector<f64> {
+        self.trees.iter().fold(
+            RowDVector::zeros(self.trees[0].feature_count),
+            |importances, tree| importances + tree.get_feature_importances(),
+        ) / self.trees.len() as f64
+    }
+
+    /// Increase of the error on `features_set` when one column is shuffled,
+    /// averaged over `repeat_count` shuffles. For a classifier the targets
+    /// are 1.0 for `true` and 0.0 for `false`. Best measured on rows the
+    /// forest was not trained on.
+    pub fn get_permutation_importances(
+        &self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        repeat_count: usize,
+        seed: u64,
+    ) -> RowDVector<f64> {
+        let is_classification = self.is_classification();
+        let base_error = get_error(
+            is_classification,
+            self.predict(features_set).as_slice(),
+            target_set.as_slice(),
+        );
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut importances = RowDVector::zeros(features_set.ncols());
+        for column in 0..features_set.ncols() {
+            for _ in 0..repeat_count {
+                let mut shuffled_column = features_set
+                    .column(column)
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                shuffled_column.shuffle(&mut rng);
+                let mut shuffled_set = features_set.clone();
+                shuffled_set.set_column(column, &MatrixXx1::from_vec(shuffled_column));
+                importances[column] += get_error(
+                    is_classification,
+                    self.predict(&shuffled_set).as_slice(),
+                    target_set.as_slice(),
+                ) - base_error;
+            }
+        }
+        importances / repeat_count.max(1) as f64
+    }
+}
+
+fn get_error(is_classification: bool, estimates: &[f64], targets: &[f64]) -> f64 {
+    estimates
+        .iter()
+        .zip(targets)
+        .map(|(estimate, target)| {
+            if is_classification {
+                if (*estimate >= 0.5) == (*target >= 0.5) {
+                    0.0
+                } else {
+                    1.0
+                }
+            } else {
+                (estimate - target).powi(2)
+            }
+        })
+        .sum::<f64>()
+        / targets.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::SplitCriterion;
+
+    const PARAMETERS: ForestParameters = ForestParameters {
+        tree_count: 30,
+        tree_parameters: TreeParameters {
+            criterion: SplitCriterion::Mse,
+            max_depth: None,
+            min_samples_leaf: 1,
+            max_features: Some(1),
+            ccp_alpha: 0.0,
+        },
+        seed: 7,
+    };
+
+    /// `y = x0²`; `x1` is noise.
+    fn get_regression_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let features_set = DMatrix::from_fn(80, 2, |row, column| match column {
+            0 => row as f64 / 10.0,
+            _ => ((row * 37) % 80) as f64,
+        });
+        let target_set = MatrixXx1::from_fn(80, |row, _| (row as f64 / 10.0).powi(2));
+        (features_set, target_set)
+    }
+
+    #[test]
+    fn regressor_has_small_oob_error() {
+        let (features_set, target_set) = get_regression_set();
+        let forest = RandomForest::fit_regressor(&features_set, &target_set, &PARAMETERS).unwrap();
+        let variance = target_set.variance();
+        assert!(forest.oob_error.unwrap() < 0.1 * variance);
+        let importances = forest.get_feature_importances();
+        assert!(importances[0] > importances[1]);
+        approx::assert_relative_eq!(importances.sum(), 1.0, epsilon = 1.0e-12);
+    }
+
+    #[test]
+    fn same_seed_gives_same_forest() {
+        let (features_set, target_set) = get_regression_set();
+        let a = RandomForest::fit_regressor(&features_set, &target_set, &PARAMETERS).unwrap();
+        let b = RandomForest::fit_regressor(&features_set, &target_set, &PARAMETERS).unwrap();
+        let c = RandomForest::fit_regressor(
+            &features_set,
+            &target_set,
+            &ForestParameters {
+                seed: 8,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        assert_eq!(a.predict(&features_set), b.predict(&features_set));
+        assert_eq!(a.oob_error, b.oob_error);
+        assert_ne!(a.oob_error, c.oob_error);
+    }
+
+    #[test]
+    fn classifier_and_permutation_importances() {
+        let (features_set, target_set) = get_regression_set();
+        let class_set = target_set.map(|target| target > 16.0);
+        let forest = RandomForest::fit_classifier(
+            &features_set,
+            &class_set,
+            &ForestParameters {
+                tree_parameters: TreeParameters {
+                    criterion: SplitCriterion::Gini,
+                    ..PARAMETERS.tree_parameters
+                },
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        assert_eq!(forest.predict_class(&features_set), class_set);
+        assert!(forest.oob_error.unwrap() < 0.1);
+        let importances = forest.get_permutation_importances(
+            &features_set,
+            &class_set.map(|target| if target { 1.0 } else { 0.0 }),
+            5,
+            0,
+        );
+        assert!(importances[0] > 0.2);
+        assert!(importances[0] > importances[1]);
+    }
+
+    #[test]
+    fn rejects_empty_forest() {
+        let (features_set, target_set) = get_regression_set();
+        assert!(RandomForest::fit_regressor(
+            &features_set,
+            &target_set,
+            &ForestParameters {
+                tree_count: 0,
+                ..PARAMETERS
+            },
+        )
+        .is_err());
+    }
+}
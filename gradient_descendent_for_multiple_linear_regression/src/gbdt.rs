@@ -0,0 +1,563 @@
+use crate::loss::Loss;
+use crate::tree::check_training_set;
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BoostingParameters {
+    pub loss: Loss,
+    /// Upper bound on the number of boosting rounds, one tree each.
+    pub tree_count: usize,
+    /// Shrinkage applied to every tree's leaf values.
+    pub learning_rate: f64,
+    pub max_depth: usize,
+    pub min_samples_leaf: usize,
+    /// L2 penalty on leaf values. Keep it positive for `LogLoss`, whose
+    /// hessians vanish in pure leaves.
+    pub l2_regularization: f64,
+    /// Histogram bins per feature, between 2 and 65536. Split candidates are
+    /// the bin boundaries.
+    pub bin_count: usize,
+    /// Fraction of the rows drawn without replacement for every tree.
+    pub row_subsample: f64,
+    /// Fraction of the columns drawn for every tree.
+    pub column_subsample: f64,
+    /// Stops after this many rounds without a lower validation cost and
+    /// keeps the trees up to the best round. Needs a validation set.
+    pub early_stopping_rounds: Option<usize>,
+    /// Seeds the row and column subsampling.
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BoostedTreeNode {
+    /// Rows with `row[column] <= split` go left.
+    Node {
+        column: usize,
+        split: f64,
+        left: Box<BoostedTreeNode>,
+        right: Box<BoostedTreeNode>,
+    },
+    /// Already scaled by the learning rate.
+    Leaf { value: f64 },
+}
+
+/// A boosted ensemble of regression trees. It is serializable like
+/// `EstimationModel`, and `ModelArtifact::boosted_trees` ships it with the
+/// preprocessing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GradientBoostedTrees {
+    pub loss: Loss,
+    pub initial_estimate: f64,
+    pub trees: Vec<BoostedTreeNode>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoostingHistory {
+    /// Training cost after every round.
+    pub training_costs: Vec<f64>,
+    /// Validation cost after every round; empty without a validation set.
+    pub validation_costs: Vec<f64>,
+    /// Number of trees kept.
+    pub best_tree_count: usize,
+}
+
+/// Upper bounds of the histogram bins of every column. A value falls in the
+/// first bin whose bound is at least the value, or in the last, unbounded
+/// bin.
+struct FeatureBins {
+    bounds: Vec<Vec<f64>>,
+}
+
+impl FeatureBins {
+    /// Bounds sit between distinct values when a column has at most
+    /// `bin_count` of them, and at quantiles of the column otherwise.
+    fn fit(features_set: &DMatrix<f64>, bin_count: usize) -> FeatureBins {
+        let bounds = features_set
+            .column_iter()
+            .map(|column| {
+                let mut sorted = column.iter().cloned().collect::<Vec<_>>();
+                sorted.sort_by(f64::total_cmp);
+                let mut distinct = sorted.clone();
+                distinct.dedup();
+                if distinct.len() <= bin_count {
+                    distinct
+                        .windows(2)
+                        .map(|pair| (pair[0] + pair[1]) / 2.0)
+                        .collect()
+                } else {
+                    let mut bounds = (1..bin_count)
+                        .map(|bin| sorted[bin * sorted.len() / bin_count])
+                        .collect::<Vec<_>>();
+                    bounds.dedup();
+                    bounds.retain(|bound| bound < &distinct[distinct.len() - 1]);
+                    bounds
+                }
+            })
+            .collect();
+        FeatureBins { bounds }
+    }
+
+    /// Bin of every value, column by column.
+    fn transform(&self, features_set: &DMatrix<f64>) -> Vec<Vec<u16>> {
+        features_set
+            .column_iter()
+            .zip(&self.bounds)
+            .map(|(column, bounds)| {
+                column
+                    .iter()
+                    .map(|value| bounds.partition_point(|bound| bound < value) as u16)
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Gradient and hessian sums of the rows in one bin or node.
+#[derive(Debug, Clone, Copy, Default)]
+struct GradientSums {
+    gradient: f64,
+    hessian: f64,
+    row_count: usize,
+}
+
+impl GradientSums {
+    fn add_row(&mut self, gradient: f64, hessian: f64) {
+        self.gradient += gradient;
+        self.hessian += hessian;
+        self.row_count += 1;
+    }
+
+    fn get_score(&self, l2_regularization: f64) -> f64 {
+        self.gradient.powi(2) / (self.hessian + l2_regularization).max(f64::MIN_POSITIVE)
+    }
+
+    fn get_leaf_value(&self, l2_regularization: f64) -> f64 {
+        -self.gradient / (self.hessian + l2_regularization).max(f64::MIN_POSITIVE)
+    }
+}
+
+struct TreeBuilder<'a> {
+    binned_columns: &'a [Vec<u16>],
+    bins: &'a FeatureBins,
+    gradients: &'a [f64],
+    hessians: &'a [f64],
+    columns: &'a [usize],
+    parameters: &'a BoostingParameters,
+}
+
+impl TreeBuilder<'_> {
+    fn build(&self, rows: Vec<usize>, depth: usize) -> BoostedTreeNode {
+        let mut sums = GradientSums::default();
+        for row in &rows {
+            sums.add_row(self.gradients[*row], self.hessians[*row]);
+        }
+        let leaf = BoostedTreeNode::Leaf {
+            value: self.parameters.learning_rate
+                * sums.get_leaf_value(self.parameters.l2_regularization),
+        };
+        if depth >= self.parameters.max_depth
+            || rows.len() < 2 * self.parameters.min_samples_leaf.max(1)
+        {
+            return leaf;
+        }
+        let Some((column, bin)) = self.find_best_split(&rows, &sums) else {
+            return leaf;
+        };
+        let (left_rows, right_rows) = rows
+            .into_iter()
+            .partition(|row| self.binned_columns[column][*row] as usize <= bin);
+        BoostedTreeNode::Node {
+            column,
+            split: self.bins.bounds[column][bin],
+            left: Box::new(self.build(left_rows, depth + 1)),
+            right: Box::new(self.build(right_rows, depth + 1)),
+        }
+    }
+
+    /// The column and the last bin of the left child with the largest
+    /// positive gain, scanning one gradient histogram per column.
+    fn find_best_split(&self, rows: &[usize], sums: &GradientSums) -> Option<(usize, usize)> {
+        let l2_regularization = self.parameters.l2_regularization;
+        let min_samples_leaf = self.parameters.min_samples_leaf.max(1);
+        let parent_score = sums.get_score(l2_regularization);
+        let mut best: Option<(usize, usize, f64)> = None;
+
+        for column in self.columns {
+            let bounds = &self.bins.bounds[*column];
+            let mut histogram = vec![GradientSums::default(); bounds.len() + 1];
+            for row in rows {
+                histogram[self.binned_columns[*column][*row] as usize]
+                    .add_row(self.gradients[*row], self.hessians[*row]);
+            }
+            let mut left = GradientSums::default();
+            for (bin, bin_sums) in histogram.iter().take(bounds.len()).enumerate() {
+                left.gradient += bin_sums.gradient;
+                left.hessian += bin_sums.hessian;
+                left.row_count += bin_sums.row_count;
+                let right = GradientSums {
+                    gradient: sums.gradient - left.gradient,
+                    hessian: sums.hessian - left.hessian,
+                    row_count: sums.row_count - left.row_count,
+                };
+                if left.row_count < min_samples_leaf || right.row_count < min_samples_leaf {
+                    continue;
+                }
+                let gain = left.get_score(l2_regularization) + right.get_score(l2_regularization)
+                    - parent_score;
+                let is_better = match best {
+                    Some((_, _, best_gain)) => gain > best_gain,
+                    None => true,
+                };
+                if gain > 1.0e-12 && is_better {
+                    best = Some((*column, bin, gain));
+                }
+            }
+        }
+        best.map(|(column, bin, _)| (column, bin))
+    }
+}
+
+impl BoostedTreeNode {
+    fn get_value(&self, feature: &impl Fn(usize) -> f64) -> f64 {
+        match self {
+            BoostedTreeNode::Node {
+                column,
+                split,
+                left,
+                right,
+            } => {
+                if feature(*column) <= *split {
+                    left.get_value(feature)
+                } else {
+                    right.get_value(feature)
+                }
+            }
+            BoostedTreeNode::Leaf { value } => *value,
+        }
+    }
+}
+
+fn get_sample(rng: &mut StdRng, length: usize, fraction: f64) -> Vec<usize> {
+    let count = ((length as f64 * fraction).ceil() as usize).clamp(1, length);
+    let mut indices = sample(rng, length, count).into_vec();
+    indices.sort_unstable();
+    indices
+}
+
+impl GradientBoostedTrees {
+    /// Fits one tree per round to the gradients and hessians of the loss at
+    /// the current estimates. `LogLoss` targets must be 0 or 1.
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        validation_set: Option<(&DMatrix<f64>, &MatrixXx1<f64>)>,
+        parameters: &BoostingParameters,
+    ) -> Result<(GradientBoostedTrees, BoostingHistory), Box<dyn std::error::Error>> {
+        let targets = target_set.as_slice();
+        check_training_set(features_set, targets)?;
+        if !(2..=1 << 16).contains(&parameters.bin_count) {
+            return Err("bin count must be between 2 and 65536".into());
+        }
+        if parameters.row_subsample <= 0.0
+            || parameters.row_subsample > 1.0
+            || parameters.column_subsample <= 0.0
+            || parameters.column_subsample > 1.0
+        {
+            return Err("subsample fractions must be in (0, 1]".into());
+        }
+        if parameters.loss == Loss::LogLoss
+            && targets
+                .iter()
+                .any(|target| *target != 0.0 && *target != 1.0)
+        {
+            return Err("log-loss targets must be 0 or 1".into());
+        }
+        if let Some((validation_features_set, validation_target_set)) = validation_set {
+            check_training_set(validation_features_set, validation_target_set.as_slice())?;
+            if validation_features_set.ncols() != features_set.ncols() {
+                return Err("validation set has a different number of columns".into());
+            }
+        } else if parameters.early_stopping_rounds.is_some() {
+            return Err("early stopping needs a validation set".into());
+        }
+
+        let bins = FeatureBins::fit(features_set, parameters.bin_count);
+        let binned_columns = bins.transform(features_set);
+        let mut rng = StdRng::seed_from_u64(parameters.seed);
+        let loss = parameters.loss;
+        let initial_estimate = loss.get_initial_estimate(targets);
+        let mut model = GradientBoostedTrees {
+            loss,
+            initial_estimate,
+            trees: Vec::new(),
+        };
+        let mut estimates = vec![initial_estimate; targets.len()];
+        let mut validation_estimates =
+            validation_set.map(|(features_set, _)| vec![initial_estimate; features_set.nrows()]);
+        let mut history = BoostingHistory {
+            training_costs: Vec::new(),
+            validation_costs: Vec::new(),
+            best_tree_count: 0,
+        };
+        let mut best_validation_cost = f64::INFINITY;
+
+        for round in 0..parameters.tree_count {
+            let (gradients, hessians): (Vec<f64>, Vec<f64>) = estimates
+                .iter()
+                .zip(targets)
+                .map(|(estimate, target)| {
+                    (
+                        loss.get_gradient(*estimate, *target),
+                        loss.get_hessian(*estimate, *target),
+                    )
+                })
+                .unzip();
+            let rows = get_sample(&mut rng, targets.len(), parameters.row_subsample);
+            let columns = get_sample(&mut rng, features_set.ncols(), parameters.column_subsample);
+            let tree = TreeBuilder {
+                binned_columns: &binned_columns,
+                bins: &bins,
+                gradients: &gradients,
+                hessians: &hessians,
+                columns: &columns,
+                parameters,
+            }
+            .build(rows, 0);
+
+            for (row, estimate) in estimates.iter_mut().enumerate() {
+                *estimate += tree.get_value(&|column| features_set[(row, column)]);
+            }
+            history
+                .training_costs
+                .push(loss.get_cost(&estimates, targets));
+            model.trees.push(tree);
+
+            let (
+                Some((validation_features_set, validation_target_set)),
+                Some(validation_estimates),
+            ) = (validation_set, validation_estimates.as_mut())
+            else {
+                continue;
+            };
+            let tree = &model.trees[round];
+            for (row, estimate) in validation_estimates.iter_mut().enumerate() {
+                *estimate += tree.get_value(&|column| validation_features_set[(row, column)]);
+            }
+            let validation_cost =
+                loss.get_cost(validation_estimates, validation_target_set.as_slice());
+            history.validation_costs.push(validation_cost);
+            if validation_cost < best_validation_cost {
+                best_validation_cost = validation_cost;
+                history.best_tree_count = round + 1;
+            }
+            if parameters
+                .early_stopping_rounds
+                .is_some_and(|rounds| round + 1 - history.best_tree_count >= rounds)
+            {
+                break;
+            }
+        }
+
+        if parameters.early_stopping_rounds.is_some() {
+            model.trees.truncate(history.best_tree_count);
+        } else {
+            history.best_tree_count = model.trees.len();
+        }
+        Ok((model, history))
+    }
+
+    fn get_estimate(&self, feature: impl Fn(usize) -> f64) -> f64 {
+        self.initial_estimate
+            + self
+                .trees
+                .iter()
+                .map(|tree| tree.get_value(&feature))
+                .sum::<f64>()
+    }
+
+    /// The estimate for regression losses, the probability of 1 for
+    /// `LogLoss`.
+    pub fn predict_row(&self, row: &RowDVector<f64>) -> f64 {
+        self.loss
+            .get_prediction(self.get_estimate(|column| row[column]))
+    }
+
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> MatrixXx1<f64> {
+        MatrixXx1::from_fn(features_set.nrows(), |row, _| {
+            self.loss
+                .get_prediction(self.get_estimate(|column| features_set[(row, column)]))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMETERS: BoostingParameters = BoostingParameters {
+        loss: Loss::SquaredError,
+        tree_count: 200,
+        learning_rate: 0.1,
+        max_depth: 3,
+        min_samples_leaf: 2,
+        l2_regularization: 1.0,
+        bin_count: 32,
+        row_subsample: 0.8,
+        column_subsample: 1.0,
+        early_stopping_rounds: None,
+        seed: 3,
+    };
+
+    type Set = (DMatrix<f64>, MatrixXx1<f64>);
+
+    /// `y = sin(x0) + x1 / 10` on a 20x10 grid; row `i` is a validation row
+    /// when `i % 7 == 0`.
+    fn get_sets() -> (Set, Set) {
+        let features_set = DMatrix::from_fn(200, 2, |row, column| match column {
+            0 => (row % 20) as f64 / 3.0,
+            _ => (row / 20) as f64,
+        });
+        let target_set = MatrixXx1::from_fn(200, |row, _| {
+            features_set[(row, 0)].sin() + features_set[(row, 1)] / 10.0
+        });
+        let (validation_rows, training_rows): (Vec<usize>, Vec<usize>) =
+            (0..200).partition(|row| row % 7 == 0);
+        (
+            (
+                features_set.select_rows(&training_rows),
+                target_set.select_rows(&training_rows),
+            ),
+            (
+                features_set.select_rows(&validation_rows),
+                target_set.select_rows(&validation_rows),
+            ),
+        )
+    }
+
+    #[test]
+    fn fits_non_linear_target() {
+        let ((features_set, target_set), (validation_features_set, validation_target_set)) =
+            get_sets();
+        for loss in [
+            Loss::SquaredError,
+            Loss::Huber { delta: 0.5 },
+            Loss::Quantile { alpha: 0.5 },
+        ] {
+            let (model, history) = GradientBoostedTrees::fit(
+                &features_set,
+                &target_set,
+                Some((&validation_features_set, &validation_target_set)),
+                &BoostingParameters { loss, ..PARAMETERS },
+            )
+            .unwrap();
+            assert_eq!(model.trees.len(), 200);
+            assert!(history.training_costs[199] < history.training_costs[0]);
+            let mean_absolute_error = (model.predict(&validation_features_set)
+                - &validation_target_set)
+                .abs()
+                .mean();
+            assert!(
+                mean_absolute_error < 0.1,
+                "{:?}: {}",
+                loss,
+                mean_absolute_error
+            );
+        }
+    }
+
+    #[test]
+    fn early_stopping_keeps_best_round() {
+        let ((features_set, target_set), (validation_features_set, validation_target_set)) =
+            get_sets();
+        let (model, history) = GradientBoostedTrees::fit(
+            &features_set,
+            &target_set,
+            Some((&validation_features_set, &validation_target_set)),
+            &BoostingParameters {
+                tree_count: 2000,
+                learning_rate: 0.5,
+                early_stopping_rounds: Some(10),
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        assert!(history.validation_costs.len() < 2000);
+        assert_eq!(history.validation_costs.len(), history.best_tree_count + 10);
+        assert_eq!(model.trees.len(), history.best_tree_count);
+        let best_cost = history.validation_costs[history.best_tree_count - 1];
+        assert!(history
+            .validation_costs
+            .iter()
+            .all(|cost| *cost >= best_cost));
+    }
+
+    #[test]
+    fn classifies_with_log_loss() {
+        let ((features_set, target_set), _) = get_sets();
+        let class_set = target_set.map(|target| if target > 0.5 { 1.0 } else { 0.0 });
+        let (model, _) = GradientBoostedTrees::fit(
+            &features_set,
+            &class_set,
+            None,
+            &BoostingParameters {
+                loss: Loss::LogLoss,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        let accuracy = model
+            .predict(&features_set)
+            .iter()
+            .zip(class_set.iter())
+            .filter(|(probability, target)| (**probability >= 0.5) == (**target == 1.0))
+            .count() as f64
+            / class_set.len() as f64;
+        assert!(accuracy > 0.95);
+        assert!(GradientBoostedTrees::fit(
+            &features_set,
+            &target_set,
+            None,
+            &BoostingParameters {
+                loss: Loss::LogLoss,
+                ..PARAMETERS
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn binning_respects_bin_count() {
+        let features_set = DMatrix::from_fn(1000, 2, |row, column| match column {
+            0 => row as f64,
+            _ => (row % 3) as f64,
+        });
+        let bins = FeatureBins::fit(&features_set, 16);
+        assert_eq!(bins.bounds[0].len(), 15);
+        assert_eq!(bins.bounds[1], vec![0.5, 1.5]);
+        let binned_columns = bins.transform(&features_set);
+        assert_eq!(binned_columns[0].iter().max(), Some(&15));
+        assert_eq!(binned_columns[1][..4], [0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let ((features_set, target_set), _) = get_sets();
+        let (model, _) = GradientBoostedTrees::fit(
+            &features_set,
+            &target_set,
+            None,
+            &BoostingParameters {
+                tree_count: 20,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        let loaded: GradientBoostedTrees =
+            serde_json::from_str(&serde_json::to_string(&model).unwrap()).unwrap();
+        assert_eq!(loaded.predict(&features_set), model.predict(&features_set));
+    }
+}
@@ -0,0 +1,610 @@
+use crate::model::{EstimationModel, Gradient};
+use crate::training::estimate;
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use serde::{Deserialize, Serialize};
+
+/// Maps the mean `μ` of the target to the linear predictor `η = w · x + b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Link {
+    /// `η = μ`, the link of `training::estimate`.
+    Identity,
+    /// `η = ln μ`, which keeps means positive and makes features act
+    /// multiplicatively.
+    Log,
+    /// `η = ln(μ / (1 - μ))`, for means between 0 and 1.
+    Logit,
+    /// `η = 1 / μ`, the canonical link of `Gamma`.
+    Inverse,
+}
+
+impl Link {
+    pub fn get_linear_predictor(self, mean: f64) -> f64 {
+        match self {
+            Link::Identity => mean,
+            Link::Log => mean.ln(),
+            Link::Logit => (mean / (1.0 - mean)).ln(),
+            Link::Inverse => 1.0 / mean,
+        }
+    }
+
+    pub fn get_mean(self, linear_predictor: f64) -> f64 {
+        match self {
+            Link::Identity => linear_predictor,
+            Link::Log => linear_predictor.exp(),
+            Link::Logit => 1.0 / (1.0 + (-linear_predictor).exp()),
+            Link::Inverse => 1.0 / linear_predictor,
+        }
+    }
+
+    /// `dμ / dη`
+    pub fn get_mean_derivative(self, linear_predictor: f64) -> f64 {
+        match self {
+            Link::Identity => 1.0,
+            Link::Log => linear_predictor.exp(),
+            Link::Logit => {
+                let mean = self.get_mean(linear_predictor);
+                mean * (1.0 - mean)
+            }
+            Link::Inverse => -1.0 / linear_predictor.powi(2),
+        }
+    }
+}
+
+/// Distribution of the target around its mean, from the exponential
+/// dispersion family.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Distribution {
+    Gaussian,
+    /// Counts, such as transactions per district per month.
+    Poisson,
+    /// Positive, right-skewed targets such as prices.
+    Gamma,
+    /// Variance `μ^power`: 0 is `Gaussian`, 1 `Poisson` and 2 `Gamma`.
+    /// Powers between 1 and 2 fit non-negative targets with exact zeros.
+    /// Powers between 0 and 1 are not allowed.
+    Tweedie {
+        power: f64,
+    },
+}
+
+// Float literal patterns are deprecated, so Tweedie powers are matched with
+// guards instead.
+#[allow(clippy::redundant_guards)]
+impl Distribution {
+    /// The link conventionally fitted with this distribution.
+    pub fn get_default_link(self) -> Link {
+        match self {
+            Distribution::Gaussian => Link::Identity,
+            Distribution::Poisson => Link::Log,
+            Distribution::Gamma => Link::Inverse,
+            Distribution::Tweedie { power } if power == 0.0 => Link::Identity,
+            Distribution::Tweedie { .. } => Link::Log,
+        }
+    }
+
+    /// `V(μ)`, the variance of the target up to the dispersion.
+    pub fn get_variance(self, mean: f64) -> f64 {
+        match self {
+            Distribution::Gaussian => 1.0,
+            Distribution::Poisson => mean,
+            Distribution::Gamma => mean.powi(2),
+            Distribution::Tweedie { power } => mean.powf(power),
+        }
+    }
+
+    /// Deviance of one row, twice the log-likelihood lost against a model
+    /// that predicts every target exactly.
+    pub fn get_unit_deviance(self, target: f64, mean: f64) -> f64 {
+        match self {
+            Distribution::Gaussian => (target - mean).powi(2),
+            Distribution::Poisson => {
+                let log_ratio = if target > 0.0 {
+                    target * (target / mean).ln()
+                } else {
+                    0.0
+                };
+                2.0 * (log_ratio - (target - mean))
+            }
+            Distribution::Gamma => 2.0 * ((mean / target).ln() + (target - mean) / mean),
+            Distribution::Tweedie { power } if power == 0.0 => {
+                Distribution::Gaussian.get_unit_deviance(target, mean)
+            }
+            Distribution::Tweedie { power } if power == 1.0 => {
+                Distribution::Poisson.get_unit_deviance(target, mean)
+            }
+            Distribution::Tweedie { power } if power == 2.0 => {
+                Distribution::Gamma.get_unit_deviance(target, mean)
+            }
+            Distribution::Tweedie { power } => {
+                2.0 * (target.max(0.0).powf(2.0 - power) / ((1.0 - power) * (2.0 - power))
+                    - target * mean.powf(1.0 - power) / (1.0 - power)
+                    + mean.powf(2.0 - power) / (2.0 - power))
+            }
+        }
+    }
+
+    /// Tweedie distributions exist only for a power of 0 or at least 1.
+    fn check_power(self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Distribution::Tweedie { power } if power == 0.0 => Ok(()),
+            Distribution::Tweedie { power } if !power.is_finite() || power < 1.0 => {
+                Err(format!("Tweedie power {} must be 0 or at least 1", power).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_target(self, target: f64) -> Result<(), Box<dyn std::error::Error>> {
+        let is_valid = match self {
+            Distribution::Gaussian => target.is_finite(),
+            Distribution::Poisson => target.is_finite() && target >= 0.0,
+            Distribution::Gamma => target.is_finite() && target > 0.0,
+            Distribution::Tweedie { power } if power == 0.0 => target.is_finite(),
+            Distribution::Tweedie { power } if power < 2.0 => target.is_finite() && target >= 0.0,
+            Distribution::Tweedie { .. } => target.is_finite() && target > 0.0,
+        };
+        if !is_valid {
+            return Err(format!("target {} is out of the range of {:?}", target, self).into());
+        }
+        Ok(())
+    }
+
+    fn is_valid_mean(self, mean: f64) -> bool {
+        match self {
+            Distribution::Gaussian => mean.is_finite(),
+            Distribution::Tweedie { power } if power == 0.0 => mean.is_finite(),
+            _ => mean.is_finite() && mean > 0.0,
+        }
+    }
+}
+
+/// Mean unit deviance of the predictions. With `Gaussian` it is the mean
+/// squared error.
+pub fn get_mean_deviance(
+    distribution: Distribution,
+    target_set: &MatrixXx1<f64>,
+    predictions: &MatrixXx1<f64>,
+) -> f64 {
+    target_set
+        .iter()
+        .zip(predictions.iter())
+        .map(|(target, mean)| distribution.get_unit_deviance(*target, *mean))
+        .sum::<f64>()
+        / target_set.len() as f64
+}
+
+/// Share of the deviance of the mean target that the predictions explain.
+/// With `Gaussian` it is the R² score.
+pub fn get_d2_score(
+    distribution: Distribution,
+    target_set: &MatrixXx1<f64>,
+    predictions: &MatrixXx1<f64>,
+) -> f64 {
+    let null_predictions = MatrixXx1::from_element(target_set.len(), target_set.mean());
+    1.0 - get_mean_deviance(distribution, target_set, predictions)
+        / get_mean_deviance(distribution, target_set, &null_predictions)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum GlmSolver {
+    /// Full-batch gradient descent on the penalized mean deviance.
+    GradientDescent {
+        learning_rate: f64,
+        iteration_count: usize,
+    },
+    /// Iteratively reweighted least squares: Fisher scoring steps, each a
+    /// weighted least-squares solve, halved while they increase the cost.
+    /// Stops when no coefficient moves more than `tolerance`.
+    Irls {
+        max_iteration_count: usize,
+        tolerance: f64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GlmParameters {
+    pub distribution: Distribution,
+    pub link: Link,
+    pub solver: GlmSolver,
+    /// Strength of the L2 penalty on the parameters. `b` is not penalized.
+    pub regularization_strength: f64,
+}
+
+/// `estimate` gives the linear predictor, and the link maps it to the
+/// predicted mean.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GeneralizedLinearModel {
+    pub estimation_model: EstimationModel,
+    pub distribution: Distribution,
+    pub link: Link,
+}
+
+impl GeneralizedLinearModel {
+    /// Starts from the model that predicts the mean target for every row and
+    /// returns the cost before every iteration: half the mean deviance plus
+    /// the L2 penalty.
+    pub fn fit(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        parameters: &GlmParameters,
+    ) -> Result<(GeneralizedLinearModel, Vec<f64>), Box<dyn std::error::Error>> {
+        if features_set.nrows() == 0 || features_set.nrows() != target_set.nrows() {
+            return Err(format!(
+                "{} feature rows but {} targets",
+                features_set.nrows(),
+                target_set.nrows()
+            )
+            .into());
+        }
+        if features_set.iter().any(|feature| !feature.is_finite()) {
+            return Err("features must be finite; impute missing values first".into());
+        }
+        parameters.distribution.check_power()?;
+        for target in target_set.iter() {
+            parameters.distribution.check_target(*target)?;
+        }
+        let b = parameters.link.get_linear_predictor(target_set.mean());
+        if !b.is_finite() {
+            return Err(format!(
+                "the mean target {} is out of the range of the {:?} link",
+                target_set.mean(),
+                parameters.link
+            )
+            .into());
+        }
+        let mut model = GeneralizedLinearModel {
+            estimation_model: EstimationModel {
+                parameters: RowDVector::zeros(features_set.ncols()),
+                b,
+            },
+            distribution: parameters.distribution,
+            link: parameters.link,
+        };
+        let costs = match parameters.solver {
+            GlmSolver::GradientDescent {
+                learning_rate,
+                iteration_count,
+            } => {
+                let mut costs = Vec::new();
+                for _ in 0..iteration_count {
+                    costs.push(model.get_cost(
+                        features_set,
+                        target_set,
+                        parameters.regularization_strength,
+                    ));
+                    let gradient = model.get_gradient(
+                        features_set,
+                        target_set,
+                        parameters.regularization_strength,
+                    );
+                    model.estimation_model.parameters -= learning_rate * gradient.parameters;
+                    model.estimation_model.b -= learning_rate * gradient.b;
+                }
+                costs
+            }
+            GlmSolver::Irls {
+                max_iteration_count,
+                tolerance,
+            } => model.fit_irls(
+                features_set,
+                target_set,
+                parameters.regularization_strength,
+                max_iteration_count,
+                tolerance,
+            )?,
+        };
+        Ok((model, costs))
+    }
+
+    fn fit_irls(
+        &mut self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        regularization_strength: f64,
+        max_iteration_count: usize,
+        tolerance: f64,
+    ) -> Result<Vec<f64>, Box<dyn std::error::Error>> {
+        let row_count = features_set.nrows();
+        let column_count = features_set.ncols();
+        let mut costs = Vec::new();
+        for _ in 0..max_iteration_count {
+            let cost = self.get_cost(features_set, target_set, regularization_strength);
+            costs.push(cost);
+
+            // Weighted least squares of the working response on the features
+            // and a column of ones for `b`.
+            let mut normal_matrix = DMatrix::zeros(column_count + 1, column_count + 1);
+            let mut normal_vector = MatrixXx1::zeros(column_count + 1);
+            for (features, target) in features_set.row_iter().zip(target_set.iter()) {
+                let features = RowDVector::from(features);
+                let linear_predictor = estimate(&features, &self.estimation_model);
+                let mean = self.link.get_mean(linear_predictor);
+                let mean_derivative = self.link.get_mean_derivative(linear_predictor);
+                let weight = mean_derivative.powi(2) / self.distribution.get_variance(mean);
+                let working_response = linear_predictor + (target - mean) / mean_derivative;
+                let augmented_features = MatrixXx1::from_iterator(
+                    column_count + 1,
+                    features.iter().cloned().chain(std::iter::once(1.0)),
+                );
+                normal_matrix += weight * &augmented_features * augmented_features.transpose();
+                normal_vector += weight * working_response * augmented_features;
+            }
+            for column in 0..column_count {
+                normal_matrix[(column, column)] += row_count as f64 * regularization_strength;
+            }
+            let solution = normal_matrix
+                .cholesky()
+                .ok_or("IRLS weights are not positive definite; try another link")?
+                .solve(&normal_vector);
+            let current = self.estimation_model.clone();
+            let step = EstimationModel {
+                parameters: RowDVector::from_iterator(
+                    column_count,
+                    solution.rows(0, column_count).iter().cloned(),
+                ) - &current.parameters,
+                b: solution[column_count] - current.b,
+            };
+
+            let mut step_size = 1.0;
+            loop {
+                self.estimation_model = EstimationModel {
+                    parameters: &current.parameters + step_size * &step.parameters,
+                    b: current.b + step_size * step.b,
+                };
+                let next_cost = self.get_cost(features_set, target_set, regularization_strength);
+                if next_cost.is_finite() && next_cost <= cost * (1.0 + 1.0e-12) {
+                    break;
+                }
+                step_size /= 2.0;
+                if step_size < 1.0e-10 {
+                    self.estimation_model = current;
+                    return Ok(costs);
+                }
+            }
+            if step_size * step.parameters.amax().max(step.b.abs()) < tolerance {
+                break;
+            }
+        }
+        Ok(costs)
+    }
+
+    pub fn predict_row(&self, features: &RowDVector<f64>) -> f64 {
+        self.link
+            .get_mean(estimate(features, &self.estimation_model))
+    }
+
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> MatrixXx1<f64> {
+        MatrixXx1::from_iterator(
+            features_set.nrows(),
+            features_set
+                .row_iter()
+                .map(|features| self.predict_row(&RowDVector::from(features))),
+        )
+    }
+
+    /// Half the mean deviance plus `regularization_strength / 2 * |w|²`.
+    /// Infinite when a predicted mean is out of the range of the
+    /// distribution, which the identity and inverse links allow.
+    pub fn get_cost(
+        &self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        regularization_strength: f64,
+    ) -> f64 {
+        let predictions = self.predict(features_set);
+        if predictions
+            .iter()
+            .any(|mean| !self.distribution.is_valid_mean(*mean))
+        {
+            return f64::INFINITY;
+        }
+        get_mean_deviance(self.distribution, target_set, &predictions) / 2.0
+            + regularization_strength / 2.0 * self.estimation_model.parameters.norm_squared()
+    }
+
+    /// Gradient of `get_cost`. The unit deviance changes by
+    /// `-2 (y - μ) / V(μ)` per unit of `μ`.
+    pub fn get_gradient(
+        &self,
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        regularization_strength: f64,
+    ) -> Gradient {
+        let mut gradient = Gradient {
+            parameters: regularization_strength * &self.estimation_model.parameters,
+            b: 0.0,
+        };
+        let row_count = features_set.nrows() as f64;
+        for (features, target) in features_set.row_iter().zip(target_set.iter()) {
+            let features = RowDVector::from(features);
+            let linear_predictor = estimate(&features, &self.estimation_model);
+            let mean = self.link.get_mean(linear_predictor);
+            let row_gradient = -(target - mean) / self.distribution.get_variance(mean)
+                * self.link.get_mean_derivative(linear_predictor)
+                / row_count;
+            gradient.parameters += row_gradient * features;
+            gradient.b += row_gradient;
+        }
+        gradient
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradient_check::{check_gradient, EPSILON};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /// Targets with mean `exp(0.5 x0 - 0.3 x1 + 2)` and multiplicative noise.
+    fn get_training_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let mut rng = StdRng::seed_from_u64(4);
+        let features_set = DMatrix::<f64>::from_fn(300, 2, |_, _| rng.gen_range(-1.0..1.0));
+        let target_set = MatrixXx1::from_iterator(
+            300,
+            features_set.row_iter().map(|features| {
+                (0.5 * features[0] - 0.3 * features[1] + 2.0).exp() * rng.gen_range(0.5..1.5)
+            }),
+        );
+        (features_set, target_set)
+    }
+
+    #[test]
+    fn gradient_matches_central_differences() {
+        let features_set = DMatrix::from_row_slice(3, 2, &[0.5, -1.0, 1.5, 0.2, -0.3, 0.8]);
+        let target_set = MatrixXx1::from_vec(vec![0.7, 0.2, 0.4]);
+        let cases = [
+            (Distribution::Gaussian, Link::Identity),
+            (Distribution::Poisson, Link::Log),
+            (Distribution::Gamma, Link::Inverse),
+            (Distribution::Gamma, Link::Log),
+            (Distribution::Tweedie { power: 1.5 }, Link::Log),
+            (Distribution::Gaussian, Link::Logit),
+        ];
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_vec(vec![0.2, -0.1]),
+            b: 1.5,
+        };
+        for (distribution, link) in cases {
+            let with_estimation_model =
+                |estimation_model: &EstimationModel| GeneralizedLinearModel {
+                    estimation_model: estimation_model.clone(),
+                    distribution,
+                    link,
+                };
+            let gradient_check = check_gradient(
+                |estimation_model| {
+                    with_estimation_model(estimation_model).get_cost(
+                        &features_set,
+                        &target_set,
+                        0.1,
+                    )
+                },
+                |estimation_model| {
+                    with_estimation_model(estimation_model).get_gradient(
+                        &features_set,
+                        &target_set,
+                        0.1,
+                    )
+                },
+                &estimation_model,
+                EPSILON,
+            );
+            assert!(
+                gradient_check.get_max_relative_error() < 1.0e-6,
+                "{:?} {:?} {:?}",
+                distribution,
+                link,
+                gradient_check
+            );
+        }
+    }
+
+    #[test]
+    fn irls_and_gradient_descent_agree() {
+        let (features_set, target_set) = get_training_set();
+        for distribution in [
+            Distribution::Poisson,
+            Distribution::Gamma,
+            Distribution::Tweedie { power: 1.5 },
+        ] {
+            let get_parameters = |solver| GlmParameters {
+                distribution,
+                link: Link::Log,
+                solver,
+                regularization_strength: 0.0,
+            };
+            let (irls, irls_costs) = GeneralizedLinearModel::fit(
+                &features_set,
+                &target_set,
+                &get_parameters(GlmSolver::Irls {
+                    max_iteration_count: 50,
+                    tolerance: 1.0e-10,
+                }),
+            )
+            .unwrap();
+            let learning_rate = match distribution {
+                Distribution::Poisson => 0.05,
+                Distribution::Tweedie { .. } => 0.3,
+                _ => 1.0,
+            };
+            let (descended, _) = GeneralizedLinearModel::fit(
+                &features_set,
+                &target_set,
+                &get_parameters(GlmSolver::GradientDescent {
+                    learning_rate,
+                    iteration_count: 3000,
+                }),
+            )
+            .unwrap();
+            assert!(irls_costs.len() < 50, "{:?}", distribution);
+            approx::assert_relative_eq!(
+                irls.estimation_model.parameters,
+                descended.estimation_model.parameters,
+                epsilon = 1.0e-4
+            );
+            approx::assert_relative_eq!(
+                irls.estimation_model.b,
+                descended.estimation_model.b,
+                epsilon = 1.0e-4
+            );
+            approx::assert_relative_eq!(
+                irls.estimation_model.parameters,
+                RowDVector::from_vec(vec![0.5, -0.3]),
+                epsilon = 0.1
+            );
+            assert!(get_d2_score(distribution, &target_set, &irls.predict(&features_set)) > 0.3);
+        }
+    }
+
+    #[test]
+    fn tweedie_deviance_is_continuous_in_power() {
+        for (target, mean) in [(0.0, 1.3), (2.0, 0.5), (3.0, 3.5)] {
+            approx::assert_relative_eq!(
+                Distribution::Tweedie {
+                    power: 1.0 + 1.0e-7
+                }
+                .get_unit_deviance(target, mean),
+                Distribution::Poisson.get_unit_deviance(target, mean),
+                max_relative = 1.0e-5
+            );
+            if target > 0.0 {
+                approx::assert_relative_eq!(
+                    Distribution::Tweedie {
+                        power: 2.0 - 1.0e-7
+                    }
+                    .get_unit_deviance(target, mean),
+                    Distribution::Gamma.get_unit_deviance(target, mean),
+                    max_relative = 1.0e-5
+                );
+            }
+        }
+        assert!(Distribution::Gamma.check_target(0.0).is_err());
+    }
+
+    #[test]
+    fn fit_rejects_unsupported_tweedie_powers() {
+        let (features_set, target_set) = get_training_set();
+        let fit = |power| {
+            GeneralizedLinearModel::fit(
+                &features_set,
+                &target_set,
+                &GlmParameters {
+                    distribution: Distribution::Tweedie { power },
+                    link: Link::Log,
+                    solver: GlmSolver::Irls {
+                        max_iteration_count: 10,
+                        tolerance: 1.0e-8,
+                    },
+                    regularization_strength: 0.0,
+                },
+            )
+        };
+        for power in [-1.0, 0.5, f64::NAN, f64::INFINITY] {
+            assert!(fit(power).is_err());
+        }
+        for power in [0.0, 1.0, 1.5, 2.0, 3.0] {
+            assert!(fit(power).is_ok());
+        }
+    }
+}
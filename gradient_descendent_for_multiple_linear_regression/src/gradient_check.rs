@@ -0,0 +1,289 @@
+use crate::model::{EstimationModel, Gradient};
+use nalgebra::RowDVector;
+
+/// Default step for `get_numerical_gradient`, small enough for the
+/// truncation error and large enough for the rounding error of `f64` costs.
+pub const EPSILON: f64 = 1.0e-5;
+
+/// Analytic gradient compared with central differences.
+#[derive(Debug)]
+pub struct GradientCheck {
+    pub analytic_gradient: Gradient,
+    pub numerical_gradient: Gradient,
+    /// Relative error of every parameter, with `b` last.
+    pub relative_errors: Vec<f64>,
+}
+
+impl GradientCheck {
+    pub fn get_max_relative_error(&self) -> f64 {
+        self.relative_errors.iter().cloned().fold(0.0, f64::max)
+    }
+}
+
+/// `|a - n| / max(|a| + |n|, 1e-12)`, so that two components that are both
+/// almost zero do not report a large error.
+pub fn get_relative_error(analytic: f64, numerical: f64) -> f64 {
+    (analytic - numerical).abs() / (analytic.abs() + numerical.abs()).max(1.0e-12)
+}
+
+/// Central differences `(cost(x + epsilon) - cost(x - epsilon)) / 2 epsilon`
+/// of every parameter and of `b`.
+pub fn get_numerical_gradient(
+    cost: impl Fn(&EstimationModel) -> f64,
+    estimation_model: &EstimationModel,
+    epsilon: f64,
+) -> Gradient {
+    let get_difference = |shift: &dyn Fn(&mut EstimationModel, f64)| {
+        let mut plus = estimation_model.clone();
+        shift(&mut plus, epsilon);
+        let mut minus = estimation_model.clone();
+        shift(&mut minus, -epsilon);
+        (cost(&plus) - cost(&minus)) / (2.0 * epsilon)
+    };
+    let mut parameters = estimation_model.parameters.clone();
+    for (index, parameter) in parameters.iter_mut().enumerate() {
+        *parameter = get_difference(&|model, delta| model.parameters[index] += delta);
+    }
+    Gradient {
+        parameters,
+        b: get_difference(&|model, delta| model.b += delta),
+    }
+}
+
+/// Compares any cost function with its hand-derived gradient at
+/// `estimation_model`.
+pub fn check_gradient(
+    cost: impl Fn(&EstimationModel) -> f64,
+    gradient: impl Fn(&EstimationModel) -> Gradient,
+    estimation_model: &EstimationModel,
+    epsilon: f64,
+) -> GradientCheck {
+    let analytic_gradient = gradient(estimation_model);
+    let numerical_gradient = get_numerical_gradient(cost, estimation_model, epsilon);
+    let relative_errors = analytic_gradient
+        .parameters
+        .iter()
+        .zip(numerical_gradient.parameters.iter())
+        .map(|(analytic, numerical)| get_relative_error(*analytic, *numerical))
+        .chain(std::iter::once(get_relative_error(
+            analytic_gradient.b,
+            numerical_gradient.b,
+        )))
+        .collect();
+    GradientCheck {
+        analytic_gradient,
+        numerical_gradient,
+        relative_errors,
+    }
+}
+
+/// Compares the derivative of a function of one value, such as a loss of the
+/// estimate, by checking it as `b` of a model without parameters.
+pub fn check_derivative(
+    function: impl Fn(f64) -> f64,
+    derivative: impl Fn(f64) -> f64,
+    value: f64,
+    epsilon: f64,
+) -> GradientCheck {
+    check_gradient(
+        |model| function(model.b),
+        |model| Gradient {
+            parameters: RowDVector::zeros(0),
+            b: derivative(model.b),
+        },
+        &EstimationModel {
+            parameters: RowDVector::zeros(0),
+            b: value,
+        },
+        epsilon,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::autodiff::{Tape, Tensor, Var};
+    use crate::training::{
+        get_cost, get_cost_of_rows_1, get_gradient, get_regularized_cost, get_regularized_gradient,
+        get_serial_gradient,
+    };
+    use nalgebra::{DMatrix, MatrixXx1};
+
+    const TOLERANCE: f64 = 1.0e-6;
+
+    fn get_training_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let features_set = DMatrix::from_fn(40, 3, |row, column| {
+            ((row * 7 + column * 5) % 11) as f64 / 5.0 - 1.0
+        });
+        let target_set = MatrixXx1::from_fn(40, |row, _| ((row * 3) % 13) as f64 - 4.0);
+        (features_set, target_set)
+    }
+
+    fn get_estimation_models() -> Vec<EstimationModel> {
+        vec![
+            EstimationModel {
+                parameters: RowDVector::zeros(3),
+                b: 0.0,
+            },
+            EstimationModel {
+                parameters: RowDVector::from_vec(vec![0.8, -1.3, 0.4]),
+                b: -0.6,
+            },
+        ]
+    }
+
+    #[test]
+    fn linear_gradient_matches_central_differences() {
+        let (features_set, target_set) = get_training_set();
+        for estimation_model in get_estimation_models() {
+            let gradient_check = check_gradient(
+                |model| get_cost_of_rows_1(&features_set, &target_set, model),
+                |model| get_serial_gradient(&features_set, &target_set, model),
+                &estimation_model,
+                EPSILON,
+            );
+            assert!(
+                gradient_check.get_max_relative_error() < TOLERANCE,
+                "{:?}",
+                gradient_check
+            );
+        }
+    }
+
+    /// Covers the `parallel` implementation when the feature is enabled.
+    #[test]
+    fn dispatched_gradient_matches_central_differences() {
+        let (features_set, target_set) = get_training_set();
+        for estimation_model in get_estimation_models() {
+            let gradient_check = check_gradient(
+                |model| get_cost(&features_set, &target_set, model),
+                |model| get_gradient(&features_set, &target_set, model),
+                &estimation_model,
+                EPSILON,
+            );
+            assert!(
+                gradient_check.get_max_relative_error() < TOLERANCE,
+                "{:?}",
+                gradient_check
+            );
+        }
+    }
+
+    #[test]
+    fn regularized_gradient_matches_central_differences() {
+        let (features_set, target_set) = get_training_set();
+        for estimation_model in get_estimation_models() {
+            let gradient_check = check_gradient(
+                |model| get_regularized_cost(&features_set, &target_set, model, 0.3),
+                |model| get_regularized_gradient(&features_set, &target_set, model, 0.3),
+                &estimation_model,
+                EPSILON,
+            );
+            assert!(
+                gradient_check.get_max_relative_error() < TOLERANCE,
+                "{:?}",
+                gradient_check
+            );
+        }
+    }
+
+    /// Checks the gradient of every component of `value` as a parameter of
+    /// an `EstimationModel` whose `b` is unused.
+    fn assert_autodiff_gradient(
+        build: impl for<'t> Fn(&'t Tape, Var<'t>) -> Var<'t>,
+        value: Tensor,
+    ) {
+        let (nrows, ncols) = value.shape();
+        let get_tensor = |model: &EstimationModel| {
+            Tensor::from_row_slice(nrows, ncols, model.parameters.as_slice())
+        };
+        let gradient_check = check_gradient(
+            |model| {
+                let tape = Tape::new();
+                let input = tape.var(get_tensor(model));
+                build(&tape, input).get_value()[(0, 0)]
+            },
+            |model| {
+                let tape = Tape::new();
+                let input = tape.var(get_tensor(model));
+                let gradients = tape.get_gradients(build(&tape, input));
+                Gradient {
+                    parameters: RowDVector::from_iterator(
+                        nrows * ncols,
+                        gradients.get(input).transpose().iter().cloned(),
+                    ),
+                    b: 0.0,
+                }
+            },
+            &EstimationModel {
+                parameters: RowDVector::from_iterator(
+                    nrows * ncols,
+                    value.transpose().iter().cloned(),
+                ),
+                b: 0.0,
+            },
+            EPSILON,
+        );
+        assert!(
+            gradient_check.get_max_relative_error() < 1.0e-6,
+            "{:?}",
+            gradient_check
+        );
+    }
+
+    fn get_autodiff_input() -> Tensor {
+        Tensor::from_row_slice(2, 3, &[0.5, -1.2, 2.0, 0.3, 1.1, -0.7])
+    }
+
+    #[test]
+    fn autodiff_element_wise_gradients_match_central_differences() {
+        assert_autodiff_gradient(|_, x| x.sigmoid().sum(), get_autodiff_input());
+        assert_autodiff_gradient(|_, x| x.exp().mean(), get_autodiff_input());
+        assert_autodiff_gradient(
+            |_, x| x.exp().ln().component_mul(x).sum(),
+            get_autodiff_input(),
+        );
+        assert_autodiff_gradient(
+            |_, x| (-x).scale(3.0).sigmoid().mean(),
+            get_autodiff_input(),
+        );
+    }
+
+    #[test]
+    fn autodiff_broadcast_gradients_match_central_differences() {
+        assert_autodiff_gradient(
+            |tape, x| (x - tape.scalar(0.4)).component_mul(x).sum(),
+            get_autodiff_input(),
+        );
+        assert_autodiff_gradient(
+            |tape, x| (tape.var(get_autodiff_input()) + x.sum()).exp().mean(),
+            Tensor::from_element(1, 1, 0.2),
+        );
+    }
+
+    #[test]
+    fn autodiff_matmul_gradients_match_central_differences() {
+        let weights = Tensor::from_row_slice(3, 1, &[0.2, -0.5, 1.5]);
+        assert_autodiff_gradient(
+            |tape, x| x.matmul(tape.var(weights.clone())).sigmoid().sum(),
+            get_autodiff_input(),
+        );
+        assert_autodiff_gradient(
+            |tape, w| tape.var(get_autodiff_input()).matmul(w).exp().mean(),
+            weights.clone(),
+        );
+    }
+
+    #[test]
+    fn wrong_gradient_is_detected() {
+        let (features_set, target_set) = get_training_set();
+        let gradient_check = check_gradient(
+            |model| get_regularized_cost(&features_set, &target_set, model, 0.3),
+            // Forgets the penalty.
+            |model| get_gradient(&features_set, &target_set, model),
+            &get_estimation_models()[1],
+            EPSILON,
+        );
+        assert!(gradient_check.get_max_relative_error() > 0.01);
+    }
+}
@@ -0,0 +1,288 @@
+use nalgebra::{DMatrix, RowDVector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How missing (NaN) feature values are filled.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ImputerKind {
+    Mean,
+    Median,
+    MostFrequent,
+    Constant(f64),
+    /// Mean of the column over the `neighbor_count` nearest training rows,
+    /// measured on the columns both rows have. Each column is divided by its
+    /// standard deviation first so that large-valued columns don't dominate
+    /// the distance.
+    KNearestNeighbors {
+        neighbor_count: usize,
+    },
+}
+
+/// Fills missing feature values with statistics learned on the training set.
+/// With `add_indicator`, one "was missing" column is appended for every
+/// column that had missing values during fitting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Imputer {
+    pub kind: ImputerKind,
+    pub fill_values: RowDVector<f64>,
+    pub indicator_columns: Vec<usize>,
+    /// Training rows without missing values, used by `KNearestNeighbors`.
+    pub reference_set: DMatrix<f64>,
+    /// Standard deviation of each column of `reference_set`, or 1 for a
+    /// constant column.
+    pub reference_std_deviation: RowDVector<f64>,
+}
+
+fn get_mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn get_median(values: &[f64]) -> f64 {
+    let mut sorted_values = values.to_vec();
+    sorted_values.sort_by(|a, b| a.total_cmp(b));
+    let middle = sorted_values.len() / 2;
+    if sorted_values.len() % 2 == 1 {
+        sorted_values[middle]
+    } else {
+        (sorted_values[middle - 1] + sorted_values[middle]) / 2.0
+    }
+}
+
+/// Ties are broken towards the smallest value.
+fn get_most_frequent(values: &[f64]) -> f64 {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value.to_bits()).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(bits, count)| (f64::from_bits(bits), count))
+        .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then(b.total_cmp(a)))
+        .map(|(value, _)| value)
+        .unwrap()
+}
+
+impl Imputer {
+    /// Columns that are missing in every training row are filled with 0.
+    pub fn fit(features_set: &DMatrix<f64>, kind: ImputerKind, add_indicator: bool) -> Imputer {
+        let fill_values = RowDVector::from_vec(
+            features_set
+                .column_iter()
+                .map(|column| {
+                    let values = column
+                        .iter()
+                        .cloned()
+                        .filter(|feature| !feature.is_nan())
+                        .collect::<Vec<f64>>();
+                    if values.is_empty() {
+                        return 0.0;
+                    }
+                    match kind {
+                        ImputerKind::Mean | ImputerKind::KNearestNeighbors { .. } => {
+                            get_mean(&values)
+                        }
+                        ImputerKind::Median => get_median(&values),
+                        ImputerKind::MostFrequent => get_most_frequent(&values),
+                        ImputerKind::Constant(value) => value,
+                    }
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        let indicator_columns = if add_indicator {
+            features_set
+                .column_iter()
+                .enumerate()
+                .filter(|(_, column)| column.iter().any(|feature| feature.is_nan()))
+                .map(|(index, _)| index)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let reference_set = match kind {
+            ImputerKind::KNearestNeighbors { .. } => {
+                let complete_rows = features_set
+                    .row_iter()
+                    .filter(|features| !features.iter().any(|feature| feature.is_nan()))
+                    .collect::<Vec<_>>();
+                if complete_rows.is_empty() {
+                    DMatrix::zeros(0, features_set.ncols())
+                } else {
+                    DMatrix::from_rows(&complete_rows)
+                }
+            }
+            _ => DMatrix::zeros(0, features_set.ncols()),
+        };
+        let reference_std_deviation = RowDVector::from_iterator(
+            reference_set.ncols(),
+            reference_set.column_iter().map(|column| {
+                let std_deviation = column.variance().sqrt();
+                if std_deviation > 0.0 {
+                    std_deviation
+                } else {
+                    1.0
+                }
+            }),
+        );
+
+        Imputer {
+            kind,
+            fill_values,
+            indicator_columns,
+            reference_set,
+            reference_std_deviation,
+        }
+    }
+
+    fn get_neighbor_mean(&self, features: &RowDVector<f64>, column: usize) -> f64 {
+        let neighbor_count = match self.kind {
+            ImputerKind::KNearestNeighbors { neighbor_count } => neighbor_count,
+            _ => return self.fill_values[column],
+        };
+        if self.reference_set.nrows() == 0 || neighbor_count == 0 {
+            return self.fill_values[column];
+        }
+
+        let mut distances = self
+            .reference_set
+            .row_iter()
+            .enumerate()
+            .map(|(index, reference)| {
+                let distance = features
+                    .iter()
+                    .zip(reference.iter())
+                    .zip(self.reference_std_deviation.iter())
+                    .filter(|((feature, _), _)| !feature.is_nan())
+                    .map(|((feature, reference_feature), std_deviation)| {
+                        ((feature - reference_feature) / std_deviation).powi(2)
+                    })
+                    .sum::<f64>();
+                (distance, index)
+            })
+            .collect::<Vec<_>>();
+        distances.sort_by(|(a, a_index), (b, b_index)| a.total_cmp(b).then(a_index.cmp(b_index)));
+        let neighbors = &distances[..neighbor_count.min(distances.len())];
+        neighbors
+            .iter()
+            .map(|(_, index)| self.reference_set[(*index, column)])
+            .sum::<f64>()
+            / neighbors.len() as f64
+    }
+
+    pub fn transform_features(&self, features: &RowDVector<f64>) -> RowDVector<f64> {
+        let mut imputed_features = features.clone();
+        for (column, feature) in imputed_features.iter_mut().enumerate() {
+            if feature.is_nan() {
+                *feature = self.get_neighbor_mean(features, column);
+            }
+        }
+        let indicators =
+            self.indicator_columns
+                .iter()
+                .map(|column| if features[*column].is_nan() { 1.0 } else { 0.0 });
+        RowDVector::from_iterator(
+            features.len() + self.indicator_columns.len(),
+            imputed_features.iter().cloned().chain(indicators),
+        )
+    }
+
+    pub fn transform(&self, features_set: &DMatrix<f64>) -> DMatrix<f64> {
+        let rows = features_set
+            .row_iter()
+            .map(|features| self.transform_features(&RowDVector::from(features)))
+            .collect::<Vec<_>>();
+        if rows.is_empty() {
+            return DMatrix::zeros(0, features_set.ncols() + self.indicator_columns.len());
+        }
+        DMatrix::from_rows(&rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_features_set() -> DMatrix<f64> {
+        DMatrix::from_row_slice(
+            5,
+            2,
+            &[
+                [1.0, 10.0],
+                [2.0, f64::NAN],
+                [2.0, 30.0],
+                [7.0, 30.0],
+                [f64::NAN, 50.0],
+            ]
+            .concat(),
+        )
+    }
+
+    #[test]
+    fn fills_missing_values_with_each_statistic() {
+        let features_set = get_features_set();
+        for (kind, fill_values) in [
+            (ImputerKind::Mean, [3.0, 30.0]),
+            (ImputerKind::Median, [2.0, 30.0]),
+            (ImputerKind::MostFrequent, [2.0, 30.0]),
+            (ImputerKind::Constant(-1.0), [-1.0, -1.0]),
+        ] {
+            let imputer = Imputer::fit(&features_set, kind, false);
+            assert_eq!(imputer.fill_values.as_slice(), fill_values, "{:?}", kind);
+            let imputed_features_set = imputer.transform(&features_set);
+            assert_eq!(imputed_features_set[(1, 1)], fill_values[1]);
+            assert_eq!(imputed_features_set[(4, 0)], fill_values[0]);
+            assert_eq!(imputed_features_set[(3, 0)], 7.0);
+        }
+    }
+
+    #[test]
+    fn most_frequent_breaks_ties_towards_the_smallest_value() {
+        assert_eq!(get_most_frequent(&[3.0, 1.0, 3.0, 1.0, 2.0]), 1.0);
+    }
+
+    #[test]
+    fn appends_indicators_and_fills_empty_columns_with_zero() {
+        let features_set = DMatrix::from_row_slice(2, 3, &[1.0, f64::NAN, 5.0, 3.0, f64::NAN, 5.0]);
+        let imputer = Imputer::fit(&features_set, ImputerKind::Mean, true);
+        assert_eq!(imputer.indicator_columns, vec![1]);
+        let imputed_features_set = imputer.transform(&features_set);
+        assert_eq!(
+            imputed_features_set,
+            DMatrix::from_row_slice(2, 4, &[1.0, 0.0, 5.0, 1.0, 3.0, 0.0, 5.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn nearest_neighbors_are_measured_on_standardized_columns() {
+        // Column 0 is in the thousands and column 1 in the units. Unscaled,
+        // row 4 would be nearest to row 0 by column 0 alone; standardized,
+        // row 1 is nearer because it matches column 1.
+        let features_set = DMatrix::from_row_slice(
+            5,
+            3,
+            &[
+                [1000.0, 0.0, 1.0],
+                [1100.0, 5.0, 2.0],
+                [2000.0, 0.1, 3.0],
+                [2100.0, 5.1, 4.0],
+                [1010.0, 5.0, f64::NAN],
+            ]
+            .concat(),
+        );
+        let imputer = Imputer::fit(
+            &features_set,
+            ImputerKind::KNearestNeighbors { neighbor_count: 1 },
+            false,
+        );
+        assert_eq!(imputer.reference_set.nrows(), 4);
+        assert_eq!(imputer.transform(&features_set)[(4, 2)], 2.0);
+
+        let imputer = Imputer::fit(
+            &features_set,
+            ImputerKind::KNearestNeighbors { neighbor_count: 2 },
+            false,
+        );
+        assert_eq!(imputer.transform(&features_set)[(4, 2)], 1.5);
+    }
+}
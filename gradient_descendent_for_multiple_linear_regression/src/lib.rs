@@ -0,0 +1,26 @@
+pub mod autodiff;
+pub mod callback;
+pub mod checkpoint;
+pub mod cluster;
+pub mod data_loader;
+pub mod decomposition;
+pub mod encoding;
+pub mod forest;
+pub mod gbdt;
+pub mod glm;
+pub mod gradient_check;
+pub mod imputation;
+pub mod loss;
+pub mod model;
+pub mod neighbors;
+pub mod outlier;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+pub mod plot;
+pub mod report;
+pub mod search;
+#[cfg(feature = "sparse")]
+pub mod sparse;
+pub mod target_transform;
+pub mod training;
+pub mod tree;
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+
+/// Per-row loss of an estimate. Estimates are on the target scale except for
+/// `LogLoss`, whose estimates are log-odds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Loss {
+    /// `(estimate - target)² / 2`, the cost the linear models minimize.
+    SquaredError,
+    /// Binary cross-entropy. Targets are 0 or 1.
+    LogLoss,
+    /// Squared within `delta` of the target, linear beyond it.
+    Huber { delta: f64 },
+    /// Pinball loss; the minimizer is the `alpha` quantile of the targets.
+    Quantile { alpha: f64 },
+}
+
+/// The smallest value with at least a `quantile` fraction of the values at
+/// or below it, which minimizes the pinball loss.
+fn get_quantile(values: &[f64], quantile: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = ((quantile * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+fn sigmoid(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+impl Loss {
+    pub fn get_loss(self, estimate: f64, target: f64) -> f64 {
+        let residual = estimate - target;
+        match self {
+            Loss::SquaredError => residual.powi(2) / 2.0,
+            // ln(1 + e^x) - y x without overflow.
+            Loss::LogLoss => {
+                estimate.max(0.0) + (-estimate.abs()).exp().ln_1p() - target * estimate
+            }
+            Loss::Huber { delta } => {
+                if residual.abs() <= delta {
+                    residual.powi(2) / 2.0
+                } else {
+                    delta * (residual.abs() - delta / 2.0)
+                }
+            }
+            Loss::Quantile { alpha } => {
+                if target >= estimate {
+                    alpha * (target - estimate)
+                } else {
+                    (1.0 - alpha) * (estimate - target)
+                }
+            }
+        }
+    }
+
+    /// Derivative of `get_loss` with respect to the estimate.
+    pub fn get_gradient(self, estimate: f64, target: f64) -> f64 {
+        let residual = estimate - target;
+        match self {
+            Loss::SquaredError => residual,
+            Loss::LogLoss => sigmoid(estimate) - target,
+            Loss::Huber { delta } => residual.clamp(-delta, delta),
+            Loss::Quantile { alpha } => {
+                if target >= estimate {
+                    -alpha
+                } else {
+                    1.0 - alpha
+                }
+            }
+        }
+    }
+
+    /// Second derivative of `get_loss`. Taken as 1 for `Huber` and
+    /// `Quantile`, whose true second derivative is 0 almost everywhere, so
+    /// that Newton steps become plain gradient steps.
+    pub fn get_hessian(self, estimate: f64, _target: f64) -> f64 {
+        match self {
+            Loss::LogLoss => {
+                let probability = sigmoid(estimate);
+                probability * (1.0 - probability)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// A constant starting estimate: the one with the lowest total loss, or
+    /// the median for `Huber`.
+    pub fn get_initial_estimate(self, targets: &[f64]) -> f64 {
+        match self {
+            Loss::SquaredError => targets.iter().sum::<f64>() / targets.len() as f64,
+            Loss::LogLoss => {
+                let probability = (targets.iter().sum::<f64>() / targets.len() as f64)
+                    .clamp(1.0e-12, 1.0 - 1.0e-12);
+                (probability / (1.0 - probability)).ln()
+            }
+            Loss::Huber { .. } => get_quantile(targets, 0.5),
+            Loss::Quantile { alpha } => get_quantile(targets, alpha),
+        }
+    }
+
+    /// Maps an estimate to a prediction: the probability of 1 for `LogLoss`,
+    /// the estimate itself otherwise.
+    pub fn get_prediction(self, estimate: f64) -> f64 {
+        match self {
+            Loss::LogLoss => sigmoid(estimate),
+            _ => estimate,
+        }
+    }
+
+    /// Mean loss over the rows.
+    pub fn get_cost(self, estimates: &[f64], targets: &[f64]) -> f64 {
+        estimates
+            .iter()
+            .zip(targets)
+            .map(|(estimate, target)| self.get_loss(*estimate, *target))
+            .sum::<f64>()
+            / targets.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradient_check::{check_derivative, EPSILON};
+
+    const LOSSES: [Loss; 4] = [
+        Loss::SquaredError,
+        Loss::LogLoss,
+        Loss::Huber { delta: 1.0 },
+        Loss::Quantile { alpha: 0.9 },
+    ];
+
+    #[test]
+    fn gradients_match_central_differences() {
+        for loss in LOSSES {
+            for (estimate, target) in [(0.3, 1.0), (-2.0, 0.0), (2.5, 0.4), (-0.7, 1.0)] {
+                let gradient_check = check_derivative(
+                    |estimate| loss.get_loss(estimate, target),
+                    |estimate| loss.get_gradient(estimate, target),
+                    estimate,
+                    EPSILON,
+                );
+                assert!(
+                    gradient_check.get_max_relative_error() < 1.0e-6,
+                    "{:?} {:?}",
+                    loss,
+                    gradient_check
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn initial_estimate_minimizes_cost() {
+        let targets = [0.0, 1.0, 1.0, 0.0, 1.0, 4.0, 1.0];
+        let binary_targets = [0.0, 1.0, 1.0, 0.0, 1.0, 1.0, 1.0];
+        for loss in [LOSSES[0], LOSSES[1], LOSSES[3]] {
+            let targets = if loss == Loss::LogLoss {
+                &binary_targets
+            } else {
+                &targets
+            };
+            let initial = loss.get_initial_estimate(targets);
+            let cost = loss.get_cost(&[initial; 7], targets);
+            for shift in [-0.1, 0.1] {
+                assert!(cost <= loss.get_cost(&[initial + shift; 7], targets) + 1.0e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn log_loss_is_stable_for_large_estimates() {
+        assert_eq!(Loss::LogLoss.get_loss(1000.0, 1.0), 0.0);
+        assert_eq!(Loss::LogLoss.get_loss(-1000.0, 1.0), 1000.0);
+    }
+}
@@ -1,22 +1,61 @@
-mod data_loader;
-mod model;
-mod plot;
-mod training;
-use crate::data_loader::load_training_set;
-use crate::model::{EstimationModel, SeoulRealEstateTransactionPrice};
-use crate::plot::draw_costs_plot;
-use crate::training::{
-    estimate, get_row_mean, get_row_std_deviation, gradient_descent, zscore_normalize_features,
-    zscore_normalize_features_set,
-};
 use chrono::NaiveDate;
-use nalgebra::RowDVector;
+use ml_example::callback::{EarlyStopping, ProgressPrinter};
+use ml_example::cluster::{get_elbow_scores, KMeans, KMeansAlgorithm, KMeansParameters};
+use ml_example::data_loader::{fit_categorical_training_set, load_records};
+use ml_example::decomposition::{ComponentSelection, Pca};
+use ml_example::encoding::EncoderKind;
+use ml_example::forest::{ForestParameters, RandomForest};
+use ml_example::gbdt::{BoostingParameters, GradientBoostedTrees};
+use ml_example::glm::{
+    get_d2_score, get_mean_deviance, Distribution, GeneralizedLinearModel, GlmParameters,
+    GlmSolver, Link,
+};
+use ml_example::imputation::{Imputer, ImputerKind};
+use ml_example::loss::Loss;
+use ml_example::model::{
+    CategoricalColumn, EstimationModel, ModelArtifact, SeoulRealEstateTransactionPrice,
+};
+use ml_example::neighbors::{
+    DistanceMetric, IndexKind, KNearestNeighbors, NeighborWeighting, NeighborsParameters,
+};
+use ml_example::plot::{
+    draw_costs_plot, draw_costs_plot_on, draw_elbow_plot, draw_explained_variance_plot,
+    draw_feature_importance, draw_prediction_plot, draw_prediction_plot_on,
+    draw_residual_histogram, draw_residual_plot, draw_search_costs_plot, render_svg, CostScale,
+    PlotOutput,
+};
+use ml_example::report::TrainingReport;
+use ml_example::search::{search, SearchSpace, SearchStrategy};
+use ml_example::target_transform::{TargetTransformKind, TargetTransformer};
+use ml_example::training::{
+    get_estimates, get_row_mean, get_row_std_deviation, train, train_with_callbacks,
+    zscore_normalize_features_set, Hyperparameters,
+};
+use ml_example::tree::{DecisionTree, SplitCriterion, TreeParameters};
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let (features_set, target_set) = load_training_set("seoul_real_estate_transaction_price.csv")?;
+    let records = load_records("seoul_real_estate_transaction_price.csv")?;
+    let (features_set, target_set, column_encoders) = fit_categorical_training_set(
+        &records,
+        &[(
+            CategoricalColumn::자치구명,
+            EncoderKind::Target {
+                smoothing: 10.0,
+                fold_count: 5,
+            },
+        )],
+        1,
+    )?;
+    let feature_names = ["건물면적", "토지면적", "자치구명"];
+    let imputer = Imputer::fit(&features_set, ImputerKind::Median, false);
+    let features_set = imputer.transform(&features_set);
     let row_mean = get_row_mean(&features_set);
     let row_std_deviation = get_row_std_deviation(&features_set, &row_mean);
     let features_set = zscore_normalize_features_set(&features_set, &row_mean, &row_std_deviation);
+    let price_set = target_set.clone();
+    let mut target_transformer = TargetTransformer::fit(&target_set, TargetTransformKind::Log1p)?;
+    let target_set = target_transformer.transform_set(&target_set);
 
     let learning_rate = 1.0e-1;
     let iteration_count = 100;
@@ -25,56 +64,448 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         b: 0.0,
     };
 
-    let (result, costs) = gradient_descent(
+    let (result, costs) = train_with_callbacks(
         &features_set,
         &target_set,
         &initial,
+        &Hyperparameters {
+            learning_rate,
+            iteration_count,
+            regularization_strength: 0.0,
+            batch_size: None,
+            shuffle_seed: None,
+        },
+        &mut [
+            &mut ProgressPrinter { every: 20 },
+            &mut EarlyStopping::new(5, 1.0e-6),
+        ],
+    )?;
+
+    draw_costs_plot(
+        &PlotOutput::new("plot/training.png", (640, 480)),
         learning_rate,
-        iteration_count,
+        &costs,
+        CostScale::Log,
+    )?;
+
+    let search_result = search(
+        &features_set,
+        &target_set,
+        &SearchSpace {
+            learning_rates: vec![1.0e-2, 3.0e-2, 1.0e-1],
+            iteration_counts: vec![iteration_count],
+            regularization_strengths: vec![0.0, 1.0e-2],
+            batch_sizes: vec![None, Some(256)],
+        },
+        SearchStrategy::SuccessiveHalving {
+            candidate_count: 8,
+            seed: 0,
+        },
+        3,
+    )?;
+    print!("{}", search_result.get_results_table());
+    draw_search_costs_plot(
+        &PlotOutput::new("plot/search_costs.png", (1024, 768)),
+        &search_result.trials,
+        CostScale::Log,
+    )?;
+
+    let tree = DecisionTree::fit_regressor(
+        &features_set,
+        &target_set,
+        &TreeParameters {
+            criterion: SplitCriterion::Mse,
+            max_depth: Some(4),
+            min_samples_leaf: 20,
+            max_features: None,
+            ccp_alpha: 1.0e-4,
+        },
+    )?;
+    print!("{}", tree.to_text(&feature_names));
+    std::fs::write("plot/tree.dot", tree.to_dot(&feature_names))?;
+
+    let estimates = MatrixXx1::from_vec(get_estimates(&features_set, &result));
+    let forest = RandomForest::fit_regressor(
+        &features_set,
+        &target_set,
+        &ForestParameters {
+            tree_count: 100,
+            tree_parameters: TreeParameters {
+                criterion: SplitCriterion::Mse,
+                max_depth: None,
+                min_samples_leaf: 5,
+                max_features: None,
+                ccp_alpha: 0.0,
+            },
+            seed: 0,
+        },
+    )?;
+    let neighbors = KNearestNeighbors::fit_regressor(
+        &features_set,
+        &target_set,
+        &NeighborsParameters {
+            k: 5.min(target_set.len()),
+            metric: DistanceMetric::Euclidean,
+            weighting: NeighborWeighting::Distance,
+            index: IndexKind::Auto,
+            leaf_size: 32,
+        },
+    )?;
+    println!(
+        "comparable sales of row 0: {:?}",
+        neighbors
+            .get_neighbors(&features_set.row(0).into_owned())
+            .iter()
+            .map(|neighbor| neighbor.index)
+            .collect::<Vec<_>>()
     );
 
-    draw_costs_plot("plot/training.png", learning_rate, &costs)?;
+    let k_means_parameters = KMeansParameters {
+        cluster_count: 4.min(target_set.len()),
+        algorithm: KMeansAlgorithm::MiniBatch { batch_size: 1024 },
+        restart_count: 3,
+        max_iteration_count: 100,
+        tolerance: 1.0e-6,
+        seed: 0,
+    };
+    let elbow_scores = get_elbow_scores(
+        &features_set,
+        &(1..=8.min(target_set.len())).collect::<Vec<_>>(),
+        &k_means_parameters,
+    )?;
+    for elbow_score in &elbow_scores {
+        println!(
+            "k-means with {} clusters inertia: {} silhouette: {}",
+            elbow_score.cluster_count, elbow_score.inertia, elbow_score.silhouette_score
+        );
+    }
+    draw_elbow_plot(
+        &PlotOutput::new("plot/elbow.png", (640, 480)),
+        &elbow_scores
+            .iter()
+            .map(|score| score.cluster_count)
+            .collect::<Vec<_>>(),
+        &elbow_scores
+            .iter()
+            .map(|score| score.inertia)
+            .collect::<Vec<_>>(),
+    )?;
+    let k_means = KMeans::fit(&features_set, &k_means_parameters)?;
+    let centroid_distances = k_means.transform(&features_set);
+    let clustered_features_set = DMatrix::from_fn(
+        features_set.nrows(),
+        features_set.ncols() + centroid_distances.ncols(),
+        |row, column| {
+            if column < features_set.ncols() {
+                features_set[(row, column)]
+            } else {
+                centroid_distances[(row, column - features_set.ncols())]
+            }
+        },
+    );
+    // The distances are on their own scale, which diverges at this learning
+    // rate unless they are normalized like the other features.
+    let clustered_row_mean = get_row_mean(&clustered_features_set);
+    let clustered_features_set = zscore_normalize_features_set(
+        &clustered_features_set,
+        &clustered_row_mean,
+        &get_row_std_deviation(&clustered_features_set, &clustered_row_mean),
+    );
+    let (_, clustered_costs) = train(
+        &clustered_features_set,
+        &target_set,
+        &EstimationModel {
+            parameters: RowDVector::zeros(clustered_features_set.ncols()),
+            b: 0.0,
+        },
+        &Hyperparameters {
+            learning_rate,
+            iteration_count,
+            regularization_strength: 0.0,
+            batch_size: None,
+            shuffle_seed: None,
+        },
+    );
+    println!(
+        "final cost with centroid distance features: {}",
+        clustered_costs[clustered_costs.len() - 1]
+    );
+
+    let pca = Pca::fit(
+        &features_set,
+        ComponentSelection::ExplainedVarianceRatio(1.0),
+        true,
+    )?;
+    println!(
+        "PCA explained variance ratios: {}",
+        pca.explained_variance_ratios
+    );
+    draw_explained_variance_plot(
+        &PlotOutput::new("plot/explained_variance.png", (640, 480)),
+        pca.explained_variance_ratios.as_slice(),
+    )?;
+    let whitened_features_set = pca.transform(&features_set);
+    let (whitened_result, whitened_costs) = train(
+        &whitened_features_set,
+        &target_set,
+        &EstimationModel {
+            parameters: RowDVector::zeros(whitened_features_set.ncols()),
+            b: 0.0,
+        },
+        &Hyperparameters {
+            learning_rate,
+            iteration_count,
+            regularization_strength: 0.0,
+            batch_size: None,
+            shuffle_seed: None,
+        },
+    );
+    println!(
+        "final cost on whitened principal components: {}",
+        whitened_costs[whitened_costs.len() - 1]
+    );
+
+    // Prices are positive and right-skewed, which a Gamma GLM with a log link
+    // fits without transforming the target. The GLMs are fitted to prices
+    // divided by their mean, which keeps the gradients small; with the log
+    // link, adding the log of the mean to `b` undoes the scaling.
+    let price_scale = price_set.mean();
+    let scaled_price_set = &price_set / price_scale;
+    for (distribution, solver) in [
+        (
+            Distribution::Gamma,
+            GlmSolver::Irls {
+                max_iteration_count: 100,
+                tolerance: 1.0e-8,
+            },
+        ),
+        (
+            Distribution::Tweedie { power: 1.5 },
+            GlmSolver::GradientDescent {
+                learning_rate: 1.0e-1,
+                iteration_count: 1000,
+            },
+        ),
+    ] {
+        let (mut glm, _) = GeneralizedLinearModel::fit(
+            &features_set,
+            &scaled_price_set,
+            &GlmParameters {
+                distribution,
+                link: Link::Log,
+                solver,
+                regularization_strength: 0.0,
+            },
+        )?;
+        glm.estimation_model.b += price_scale.ln();
+        let predictions = glm.predict(&features_set);
+        println!(
+            "{:?} GLM mean deviance: {} D²: {}",
+            distribution,
+            get_mean_deviance(distribution, &price_set, &predictions),
+            get_d2_score(distribution, &price_set, &predictions)
+        );
+    }
+
+    let (validation_rows, training_rows): (Vec<usize>, Vec<usize>) =
+        (0..target_set.len()).partition(|row| row % 5 == 0);
+    // The artifact applies the PCA before predicting, so the boosted trees
+    // are fitted to the whitened components.
+    let (boosted_trees, boosting_history) = GradientBoostedTrees::fit(
+        &whitened_features_set.select_rows(&training_rows),
+        &target_set.select_rows(&training_rows),
+        Some((
+            &whitened_features_set.select_rows(&validation_rows),
+            &target_set.select_rows(&validation_rows),
+        )),
+        &BoostingParameters {
+            loss: Loss::Huber { delta: 1.0 },
+            tree_count: 500,
+            learning_rate: 0.1,
+            max_depth: 4,
+            min_samples_leaf: 20,
+            l2_regularization: 1.0,
+            bin_count: 255,
+            row_subsample: 0.8,
+            column_subsample: 1.0,
+            early_stopping_rounds: Some(20),
+            seed: 0,
+        },
+    )?;
+    // The artifact predicts with the boosted trees, so the smearing factor
+    // comes from their residuals on the rows they were fitted to. Smearing
+    // assumes the model estimates the mean in log space while the Huber loss
+    // pulls it toward the median, so the correction is only approximate.
+    target_transformer.fit_smearing(
+        &target_set.select_rows(&training_rows),
+        &boosted_trees.predict(&whitened_features_set.select_rows(&training_rows)),
+    );
+    println!(
+        "gradient boosting kept {} trees, validation cost: {:?}",
+        boosting_history.best_tree_count,
+        boosting_history
+            .validation_costs
+            .get(boosting_history.best_tree_count.saturating_sub(1))
+    );
+    println!(
+        "linear model MSE: {} random forest out-of-bag MSE: {:?}",
+        (&estimates - &target_set).norm_squared() / target_set.len() as f64,
+        forest.oob_error
+    );
+    draw_prediction_plot(
+        &PlotOutput::new("plot/prediction.png", (640, 640)),
+        estimates.as_slice(),
+        &target_set,
+    )?;
+    draw_residual_plot(
+        &PlotOutput::new("plot/residuals.png", (640, 480)),
+        estimates.as_slice(),
+        &target_set,
+    )?;
+    draw_residual_histogram(
+        &PlotOutput::new("plot/residual_histogram.png", (640, 480)),
+        estimates.as_slice(),
+        &target_set,
+        50,
+    )?;
+    draw_feature_importance(
+        &PlotOutput::new("plot/feature_importance.png", (640, 480)),
+        &feature_names,
+        result.parameters.map(f64::abs).as_slice(),
+    )?;
+
+    #[cfg(feature = "sparse")]
+    {
+        use ml_example::encoding::CategoricalEncoder;
+
+        // The normalized features of the dense model with its target-encoded
+        // 자치구명 column swapped for sparse one-hot columns.
+        let numeric_column_count = features_set.ncols()
+            - column_encoders
+                .iter()
+                .map(|column_encoder| column_encoder.encoder.get_column_count())
+                .sum::<usize>();
+        let districts = records
+            .iter()
+            .map(|record| record.get_category(CategoricalColumn::자치구명))
+            .collect::<Vec<_>>();
+        let (_, one_hot_rows) = CategoricalEncoder::fit_transform(
+            EncoderKind::OneHot,
+            &districts,
+            price_set.as_slice(),
+            1,
+        )?;
+        let one_hot_column_count = one_hot_rows.first().map_or(0, |row| row.len());
+        let district_features_set = DMatrix::from_fn(
+            features_set.nrows(),
+            numeric_column_count + one_hot_column_count,
+            |row, column| {
+                if column < numeric_column_count {
+                    features_set[(row, column)]
+                } else {
+                    one_hot_rows[row][column - numeric_column_count]
+                }
+            },
+        );
+        let sparse_features_set =
+            ml_example::sparse::to_sparse_features_set(&district_features_set);
+        let (_, district_costs) = train(
+            &sparse_features_set,
+            &target_set,
+            &EstimationModel {
+                parameters: RowDVector::zeros(sparse_features_set.ncols()),
+                b: 0.0,
+            },
+            &Hyperparameters {
+                learning_rate,
+                iteration_count,
+                regularization_strength: 0.0,
+                batch_size: None,
+                shuffle_seed: None,
+            },
+        );
+        println!(
+            "sparse one-hot 자치구명 model: {} stored entries of {}, final cost: {:?}",
+            sparse_features_set.nnz(),
+            district_features_set.len(),
+            district_costs.last()
+        );
+    }
+
+    let mut report = TrainingReport::new("Seoul real estate transaction price");
+    report.add_hyperparameter("learning rate", learning_rate);
+    report.add_hyperparameter("iteration count", iteration_count);
+    report.add_hyperparameter("target transform", "log1p");
+    report.add_metric("final cost", costs[costs.len() - 1]);
+    report.add_dataset_statistic("row count", target_set.len());
+    report.add_dataset_statistic("feature count", features_set.ncols());
+    report.add_chart(
+        "Training cost",
+        render_svg((640, 480), |root| {
+            draw_costs_plot_on(root, learning_rate, &costs, CostScale::Log)
+        })?,
+    );
+    report.add_chart(
+        "Predicted vs actual",
+        render_svg((480, 480), |root| {
+            draw_prediction_plot_on(root, estimates.as_slice(), &target_set)
+        })?,
+    );
+    report.save("plot/report.html")?;
+    let model_artifact = ModelArtifact {
+        estimation_model: whitened_result,
+        row_mean,
+        row_std_deviation,
+        column_encoders,
+        imputer: Some(imputer),
+        target_transformer: Some(target_transformer),
+        pca: Some(pca),
+        boosted_trees: Some(boosted_trees),
+    };
+    model_artifact.save("plot/model_artifact.json")?;
+    let model_artifact = ModelArtifact::load("plot/model_artifact.json")?;
 
     // Test
     for test_data in vec![
         SeoulRealEstateTransactionPrice {
             계약일: NaiveDate::from_ymd(2015, 6, 2),
             물건금액: None,
-            건물면적: 10.0,
-            토지면적: 20.0,
+            건물면적: Some(10.0),
+            토지면적: Some(20.0),
+            자치구명: "강남구".to_string(),
+            건물용도: "아파트".to_string(),
         },
         SeoulRealEstateTransactionPrice {
             계약일: NaiveDate::from_ymd(2015, 6, 2),
             물건금액: None,
-            건물면적: 39.54,
-            토지면적: 53.33,
+            건물면적: Some(39.54),
+            토지면적: Some(53.33),
+            자치구명: "강남구".to_string(),
+            건물용도: "아파트".to_string(),
         },
         SeoulRealEstateTransactionPrice {
             계약일: NaiveDate::from_ymd(2015, 6, 2),
             물건금액: None,
-            건물면적: 80.0,
-            토지면적: 100.0,
+            건물면적: Some(80.0),
+            토지면적: Some(100.0),
+            자치구명: "강남구".to_string(),
+            건물용도: "아파트".to_string(),
         },
         SeoulRealEstateTransactionPrice {
             계약일: NaiveDate::from_ymd(2015, 6, 2),
             물건금액: None,
-            건물면적: 160.0,
-            토지면적: 200.0,
+            건물면적: Some(160.0),
+            토지면적: Some(200.0),
+            자치구명: "강남구".to_string(),
+            건물용도: "아파트".to_string(),
         },
     ]
     .iter()
     {
         println!(
-            "{:?} 의 가격 예상가: {:?}만원",
+            "{:?} 의 가격 예상가: {:.0}만원",
             test_data,
-            estimate(
-                &zscore_normalize_features(
-                    &RowDVector::from_vec(test_data.into_features()),
-                    &row_mean,
-                    &row_std_deviation
-                ),
-                &result
-            ) as usize
+            model_artifact.predict(test_data)
         );
     }
 
@@ -1,8 +1,15 @@
+use crate::decomposition::Pca;
+use crate::encoding::CategoricalEncoder;
+use crate::gbdt::GradientBoostedTrees;
+use crate::imputation::Imputer;
+use crate::target_transform::TargetTransformer;
+use crate::training::{estimate, zscore_normalize_features};
 use chrono::{Datelike, NaiveDate};
 use nalgebra::RowDVector;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EstimationModel {
     pub parameters: RowDVector<f64>,
     pub b: f64,
@@ -14,26 +21,122 @@ pub struct Gradient {
     pub b: f64,
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct SeoulRealEstateTransactionPrice {
     #[serde(with = "datetime_ymd_format")]
     pub 계약일: NaiveDate,
     #[serde(rename(deserialize = "물건금액(만원)"))]
     pub 물건금액: Option<f64>,
     #[serde(rename(deserialize = "건물면적(㎡)"))]
-    pub 건물면적: f64,
+    pub 건물면적: Option<f64>,
     #[serde(rename(deserialize = "토지면적(㎡)"))]
-    pub 토지면적: f64,
+    pub 토지면적: Option<f64>,
+    #[serde(default)]
+    pub 자치구명: String,
+    #[serde(default)]
+    pub 건물용도: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum CategoricalColumn {
+    자치구명,
+    건물용도,
 }
 
 impl SeoulRealEstateTransactionPrice {
-    pub fn into_features(self) -> Vec<f64> {
-        vec![self.건물면적]
+    /// Missing values are returned as NaN, to be filled by an `Imputer`.
+    pub fn into_features(&self) -> Vec<f64> {
+        vec![
+            self.건물면적.unwrap_or(f64::NAN),
+            self.토지면적.unwrap_or(f64::NAN),
+        ]
     }
 
-    pub fn into_target(self) -> f64 {
+    pub fn has_missing_features(&self) -> bool {
+        self.into_features().iter().any(|feature| feature.is_nan())
+    }
+
+    pub fn into_target(&self) -> f64 {
         self.물건금액.unwrap()
     }
+
+    pub fn get_category(&self, column: CategoricalColumn) -> &str {
+        match column {
+            CategoricalColumn::자치구명 => &self.자치구명,
+            CategoricalColumn::건물용도 => &self.건물용도,
+        }
+    }
+
+    /// Numeric features followed by the output of each fitted encoder.
+    pub fn into_encoded_features(&self, column_encoders: &[ColumnEncoder]) -> Vec<f64> {
+        let mut features = self.into_features();
+        for column_encoder in column_encoders {
+            features.extend(
+                column_encoder
+                    .encoder
+                    .transform(self.get_category(column_encoder.column)),
+            );
+        }
+        features
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColumnEncoder {
+    pub column: CategoricalColumn,
+    pub encoder: CategoricalEncoder,
+}
+
+/// Everything needed to predict on new records: the trained model and the
+/// preprocessing fitted on the training set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelArtifact {
+    /// Unused by `predict` when `boosted_trees` is set.
+    pub estimation_model: EstimationModel,
+    pub row_mean: RowDVector<f64>,
+    pub row_std_deviation: RowDVector<f64>,
+    pub column_encoders: Vec<ColumnEncoder>,
+    pub imputer: Option<Imputer>,
+    pub target_transformer: Option<TargetTransformer>,
+    /// Applied after the z-score normalization.
+    pub pca: Option<Pca>,
+    /// When set, predicts instead of `estimation_model`, on the same
+    /// preprocessed features.
+    pub boosted_trees: Option<GradientBoostedTrees>,
+}
+
+impl ModelArtifact {
+    pub fn save(&self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        serde_json::to_writer_pretty(File::create(file_path)?, self)?;
+        Ok(())
+    }
+
+    pub fn load(file_path: &str) -> Result<ModelArtifact, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_reader(File::open(file_path)?)?)
+    }
+
+    /// Estimates the target of `record` on the original scale.
+    pub fn predict(&self, record: &SeoulRealEstateTransactionPrice) -> f64 {
+        let features = RowDVector::from_vec(record.into_encoded_features(&self.column_encoders));
+        let features = match &self.imputer {
+            Some(imputer) => imputer.transform_features(&features),
+            None => features,
+        };
+        let features =
+            zscore_normalize_features(&features, &self.row_mean, &self.row_std_deviation);
+        let features = match &self.pca {
+            Some(pca) => pca.transform_features(&features),
+            None => features,
+        };
+        let estimated = match &self.boosted_trees {
+            Some(boosted_trees) => boosted_trees.predict_row(&features),
+            None => estimate(&features, &self.estimation_model),
+        };
+        match &self.target_transformer {
+            Some(target_transformer) => target_transformer.inverse_transform(estimated),
+            None => estimated,
+        }
+    }
 }
 
 mod datetime_ymd_format {
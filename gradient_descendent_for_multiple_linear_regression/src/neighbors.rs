@@ -0,0 +1,596 @@
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DistanceMetric {
+    Euclidean,
+    Manhattan,
+    /// `1 - cos θ`. Rows are indexed by their unit vectors, on which it is
+    /// half the squared Euclidean distance.
+    Cosine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NeighborWeighting {
+    Uniform,
+    /// Weights each neighbor by the inverse of its distance. Neighbors at
+    /// distance 0, if any, share all the weight.
+    Distance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexKind {
+    /// Axis-aligned splits at the median of the widest column.
+    KdTree,
+    /// Nested bounding balls; prunes better than a KD-tree on clustered data.
+    BallTree,
+    /// Scans every row. Nothing prunes well in high dimensions.
+    BruteForce,
+    /// `KdTree` up to `AUTO_MAX_TREE_DIMENSION` columns, `BruteForce` above.
+    Auto,
+}
+
+pub const AUTO_MAX_TREE_DIMENSION: usize = 16;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NeighborsParameters {
+    pub k: usize,
+    pub metric: DistanceMetric,
+    pub weighting: NeighborWeighting,
+    pub index: IndexKind,
+    /// Largest number of rows in a tree leaf.
+    pub leaf_size: usize,
+}
+
+/// A training row near a query row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    /// Row of the training set.
+    pub index: usize,
+    pub distance: f64,
+}
+
+impl Eq for Neighbor {}
+
+impl PartialOrd for Neighbor {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// By distance, then by row, so that ties are broken the same way by every
+/// index.
+impl Ord for Neighbor {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum KdNode {
+    Leaf(Vec<usize>),
+    /// Rows in `left` have `row[column] <= split`, rows in `right` have
+    /// `row[column] >= split`.
+    Split {
+        column: usize,
+        split: f64,
+        left: Box<KdNode>,
+        right: Box<KdNode>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BallNode {
+    center: Vec<f64>,
+    radius: f64,
+    children: BallChildren,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum BallChildren {
+    Leaf(Vec<usize>),
+    Split(Box<BallNode>, Box<BallNode>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum NeighborIndex {
+    KdTree(KdNode),
+    BallTree(BallNode),
+    BruteForce,
+}
+
+/// k-nearest-neighbors regressor or classifier. A classifier predicts the
+/// weighted fraction of `true` among the neighbors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KNearestNeighbors {
+    pub parameters: NeighborsParameters,
+    points: Vec<Vec<f64>>,
+    targets: Vec<f64>,
+    index: NeighborIndex,
+}
+
+/// The distance the index works with: Euclidean for `Cosine`, whose points
+/// are unit vectors.
+fn get_index_distance(metric: DistanceMetric, a: &[f64], b: &[f64]) -> f64 {
+    match metric {
+        DistanceMetric::Manhattan => a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum(),
+        DistanceMetric::Euclidean | DistanceMetric::Cosine => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt(),
+    }
+}
+
+fn get_point(metric: DistanceMetric, row: &[f64]) -> Vec<f64> {
+    match metric {
+        DistanceMetric::Cosine => {
+            let norm = row.iter().map(|value| value.powi(2)).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                row.iter().map(|value| value / norm).collect()
+            } else {
+                row.to_vec()
+            }
+        }
+        _ => row.to_vec(),
+    }
+}
+
+/// The column with the widest range over `indices`.
+fn get_widest_column(points: &[Vec<f64>], indices: &[usize]) -> usize {
+    (0..points[indices[0]].len())
+        .map(|column| {
+            let (min, max) =
+                indices
+                    .iter()
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), index| {
+                        (
+                            min.min(points[*index][column]),
+                            max.max(points[*index][column]),
+                        )
+                    });
+            (column, max - min)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map_or(0, |(column, _)| column)
+}
+
+/// Sorts `indices` by `column` and splits them into equal halves.
+fn split_at_median(
+    points: &[Vec<f64>],
+    mut indices: Vec<usize>,
+    column: usize,
+) -> (Vec<usize>, Vec<usize>) {
+    indices.sort_by(|a, b| points[*a][column].total_cmp(&points[*b][column]));
+    let right = indices.split_off(indices.len() / 2);
+    (indices, right)
+}
+
+/// The `k` nearest candidates seen so far, farthest on top.
+struct NeighborHeap {
+    k: usize,
+    heap: BinaryHeap<Neighbor>,
+}
+
+impl NeighborHeap {
+    fn push(&mut self, neighbor: Neighbor) {
+        if self.heap.len() < self.k {
+            self.heap.push(neighbor);
+        } else if self
+            .heap
+            .peek()
+            .is_some_and(|farthest| neighbor < *farthest)
+        {
+            self.heap.pop();
+            self.heap.push(neighbor);
+        }
+    }
+
+    /// Whether a subtree whose rows are all at least `lower_bound` away may
+    /// still hold a nearer row.
+    fn may_improve(&self, lower_bound: f64) -> bool {
+        self.heap.len() < self.k
+            || self
+                .heap
+                .peek()
+                .is_some_and(|farthest| lower_bound <= farthest.distance)
+    }
+}
+
+impl KdNode {
+    fn build(points: &[Vec<f64>], indices: Vec<usize>, leaf_size: usize) -> KdNode {
+        if indices.len() <= leaf_size.max(1) {
+            return KdNode::Leaf(indices);
+        }
+        let column = get_widest_column(points, &indices);
+        let (left, right) = split_at_median(points, indices, column);
+        KdNode::Split {
+            column,
+            split: points[left[left.len() - 1]][column],
+            left: Box::new(KdNode::build(points, left, leaf_size)),
+            right: Box::new(KdNode::build(points, right, leaf_size)),
+        }
+    }
+
+    /// The distance to the splitting plane bounds the distance to every row
+    /// on the far side for both Euclidean and Manhattan distances.
+    fn search(&self, model: &KNearestNeighbors, point: &[f64], heap: &mut NeighborHeap) {
+        match self {
+            KdNode::Leaf(indices) => model.push_candidates(indices, point, heap),
+            KdNode::Split {
+                column,
+                split,
+                left,
+                right,
+            } => {
+                let (near, far) = if point[*column] <= *split {
+                    (left, right)
+                } else {
+                    (right, left)
+                };
+                near.search(model, point, heap);
+                if heap.may_improve((point[*column] - split).abs()) {
+                    far.search(model, point, heap);
+                }
+            }
+        }
+    }
+}
+
+impl BallNode {
+    fn build(
+        points: &[Vec<f64>],
+        indices: Vec<usize>,
+        leaf_size: usize,
+        metric: DistanceMetric,
+    ) -> BallNode {
+        let dimension = points[indices[0]].len();
+        let center = (0..dimension)
+            .map(|column| {
+                indices
+                    .iter()
+                    .map(|index| points[*index][column])
+                    .sum::<f64>()
+                    / indices.len() as f64
+            })
+            .collect::<Vec<_>>();
+        let radius = indices
+            .iter()
+            .map(|index| get_index_distance(metric, &center, &points[*index]))
+            .fold(0.0, f64::max);
+        let children = if indices.len() <= leaf_size.max(1) {
+            BallChildren::Leaf(indices)
+        } else {
+            let column = get_widest_column(points, &indices);
+            let (left, right) = split_at_median(points, indices, column);
+            BallChildren::Split(
+                Box::new(BallNode::build(points, left, leaf_size, metric)),
+                Box::new(BallNode::build(points, right, leaf_size, metric)),
+            )
+        };
+        BallNode {
+            center,
+            radius,
+            children,
+        }
+    }
+
+    fn get_lower_bound(&self, metric: DistanceMetric, point: &[f64]) -> f64 {
+        (get_index_distance(metric, &self.center, point) - self.radius).max(0.0)
+    }
+
+    /// Visits the child whose ball is nearer first.
+    fn search(&self, model: &KNearestNeighbors, point: &[f64], heap: &mut NeighborHeap) {
+        match &self.children {
+            BallChildren::Leaf(indices) => model.push_candidates(indices, point, heap),
+            BallChildren::Split(left, right) => {
+                let metric = model.parameters.metric;
+                let mut children = [
+                    (left.get_lower_bound(metric, point), left),
+                    (right.get_lower_bound(metric, point), right),
+                ];
+                children.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+                for (lower_bound, child) in children {
+                    if heap.may_improve(lower_bound) {
+                        child.search(model, point, heap);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl KNearestNeighbors {
+    pub fn fit_regressor(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        parameters: &NeighborsParameters,
+    ) -> Result<KNearestNeighbors, Box<dyn std::error::Error>> {
+        KNearestNeighbors::fit(features_set, target_set.as_slice().to_vec(), parameters)
+    }
+
+    pub fn fit_classifier(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+        parameters: &NeighborsParameters,
+    ) -> Result<KNearestNeighbors, Box<dyn std::error::Error>> {
+        let targets = target_set
+            .iter()
+            .map(|target| if *target { 1.0 } else { 0.0 })
+            .collect();
+        KNearestNeighbors::fit(features_set, targets, parameters)
+    }
+
+    /// Stores the training set and builds the index over it.
+    fn fit(
+        features_set: &DMatrix<f64>,
+        targets: Vec<f64>,
+        parameters: &NeighborsParameters,
+    ) -> Result<KNearestNeighbors, Box<dyn std::error::Error>> {
+        if features_set.nrows() != targets.len() {
+            return Err(format!(
+                "{} feature rows but {} targets",
+                features_set.nrows(),
+                targets.len()
+            )
+            .into());
+        }
+        if parameters.k == 0 || parameters.k > features_set.nrows() {
+            return Err(format!(
+                "k must be between 1 and the row count {}",
+                features_set.nrows()
+            )
+            .into());
+        }
+        if features_set.iter().any(|feature| !feature.is_finite()) {
+            return Err("features must be finite; impute missing values first".into());
+        }
+        let points = features_set
+            .row_iter()
+            .map(|row| get_point(parameters.metric, RowDVector::from(row).as_slice()))
+            .collect::<Vec<_>>();
+        let indices = (0..points.len()).collect::<Vec<_>>();
+        let index = match parameters.index {
+            IndexKind::KdTree => {
+                NeighborIndex::KdTree(KdNode::build(&points, indices, parameters.leaf_size))
+            }
+            IndexKind::Auto if features_set.ncols() <= AUTO_MAX_TREE_DIMENSION => {
+                NeighborIndex::KdTree(KdNode::build(&points, indices, parameters.leaf_size))
+            }
+            IndexKind::BallTree => NeighborIndex::BallTree(BallNode::build(
+                &points,
+                indices,
+                parameters.leaf_size,
+                parameters.metric,
+            )),
+            IndexKind::BruteForce | IndexKind::Auto => NeighborIndex::BruteForce,
+        };
+        Ok(KNearestNeighbors {
+            parameters: *parameters,
+            points,
+            targets,
+            index,
+        })
+    }
+
+    fn push_candidates(&self, indices: &[usize], point: &[f64], heap: &mut NeighborHeap) {
+        for index in indices {
+            heap.push(Neighbor {
+                index: *index,
+                distance: get_index_distance(self.parameters.metric, point, &self.points[*index]),
+            });
+        }
+    }
+
+    /// The `k` nearest training rows, nearest first. For comparable-sales
+    /// pricing these are the comparables.
+    pub fn get_neighbors(&self, row: &RowDVector<f64>) -> Vec<Neighbor> {
+        let point = get_point(self.parameters.metric, row.as_slice());
+        let mut heap = NeighborHeap {
+            k: self.parameters.k,
+            heap: BinaryHeap::with_capacity(self.parameters.k + 1),
+        };
+        match &self.index {
+            NeighborIndex::KdTree(root) => root.search(self, &point, &mut heap),
+            NeighborIndex::BallTree(root) => root.search(self, &point, &mut heap),
+            NeighborIndex::BruteForce => {
+                let indices = (0..self.points.len()).collect::<Vec<_>>();
+                self.push_candidates(&indices, &point, &mut heap);
+            }
+        }
+        let mut neighbors = heap.heap.into_sorted_vec();
+        if self.parameters.metric == DistanceMetric::Cosine {
+            for neighbor in &mut neighbors {
+                neighbor.distance = neighbor.distance.powi(2) / 2.0;
+            }
+        }
+        neighbors
+    }
+
+    pub fn predict_row(&self, row: &RowDVector<f64>) -> f64 {
+        let neighbors = self.get_neighbors(row);
+        let exact_neighbors = neighbors
+            .iter()
+            .filter(|neighbor| neighbor.distance == 0.0)
+            .cloned()
+            .collect::<Vec<_>>();
+        let (neighbors, weighting) = match self.parameters.weighting {
+            NeighborWeighting::Distance if !exact_neighbors.is_empty() => {
+                (exact_neighbors, NeighborWeighting::Uniform)
+            }
+            weighting => (neighbors, weighting),
+        };
+        let (weighted_sum, weight_sum) =
+            neighbors
+                .iter()
+                .fold((0.0, 0.0), |(weighted_sum, weight_sum), neighbor| {
+                    let weight = match weighting {
+                        NeighborWeighting::Uniform => 1.0,
+                        NeighborWeighting::Distance => 1.0 / neighbor.distance,
+                    };
+                    (
+                        weighted_sum + weight * self.targets[neighbor.index],
+                        weight_sum + weight,
+                    )
+                });
+        weighted_sum / weight_sum
+    }
+
+    /// The weighted mean target of the neighbors: the estimate for a
+    /// regressor, the probability of `true` for a classifier.
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> MatrixXx1<f64> {
+        MatrixXx1::from_iterator(
+            features_set.nrows(),
+            features_set
+                .row_iter()
+                .map(|row| self.predict_row(&RowDVector::from(row))),
+        )
+    }
+
+    pub fn predict_class(&self, features_set: &DMatrix<f64>) -> MatrixXx1<bool> {
+        self.predict(features_set)
+            .map(|probability| probability >= 0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    const PARAMETERS: NeighborsParameters = NeighborsParameters {
+        k: 5,
+        metric: DistanceMetric::Euclidean,
+        weighting: NeighborWeighting::Uniform,
+        index: IndexKind::Auto,
+        leaf_size: 4,
+    };
+
+    #[test]
+    fn indexes_agree_with_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let features_set = DMatrix::from_fn(300, 3, |_, _| rng.gen_range(-5.0..5.0));
+        let target_set = MatrixXx1::zeros(300);
+        let queries = DMatrix::from_fn(50, 3, |_, _| rng.gen_range(-6.0..6.0));
+        for metric in [
+            DistanceMetric::Euclidean,
+            DistanceMetric::Manhattan,
+            DistanceMetric::Cosine,
+        ] {
+            let brute_force = KNearestNeighbors::fit_regressor(
+                &features_set,
+                &target_set,
+                &NeighborsParameters {
+                    metric,
+                    index: IndexKind::BruteForce,
+                    ..PARAMETERS
+                },
+            )
+            .unwrap();
+            for index in [IndexKind::KdTree, IndexKind::BallTree, IndexKind::Auto] {
+                let model = KNearestNeighbors::fit_regressor(
+                    &features_set,
+                    &target_set,
+                    &NeighborsParameters {
+                        metric,
+                        index,
+                        ..PARAMETERS
+                    },
+                )
+                .unwrap();
+                for query in queries.row_iter() {
+                    let query = RowDVector::from(query);
+                    assert_eq!(
+                        model.get_neighbors(&query),
+                        brute_force.get_neighbors(&query),
+                        "{:?} {:?}",
+                        metric,
+                        index
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_distance_ignores_length() {
+        let features_set = DMatrix::from_row_slice(3, 2, &[1.0, 0.0, 0.0, 3.0, 10.0, 10.0]);
+        let model = KNearestNeighbors::fit_regressor(
+            &features_set,
+            &MatrixXx1::from_vec(vec![1.0, 2.0, 3.0]),
+            &NeighborsParameters {
+                k: 3,
+                metric: DistanceMetric::Cosine,
+                index: IndexKind::BallTree,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        let neighbors = model.get_neighbors(&RowDVector::from_vec(vec![0.0, 0.5]));
+        assert_eq!(neighbors[0].index, 1);
+        approx::assert_relative_eq!(neighbors[0].distance, 0.0);
+        approx::assert_relative_eq!(neighbors[1].distance, 1.0 - 0.5_f64.sqrt());
+        approx::assert_relative_eq!(neighbors[2].distance, 1.0);
+    }
+
+    #[test]
+    fn weights_neighbors() {
+        let features_set = DMatrix::from_row_slice(3, 1, &[0.0, 1.0, 3.0]);
+        let target_set = MatrixXx1::from_vec(vec![0.0, 10.0, 30.0]);
+        let parameters = NeighborsParameters {
+            k: 2,
+            index: IndexKind::KdTree,
+            ..PARAMETERS
+        };
+        let uniform =
+            KNearestNeighbors::fit_regressor(&features_set, &target_set, &parameters).unwrap();
+        let weighted = KNearestNeighbors::fit_regressor(
+            &features_set,
+            &target_set,
+            &NeighborsParameters {
+                weighting: NeighborWeighting::Distance,
+                ..parameters
+            },
+        )
+        .unwrap();
+        let query = RowDVector::from_vec(vec![0.25]);
+        assert_eq!(uniform.predict_row(&query), 5.0);
+        approx::assert_relative_eq!(
+            weighted.predict_row(&query),
+            (4.0 * 0.0 + 4.0 / 3.0 * 10.0) / (4.0 + 4.0 / 3.0)
+        );
+        assert_eq!(weighted.predict_row(&RowDVector::from_vec(vec![3.0])), 30.0);
+    }
+
+    #[test]
+    fn classifies_by_neighbor_vote() {
+        let features_set = DMatrix::from_fn(20, 1, |row, _| row as f64);
+        let target_set = MatrixXx1::from_fn(20, |row, _| row >= 10);
+        let model = KNearestNeighbors::fit_classifier(
+            &features_set,
+            &target_set,
+            &NeighborsParameters {
+                metric: DistanceMetric::Manhattan,
+                index: IndexKind::BallTree,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        assert_eq!(model.predict_class(&features_set), target_set);
+    }
+
+    #[test]
+    fn rejects_k_larger_than_training_set() {
+        let features_set = DMatrix::from_fn(3, 1, |row, _| row as f64);
+        assert!(
+            KNearestNeighbors::fit_regressor(&features_set, &MatrixXx1::zeros(3), &PARAMETERS,)
+                .is_err()
+        );
+    }
+}
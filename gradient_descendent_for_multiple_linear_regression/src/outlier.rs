@@ -0,0 +1,501 @@
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::{Rng, SeedableRng};
+
+/// A rule deciding which rows are outliers. The first three look at the
+/// target only; `IsolationForest` scores each row on its features and target
+/// together.
+#[derive(Debug, Clone, Copy)]
+pub enum OutlierRule {
+    /// Outside `[q1 - multiplier * iqr, q3 + multiplier * iqr]`.
+    Iqr { multiplier: f64 },
+    /// More than `threshold` standard deviations away from the mean.
+    ZScore { threshold: f64 },
+    /// More than `threshold` scaled median absolute deviations away from the
+    /// median. The MAD is scaled by 1.4826 to match the standard deviation of
+    /// normally distributed data.
+    Mad { threshold: f64 },
+    /// Anomaly score above `threshold` (0.5 is average, close to 1 is
+    /// anomalous).
+    IsolationForest {
+        tree_count: usize,
+        sample_size: usize,
+        threshold: f64,
+        seed: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutlierAction {
+    /// Keep every row and only report the flags.
+    Flag,
+    Drop,
+    /// Clip the target of flagged rows into the range allowed by the rules.
+    Winsorize,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutlierReport {
+    /// Rows flagged by each rule. Rules are evaluated independently on the
+    /// original rows, so one row can be counted by several rules.
+    pub rule_counts: Vec<(OutlierRule, usize)>,
+    /// Rows flagged by any rule.
+    pub flags: Vec<bool>,
+    pub action: OutlierAction,
+    /// Rows dropped or winsorized; zero for `OutlierAction::Flag`.
+    pub touched_row_count: usize,
+}
+
+fn get_quantile(sorted_values: &[f64], quantile: f64) -> f64 {
+    let position = quantile * (sorted_values.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted_values[lower] + (sorted_values[upper] - sorted_values[lower]) * (position - lower as f64)
+}
+
+fn get_sorted(values: &[f64]) -> Vec<f64> {
+    let mut sorted_values = values.to_vec();
+    sorted_values.sort_by(|a, b| a.total_cmp(b));
+    sorted_values
+}
+
+/// Range of target values a rule accepts, for the rules that have one.
+fn get_bounds(rule: &OutlierRule, targets: &[f64]) -> Option<(f64, f64)> {
+    match rule {
+        OutlierRule::Iqr { multiplier } => {
+            let sorted_targets = get_sorted(targets);
+            let q1 = get_quantile(&sorted_targets, 0.25);
+            let q3 = get_quantile(&sorted_targets, 0.75);
+            let iqr = q3 - q1;
+            Some((q1 - multiplier * iqr, q3 + multiplier * iqr))
+        }
+        OutlierRule::ZScore { threshold } => {
+            let mean = targets.iter().sum::<f64>() / targets.len() as f64;
+            let std_deviation = (targets
+                .iter()
+                .map(|target| (target - mean).powi(2))
+                .sum::<f64>()
+                / targets.len() as f64)
+                .sqrt();
+            Some((
+                mean - threshold * std_deviation,
+                mean + threshold * std_deviation,
+            ))
+        }
+        OutlierRule::Mad { threshold } => {
+            let median = get_quantile(&get_sorted(targets), 0.5);
+            let deviations = targets
+                .iter()
+                .map(|target| (target - median).abs())
+                .collect::<Vec<_>>();
+            let mad = 1.4826 * get_quantile(&get_sorted(&deviations), 0.5);
+            Some((median - threshold * mad, median + threshold * mad))
+        }
+        OutlierRule::IsolationForest { .. } => None,
+    }
+}
+
+pub fn detect_outliers(
+    features_set: &DMatrix<f64>,
+    target_set: &MatrixXx1<f64>,
+    rule: &OutlierRule,
+) -> Result<Vec<bool>, Box<dyn std::error::Error>> {
+    if target_set.is_empty() {
+        return Ok(Vec::new());
+    }
+    let targets = target_set.as_slice();
+    match rule {
+        OutlierRule::IsolationForest {
+            tree_count,
+            sample_size,
+            threshold,
+            seed,
+        } => {
+            let mut rows_set = features_set
+                .clone()
+                .insert_column(features_set.ncols(), 0.0);
+            rows_set.set_column(features_set.ncols(), target_set);
+            let isolation_forest =
+                IsolationForest::fit(&rows_set, *tree_count, *sample_size, *seed)?;
+            Ok(rows_set
+                .row_iter()
+                .map(|row| isolation_forest.get_score(&RowDVector::from(row)) > *threshold)
+                .collect())
+        }
+        _ => {
+            let (lower, upper) = get_bounds(rule, targets).unwrap();
+            Ok(targets
+                .iter()
+                .map(|target| *target < lower || *target > upper)
+                .collect())
+        }
+    }
+}
+
+/// Features, targets and the report returned by `filter_outliers`.
+pub type FilteredTrainingSet = (DMatrix<f64>, MatrixXx1<f64>, OutlierReport);
+
+/// Applies every rule to the original rows, then flags, drops or winsorizes
+/// the rows flagged by any of them. Winsorizing clips to the intersection of
+/// the ranges of the rules, and fails if the ranges do not overlap or if
+/// `IsolationForest` flags every row, since there are no inliers to clip to.
+pub fn filter_outliers(
+    features_set: &DMatrix<f64>,
+    target_set: &MatrixXx1<f64>,
+    rules: &[OutlierRule],
+    action: OutlierAction,
+) -> Result<FilteredTrainingSet, Box<dyn std::error::Error>> {
+    let mut flags = vec![false; target_set.len()];
+    let mut rule_counts = Vec::new();
+    for rule in rules {
+        let rule_flags = detect_outliers(features_set, target_set, rule)?;
+        rule_counts.push((*rule, rule_flags.iter().filter(|flag| **flag).count()));
+        for (flag, rule_flag) in flags.iter_mut().zip(rule_flags) {
+            *flag |= rule_flag;
+        }
+    }
+    let flagged_count = flags.iter().filter(|flag| **flag).count();
+
+    let (features_set, target_set, touched_row_count) = match action {
+        OutlierAction::Flag => (features_set.clone(), target_set.clone(), 0),
+        OutlierAction::Drop => {
+            let kept_rows = flags
+                .iter()
+                .enumerate()
+                .filter(|(_, flag)| !**flag)
+                .map(|(index, _)| index)
+                .collect::<Vec<_>>();
+            (
+                features_set.select_rows(&kept_rows),
+                target_set.select_rows(&kept_rows),
+                flagged_count,
+            )
+        }
+        OutlierAction::Winsorize => {
+            let targets = target_set.as_slice();
+            // Rules without bounds of their own clip to the range of the
+            // targets no rule flagged.
+            let inlier_targets = targets
+                .iter()
+                .zip(flags.iter())
+                .filter(|(_, flag)| !**flag)
+                .map(|(target, _)| *target)
+                .collect::<Vec<_>>();
+            if inlier_targets.is_empty()
+                && rules.iter().any(|rule| get_bounds(rule, targets).is_none())
+            {
+                return Err("every row is an outlier, there is no range to winsorize to".into());
+            }
+            let inlier_bounds = (
+                inlier_targets.iter().cloned().fold(f64::INFINITY, f64::min),
+                inlier_targets
+                    .iter()
+                    .cloned()
+                    .fold(f64::NEG_INFINITY, f64::max),
+            );
+            let (lower, upper) = rules
+                .iter()
+                .map(|rule| get_bounds(rule, targets).unwrap_or(inlier_bounds))
+                .fold(
+                    (f64::NEG_INFINITY, f64::INFINITY),
+                    |(lower, upper), bounds| (lower.max(bounds.0), upper.min(bounds.1)),
+                );
+            if lower > upper {
+                return Err(format!(
+                    "the ranges of the rules do not overlap ({} > {}), there is no range to winsorize to",
+                    lower, upper
+                )
+                .into());
+            }
+            let winsorized_target_set = MatrixXx1::from_iterator(
+                targets.len(),
+                targets.iter().zip(flags.iter()).map(|(target, flag)| {
+                    if *flag {
+                        target.max(lower).min(upper)
+                    } else {
+                        *target
+                    }
+                }),
+            );
+            (features_set.clone(), winsorized_target_set, flagged_count)
+        }
+    };
+
+    Ok((
+        features_set,
+        target_set,
+        OutlierReport {
+            rule_counts,
+            flags,
+            action,
+            touched_row_count,
+        },
+    ))
+}
+
+enum IsolationTree {
+    Node {
+        column: usize,
+        split: f64,
+        left: Box<IsolationTree>,
+        right: Box<IsolationTree>,
+    },
+    Leaf {
+        row_count: usize,
+    },
+}
+
+/// Average path length of an unsuccessful search in a binary search tree of
+/// `row_count` rows, used to normalize path lengths.
+fn get_average_path_length(row_count: usize) -> f64 {
+    match row_count {
+        0 | 1 => 0.0,
+        2 => 1.0,
+        _ => {
+            let n = row_count as f64;
+            2.0 * ((n - 1.0).ln() + 0.5772156649) - 2.0 * (n - 1.0) / n
+        }
+    }
+}
+
+impl IsolationTree {
+    fn build(rows: &[RowDVector<f64>], depth: usize, max_depth: usize, rng: &mut StdRng) -> Self {
+        if depth >= max_depth || rows.len() <= 1 {
+            return IsolationTree::Leaf {
+                row_count: rows.len(),
+            };
+        }
+        let column = rng.gen_range(0..rows[0].len());
+        let min = rows
+            .iter()
+            .map(|row| row[column])
+            .fold(f64::INFINITY, f64::min);
+        let max = rows
+            .iter()
+            .map(|row| row[column])
+            .fold(f64::NEG_INFINITY, f64::max);
+        if min >= max {
+            return IsolationTree::Leaf {
+                row_count: rows.len(),
+            };
+        }
+        let split = rng.gen_range(min..max);
+        let (left_rows, right_rows): (Vec<_>, Vec<_>) =
+            rows.iter().cloned().partition(|row| row[column] < split);
+        IsolationTree::Node {
+            column,
+            split,
+            left: Box::new(IsolationTree::build(&left_rows, depth + 1, max_depth, rng)),
+            right: Box::new(IsolationTree::build(&right_rows, depth + 1, max_depth, rng)),
+        }
+    }
+
+    fn get_path_length(&self, row: &RowDVector<f64>) -> f64 {
+        match self {
+            IsolationTree::Node {
+                column,
+                split,
+                left,
+                right,
+            } => {
+                let child = if row[*column] < *split { left } else { right };
+                1.0 + child.get_path_length(row)
+            }
+            IsolationTree::Leaf { row_count } => get_average_path_length(*row_count),
+        }
+    }
+}
+
+pub struct IsolationForest {
+    trees: Vec<IsolationTree>,
+    sample_size: usize,
+}
+
+impl IsolationForest {
+    pub fn fit(
+        rows_set: &DMatrix<f64>,
+        tree_count: usize,
+        sample_size: usize,
+        seed: u64,
+    ) -> Result<IsolationForest, Box<dyn std::error::Error>> {
+        if tree_count == 0 {
+            return Err("isolation forest needs at least one tree".into());
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        let rows = rows_set
+            .row_iter()
+            .map(RowDVector::from)
+            .collect::<Vec<_>>();
+        let sample_size = sample_size.min(rows.len()).max(1);
+        let max_depth = (sample_size as f64).log2().ceil() as usize;
+        let trees = (0..tree_count)
+            .map(|_| {
+                let sampled_rows = sample(&mut rng, rows.len(), sample_size)
+                    .iter()
+                    .map(|index| rows[index].clone())
+                    .collect::<Vec<_>>();
+                IsolationTree::build(&sampled_rows, 0, max_depth, &mut rng)
+            })
+            .collect();
+        Ok(IsolationForest { trees, sample_size })
+    }
+
+    /// Anomaly score in `(0, 1]`: close to 1 for rows that are isolated in
+    /// few splits, around 0.5 or below for ordinary rows.
+    pub fn get_score(&self, row: &RowDVector<f64>) -> f64 {
+        let mean_path_length = self
+            .trees
+            .iter()
+            .map(|tree| tree.get_path_length(row))
+            .sum::<f64>()
+            / self.trees.len() as f64;
+        2.0_f64.powf(-mean_path_length / get_average_path_length(self.sample_size).max(1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_training_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let targets = [1.0, 2.0, 3.0, 4.0, 100.0];
+        (
+            DMatrix::from_fn(targets.len(), 1, |row, _| row as f64),
+            MatrixXx1::from_row_slice(&targets),
+        )
+    }
+
+    #[test]
+    fn target_rules_flag_the_extreme_target() {
+        let (features_set, target_set) = get_training_set();
+        // IQR fences (-1, 7); z-score 78 / sqrt(1522) > 1.5; MAD bounds
+        // 3 -+ 3 * 1.4826.
+        for rule in [
+            OutlierRule::Iqr { multiplier: 1.5 },
+            OutlierRule::ZScore { threshold: 1.5 },
+            OutlierRule::Mad { threshold: 3.0 },
+        ] {
+            assert_eq!(
+                detect_outliers(&features_set, &target_set, &rule).unwrap(),
+                vec![false, false, false, false, true],
+                "{:?}",
+                rule
+            );
+        }
+        assert_eq!(
+            get_bounds(&OutlierRule::Iqr { multiplier: 1.5 }, target_set.as_slice()),
+            Some((-1.0, 7.0))
+        );
+    }
+
+    fn get_isolated_training_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let mut features_set =
+            DMatrix::from_fn(41, 2, |row, column| ((row * (column + 3)) % 7) as f64 * 0.1);
+        let mut target_set = MatrixXx1::from_fn(41, |row, _| (row % 5) as f64);
+        features_set.set_row(40, &RowDVector::from_row_slice(&[30.0, -30.0]));
+        target_set[40] = 100.0;
+        (features_set, target_set)
+    }
+
+    const ISOLATION_FOREST: OutlierRule = OutlierRule::IsolationForest {
+        tree_count: 100,
+        sample_size: 32,
+        threshold: 0.6,
+        seed: 0,
+    };
+
+    #[test]
+    fn isolation_forest_flags_the_isolated_row() {
+        let (features_set, target_set) = get_isolated_training_set();
+        let flags = detect_outliers(&features_set, &target_set, &ISOLATION_FOREST).unwrap();
+        assert!(flags[40]);
+        assert!(flags.iter().filter(|flag| **flag).count() <= 2);
+    }
+
+    #[test]
+    fn isolation_forest_without_trees_is_an_error() {
+        let (features_set, target_set) = get_training_set();
+        let rule = OutlierRule::IsolationForest {
+            tree_count: 0,
+            sample_size: 32,
+            threshold: 0.6,
+            seed: 0,
+        };
+        assert!(detect_outliers(&features_set, &target_set, &rule).is_err());
+    }
+
+    #[test]
+    fn each_action_flags_drops_or_winsorizes() {
+        let (features_set, target_set) = get_training_set();
+        let rules = [OutlierRule::Iqr { multiplier: 1.5 }];
+
+        let (flagged_features_set, flagged_target_set, report) =
+            filter_outliers(&features_set, &target_set, &rules, OutlierAction::Flag).unwrap();
+        assert_eq!(flagged_features_set, features_set);
+        assert_eq!(flagged_target_set, target_set);
+        assert_eq!(report.rule_counts[0].1, 1);
+        assert_eq!(report.touched_row_count, 0);
+
+        let (dropped_features_set, dropped_target_set, report) =
+            filter_outliers(&features_set, &target_set, &rules, OutlierAction::Drop).unwrap();
+        assert_eq!(dropped_features_set.nrows(), 4);
+        assert_eq!(dropped_target_set.as_slice(), [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(report.touched_row_count, 1);
+
+        let (winsorized_features_set, winsorized_target_set, report) =
+            filter_outliers(&features_set, &target_set, &rules, OutlierAction::Winsorize).unwrap();
+        assert_eq!(winsorized_features_set, features_set);
+        assert_eq!(winsorized_target_set.as_slice(), [1.0, 2.0, 3.0, 4.0, 7.0]);
+        assert_eq!(report.touched_row_count, 1);
+    }
+
+    #[test]
+    fn winsorize_rejects_rules_with_disjoint_ranges() {
+        let features_set = DMatrix::from_fn(5, 1, |row, _| row as f64);
+        let target_set = MatrixXx1::from_row_slice(&[0.0, 0.0, 0.0, 0.0, 1000.0]);
+        // IQR range [0, 0]; z-score range 200 -+ 0.1 * 400 = [160, 240].
+        let rules = [
+            OutlierRule::ZScore { threshold: 0.1 },
+            OutlierRule::Iqr { multiplier: 1.5 },
+        ];
+        assert!(
+            filter_outliers(&features_set, &target_set, &rules, OutlierAction::Winsorize).is_err()
+        );
+        assert!(filter_outliers(&features_set, &target_set, &rules, OutlierAction::Drop).is_ok());
+    }
+
+    #[test]
+    fn winsorize_clips_isolation_forest_outliers_to_the_inlier_range() {
+        let (features_set, target_set) = get_isolated_training_set();
+        let (_, winsorized_target_set, report) = filter_outliers(
+            &features_set,
+            &target_set,
+            &[ISOLATION_FOREST],
+            OutlierAction::Winsorize,
+        )
+        .unwrap();
+        for (row, flag) in report.flags.iter().enumerate() {
+            if !flag {
+                assert_eq!(winsorized_target_set[row], target_set[row]);
+            }
+        }
+        assert_eq!(winsorized_target_set[40], 4.0);
+
+        // Every score is positive, so with threshold 0 every row is flagged
+        // and there is no inlier range.
+        let rule = OutlierRule::IsolationForest {
+            tree_count: 10,
+            sample_size: 32,
+            threshold: 0.0,
+            seed: 0,
+        };
+        assert!(filter_outliers(
+            &features_set,
+            &target_set,
+            &[OutlierRule::Iqr { multiplier: 1.5 }, rule],
+            OutlierAction::Winsorize
+        )
+        .is_err());
+    }
+}
@@ -1,37 +1,661 @@
+use crate::search::Trial;
+use nalgebra::MatrixXx1;
+use plotters::coord::ranged1d::ValueFormatter;
+use plotters::coord::types::RangedCoordusize;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlotFormat {
+    Png,
+    Svg,
+}
+
+/// Where and how a plot is written. Every `draw_*` function takes one and has
+/// a `draw_*_on` counterpart drawing on any plotters backend.
+#[derive(Debug, Clone)]
+pub struct PlotOutput {
+    pub filename: String,
+    pub format: PlotFormat,
+    pub size: (u32, u32),
+}
+
+impl PlotOutput {
+    /// The format follows the extension: `.svg` is written as SVG, anything
+    /// else as PNG.
+    pub fn new(filename: &str, size: (u32, u32)) -> PlotOutput {
+        let format = if filename.to_lowercase().ends_with(".svg") {
+            PlotFormat::Svg
+        } else {
+            PlotFormat::Png
+        };
+        PlotOutput {
+            filename: filename.to_string(),
+            format,
+            size,
+        }
+    }
+}
+
+macro_rules! draw_on {
+    ($output:expr, $draw:ident($($argument:expr),*)) => {
+        match $output.format {
+            PlotFormat::Png => $draw(
+                BitMapBackend::new(&$output.filename, $output.size).into_drawing_area()
+                $(, $argument)*
+            ),
+            PlotFormat::Svg => $draw(
+                SVGBackend::new(&$output.filename, $output.size).into_drawing_area()
+                $(, $argument)*
+            ),
+        }
+    };
+}
+
+/// Draws into an in-memory SVG document, e.g. to embed it in a report.
+pub fn render_svg(
+    size: (u32, u32),
+    draw: impl FnOnce(DrawingArea<SVGBackend, Shift>) -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut svg = String::new();
+    draw(SVGBackend::with_string(&mut svg, size).into_drawing_area())?;
+    Ok(svg)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostScale {
+    Linear,
+    /// Useful when the cost falls by orders of magnitude. Only positive costs
+    /// are drawn.
+    Log,
+}
+
 pub fn draw_costs_plot(
-    filename: &str,
+    output: &PlotOutput,
     learning_rate: f64,
     costs: &[f64],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_costs_plot_on(learning_rate, costs, scale))
+}
+
+pub fn draw_costs_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    learning_rate: f64,
+    costs: &[f64],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    draw_costs_series_on(
+        root,
+        &format!("Learning Rate: {}", learning_rate),
+        &[("cost", costs)],
+        scale,
+    )
+}
+
+/// Overlays several cost histories on one chart, e.g. one per learning rate.
+pub fn draw_costs_comparison_plot(
+    output: &PlotOutput,
+    series: &[(&str, &[f64])],
+    scale: CostScale,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Plot
-    let root = BitMapBackend::new(filename, (640, 480)).into_drawing_area();
+    draw_on!(output, draw_costs_comparison_plot_on(series, scale))
+}
+
+pub fn draw_costs_comparison_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    series: &[(&str, &[f64])],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    draw_costs_series_on(root, "Cost comparison", series, scale)
+}
+
+/// Overlays the cost curve of every hyperparameter search trial.
+pub fn draw_search_costs_plot(
+    output: &PlotOutput,
+    trials: &[Trial],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_search_costs_plot_on(trials, scale))
+}
+
+pub fn draw_search_costs_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    trials: &[Trial],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let labels = trials
+        .iter()
+        .map(|trial| trial.hyperparameters.to_string())
+        .collect::<Vec<_>>();
+    let series = labels
+        .iter()
+        .zip(trials)
+        .map(|(label, trial)| (label.as_str(), trial.costs.as_slice()))
+        .collect::<Vec<_>>();
+    draw_costs_series_on(root, "Search trials", &series, scale)
+}
+
+/// NaN and infinite costs, and non-positive ones on a log scale, are left out
+/// of both the axis range and the lines.
+fn draw_costs_series_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    caption: &str,
+    series: &[(&str, &[f64])],
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let iteration_count = series
+        .iter()
+        .map(|(_, costs)| costs.len())
+        .max()
+        .unwrap_or(0);
+    if iteration_count == 0 {
+        return Err("no cost to plot".into());
+    }
+    let is_drawable = |cost: &f64| cost.is_finite() && (scale == CostScale::Linear || *cost > 0.0);
+    let drawable_costs = series
+        .iter()
+        .flat_map(|(_, costs)| costs.iter())
+        .filter(|cost| is_drawable(cost))
+        .collect::<Vec<_>>();
+    if drawable_costs.is_empty() {
+        return Err(match scale {
+            CostScale::Linear => "no finite cost to plot",
+            CostScale::Log => "no positive finite cost to plot on a log scale",
+        }
+        .into());
+    }
+
     root.fill(&WHITE)?;
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart_builder = ChartBuilder::on(&root);
+    chart_builder
         .margin(20)
-        .caption(
-            format!("Learning Rate: {}", learning_rate),
-            ("sans-serif", 20),
-        )
-        .set_label_area_size(LabelAreaPosition::Left, 60)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
+        .caption(caption, ("sans-serif", 20))
         .x_label_area_size(30)
-        .y_label_area_size(50)
-        .build_cartesian_2d(0..costs.len(), 0.0..(costs[0] * 1.2))?;
+        .y_label_area_size(60);
+    match scale {
+        CostScale::Linear => {
+            let chart = chart_builder
+                .build_cartesian_2d(0..iteration_count, get_range(drawable_costs.into_iter()))?;
+            draw_costs_series(chart, series, is_drawable)?;
+        }
+        CostScale::Log => {
+            let (min, max) = drawable_costs
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), cost| {
+                    (min.min(**cost), max.max(**cost))
+                });
+            let chart = chart_builder
+                .build_cartesian_2d(0..iteration_count, ((min * 0.8)..(max * 1.25)).log_scale())?;
+            draw_costs_series(chart, series, is_drawable)?;
+        }
+    }
+    root.present()?;
+    Ok(())
+}
+
+fn draw_costs_series<'a, DB, Y>(
+    mut chart: ChartContext<'a, DB, Cartesian2d<RangedCoordusize, Y>>,
+    series: &[(&str, &[f64])],
+    is_drawable: impl Fn(&f64) -> bool,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
     chart
         .configure_mesh()
         .x_desc("iteration")
         .y_desc("cost of function")
         .draw()?;
+    for (index, (label, costs)) in series.iter().enumerate() {
+        let color = if series.len() == 1 {
+            RED.to_rgba()
+        } else {
+            Palette99::pick(index).to_rgba()
+        };
+        chart
+            .draw_series(LineSeries::new(
+                costs
+                    .iter()
+                    .cloned()
+                    .enumerate()
+                    .filter(|(_, cost)| is_drawable(cost)),
+                color,
+            ))?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    if series.len() > 1 {
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+    }
+    Ok(())
+}
+
+/// Range covering every finite value with 5% padding on both sides.
+fn get_range<'a>(values: impl Iterator<Item = &'a f64>) -> std::ops::Range<f64> {
+    let (min, max) = values
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(*value), max.max(*value))
+        });
+    if min > max {
+        return 0.0..1.0;
+    }
+    let padding = if max > min {
+        (max - min) * 0.05
+    } else {
+        min.abs().max(1.0) * 0.05
+    };
+    (min - padding)..(max + padding)
+}
+
+pub fn draw_prediction_plot(
+    output: &PlotOutput,
+    estimates: &[f64],
+    target_set: &MatrixXx1<f64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_prediction_plot_on(estimates, target_set))
+}
+
+pub fn draw_prediction_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    estimates: &[f64],
+    target_set: &MatrixXx1<f64>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let range = get_range(estimates.iter().chain(target_set.iter()));
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Estimated vs target", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(range.clone(), range.clone())?;
+    chart
+        .configure_mesh()
+        .x_desc("target")
+        .y_desc("estimated")
+        .draw()?;
     chart.draw_series(LineSeries::new(
-        costs
+        vec![(range.start, range.start), (range.end, range.end)],
+        BLACK.mix(0.3),
+    ))?;
+    chart.draw_series(
+        target_set
             .iter()
-            .enumerate()
-            .map(|(index, cost)| (index, cost.clone())),
-        &RED,
+            .zip(estimates.iter())
+            .map(|(target, estimated)| Circle::new((*target, *estimated), 2, RED.filled())),
+    )?;
+    root.present()?;
+    Ok(())
+}
+
+pub fn draw_residual_plot(
+    output: &PlotOutput,
+    estimates: &[f64],
+    target_set: &MatrixXx1<f64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_residual_plot_on(estimates, target_set))
+}
+
+pub fn draw_residual_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    estimates: &[f64],
+    target_set: &MatrixXx1<f64>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let residuals = target_set
+        .iter()
+        .zip(estimates.iter())
+        .map(|(target, estimated)| target - estimated)
+        .collect::<Vec<_>>();
+    let x_range = get_range(estimates.iter());
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Residuals vs fitted", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(x_range.clone(), get_range(residuals.iter()))?;
+    chart
+        .configure_mesh()
+        .x_desc("estimated")
+        .y_desc("residual")
+        .draw()?;
+    chart.draw_series(LineSeries::new(
+        vec![(x_range.start, 0.0), (x_range.end, 0.0)],
+        BLACK.mix(0.3),
     ))?;
-    chart.configure_series_labels().draw()?;
+    chart.draw_series(
+        estimates
+            .iter()
+            .zip(residuals.iter())
+            .map(|(estimated, residual)| Circle::new((*estimated, *residual), 2, RED.filled())),
+    )?;
+    root.present()?;
+    Ok(())
+}
+
+pub fn draw_residual_histogram(
+    output: &PlotOutput,
+    estimates: &[f64],
+    target_set: &MatrixXx1<f64>,
+    bin_count: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(
+        output,
+        draw_residual_histogram_on(estimates, target_set, bin_count)
+    )
+}
+
+pub fn draw_residual_histogram_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    estimates: &[f64],
+    target_set: &MatrixXx1<f64>,
+    bin_count: usize,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let residuals = target_set
+        .iter()
+        .zip(estimates.iter())
+        .map(|(target, estimated)| target - estimated)
+        .filter(|residual| residual.is_finite())
+        .collect::<Vec<_>>();
+    let range = get_range(residuals.iter());
+    let bin_count = bin_count.max(1);
+    let bin_width = (range.end - range.start) / bin_count as f64;
+    let mut bins = vec![0; bin_count];
+    for residual in &residuals {
+        let index = ((residual - range.start) / bin_width) as usize;
+        bins[index.min(bin_count - 1)] += 1;
+    }
+
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Residual histogram", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(50)
+        .build_cartesian_2d(
+            range.clone(),
+            0..(bins.iter().max().cloned().unwrap_or(0) + 1),
+        )?;
+    chart
+        .configure_mesh()
+        .x_desc("residual")
+        .y_desc("row count")
+        .draw()?;
+    chart.draw_series(bins.iter().enumerate().map(|(index, count)| {
+        let start = range.start + index as f64 * bin_width;
+        Rectangle::new(
+            [(start, 0), (start + bin_width, *count)],
+            RED.mix(0.6).filled(),
+        )
+    }))?;
+    root.present()?;
+    Ok(())
+}
+
+pub fn draw_learning_curves(
+    output: &PlotOutput,
+    training_costs: &[f64],
+    validation_costs: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(
+        output,
+        draw_learning_curves_on(training_costs, validation_costs)
+    )
+}
+
+pub fn draw_learning_curves_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    training_costs: &[f64],
+    validation_costs: &[f64],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Learning curves", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(
+            0..training_costs.len().max(validation_costs.len()),
+            get_range(training_costs.iter().chain(validation_costs.iter())),
+        )?;
+    chart
+        .configure_mesh()
+        .x_desc("iteration")
+        .y_desc("cost of function")
+        .draw()?;
+    chart
+        .draw_series(LineSeries::new(
+            training_costs.iter().cloned().enumerate(),
+            RED,
+        ))?
+        .label("training")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+    chart
+        .draw_series(LineSeries::new(
+            validation_costs.iter().cloned().enumerate(),
+            BLUE,
+        ))?
+        .label("validation")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    root.present()?;
+    Ok(())
+}
+
+/// Horizontal bars, one per feature. For a linear model trained on z-score
+/// normalized features the absolute parameters are comparable importances.
+pub fn draw_feature_importance(
+    output: &PlotOutput,
+    feature_names: &[&str],
+    importances: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(
+        output,
+        draw_feature_importance_on(feature_names, importances)
+    )
+}
+
+pub fn draw_feature_importance_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    feature_names: &[&str],
+    importances: &[f64],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let max_importance = importances
+        .iter()
+        .cloned()
+        .filter(|importance| importance.is_finite())
+        .fold(0.0, f64::max);
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Feature importance", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(100)
+        .build_cartesian_2d(
+            0.0..(max_importance * 1.1).max(f64::EPSILON),
+            (0..feature_names.len().saturating_sub(1).max(1)).into_segmented(),
+        )?;
+    chart
+        .configure_mesh()
+        .disable_y_mesh()
+        .y_labels(feature_names.len())
+        .y_label_formatter(&|value| match value {
+            SegmentValue::CenterOf(index) if *index < feature_names.len() => {
+                feature_names[*index].to_string()
+            }
+            _ => String::new(),
+        })
+        .x_desc("importance")
+        .draw()?;
+    chart.draw_series(importances.iter().enumerate().map(|(index, importance)| {
+        let mut bar = Rectangle::new(
+            [
+                (0.0, SegmentValue::Exact(index)),
+                (*importance, SegmentValue::Exact(index + 1)),
+            ],
+            RED.mix(0.6).filled(),
+        );
+        bar.set_margin(5, 5, 0, 0);
+        bar
+    }))?;
+    root.present()?;
+    Ok(())
+}
+
+/// Inertia against the cluster count; the bend of the curve suggests a
+/// cluster count.
+pub fn draw_elbow_plot(
+    output: &PlotOutput,
+    cluster_counts: &[usize],
+    inertias: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(output, draw_elbow_plot_on(cluster_counts, inertias))
+}
+
+pub fn draw_elbow_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    cluster_counts: &[usize],
+    inertias: &[f64],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    if cluster_counts.is_empty() || cluster_counts.len() != inertias.len() {
+        return Err("need one inertia per cluster count".into());
+    }
+    let max_cluster_count = cluster_counts.iter().cloned().max().unwrap_or(1);
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Elbow", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..max_cluster_count + 1, get_range(inertias.iter()))?;
+    chart
+        .configure_mesh()
+        .x_desc("cluster count")
+        .y_desc("inertia")
+        .draw()?;
+    let points = cluster_counts
+        .iter()
+        .cloned()
+        .zip(inertias.iter().cloned())
+        .collect::<Vec<_>>();
+    chart.draw_series(LineSeries::new(points.clone(), RED))?;
+    chart.draw_series(
+        points
+            .into_iter()
+            .map(|point| Circle::new(point, 3, RED.filled())),
+    )?;
+    root.present()?;
+    Ok(())
+}
+
+/// One bar per component and the cumulative ratio as a line.
+pub fn draw_explained_variance_plot(
+    output: &PlotOutput,
+    explained_variance_ratios: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(
+        output,
+        draw_explained_variance_plot_on(explained_variance_ratios)
+    )
+}
+
+pub fn draw_explained_variance_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    explained_variance_ratios: &[f64],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    if explained_variance_ratios.is_empty() {
+        return Err("no component to plot".into());
+    }
+    let component_count = explained_variance_ratios.len();
+    root.fill(&WHITE)?;
+    let mut chart = ChartBuilder::on(&root)
+        .margin(20)
+        .caption("Explained variance", ("sans-serif", 20))
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.5..component_count as f64 + 0.5, 0.0..1.05)?;
+    chart
+        .configure_mesh()
+        .x_labels(component_count.min(20))
+        .x_label_formatter(&|value| format!("{:.0}", value))
+        .x_desc("component")
+        .y_desc("explained variance ratio")
+        .draw()?;
+    chart
+        .draw_series(
+            explained_variance_ratios
+                .iter()
+                .enumerate()
+                .map(|(index, ratio)| {
+                    let x = index as f64 + 1.0;
+                    Rectangle::new([(x - 0.4, 0.0), (x + 0.4, *ratio)], RED.mix(0.6).filled())
+                }),
+        )?
+        .label("component")
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], RED.mix(0.6).filled()));
+    chart
+        .draw_series(LineSeries::new(
+            explained_variance_ratios
+                .iter()
+                .scan(0.0, |cumulative_ratio, ratio| {
+                    *cumulative_ratio += ratio;
+                    Some(*cumulative_ratio)
+                })
+                .enumerate()
+                .map(|(index, cumulative_ratio)| (index as f64 + 1.0, cumulative_ratio)),
+            BLUE,
+        ))?
+        .label("cumulative")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
     root.present()?;
     Ok(())
 }
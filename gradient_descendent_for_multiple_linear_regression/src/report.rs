@@ -0,0 +1,168 @@
+use std::fmt::Display;
+
+/// A self-contained HTML summary of one training run: hyperparameters,
+/// final metrics, dataset statistics and charts embedded as inline SVG.
+#[derive(Debug, Clone, Default)]
+pub struct TrainingReport {
+    pub title: String,
+    pub hyperparameters: Vec<(String, String)>,
+    pub metrics: Vec<(String, String)>,
+    pub dataset_statistics: Vec<(String, String)>,
+    /// `(title, svg document)` pairs, e.g. from `plot::render_svg`.
+    pub charts: Vec<(String, String)>,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn get_table_html(caption: &str, rows: &[(String, String)]) -> String {
+    if rows.is_empty() {
+        return String::new();
+    }
+    let rows_html = rows
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                escape_html(name),
+                escape_html(value)
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<h2>{}</h2>\n<table>\n{}</table>\n",
+        escape_html(caption),
+        rows_html
+    )
+}
+
+impl TrainingReport {
+    pub fn new(title: &str) -> TrainingReport {
+        TrainingReport {
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn add_hyperparameter(&mut self, name: &str, value: impl Display) {
+        self.hyperparameters
+            .push((name.to_string(), value.to_string()));
+    }
+
+    pub fn add_metric(&mut self, name: &str, value: impl Display) {
+        self.metrics.push((name.to_string(), value.to_string()));
+    }
+
+    pub fn add_dataset_statistic(&mut self, name: &str, value: impl Display) {
+        self.dataset_statistics
+            .push((name.to_string(), value.to_string()));
+    }
+
+    pub fn add_chart(&mut self, title: &str, svg: String) {
+        self.charts.push((title.to_string(), svg));
+    }
+
+    /// The page needs no external files, so it can be shared or archived on
+    /// its own.
+    pub fn to_html(&self) -> String {
+        let charts_html = self
+            .charts
+            .iter()
+            .map(|(title, svg)| {
+                format!(
+                    "<figure>\n{}\n<figcaption>{}</figcaption>\n</figure>\n",
+                    svg,
+                    escape_html(title)
+                )
+            })
+            .collect::<String>();
+        format!(
+            "<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+table {{ border-collapse: collapse; margin-bottom: 1em; }}
+th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}
+figure {{ display: inline-block; margin: 0 1em 1em 0; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{hyperparameters}{metrics}{dataset_statistics}{charts}</body>
+</html>
+",
+            title = escape_html(&self.title),
+            hyperparameters = get_table_html("Hyperparameters", &self.hyperparameters),
+            metrics = get_table_html("Metrics", &self.metrics),
+            dataset_statistics = get_table_html("Dataset", &self.dataset_statistics),
+            charts = if charts_html.is_empty() {
+                String::new()
+            } else {
+                format!("<h2>Charts</h2>\n{}", charts_html)
+            },
+        )
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(file_path, self.to_html())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_html_contains_every_section() {
+        let mut report = TrainingReport::new("Run");
+        report.add_hyperparameter("learning rate", 0.01);
+        report.add_metric("cost", 1.5);
+        report.add_dataset_statistic("rows", 100);
+        report.add_chart("cost history", "<svg></svg>".to_string());
+
+        let html = report.to_html();
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>Run</title>"));
+        assert!(html.contains("<h1>Run</h1>"));
+        assert!(html.contains("<h2>Hyperparameters</h2>"));
+        assert!(html.contains("<tr><th>learning rate</th><td>0.01</td></tr>"));
+        assert!(html.contains("<h2>Metrics</h2>"));
+        assert!(html.contains("<tr><th>cost</th><td>1.5</td></tr>"));
+        assert!(html.contains("<h2>Dataset</h2>"));
+        assert!(html.contains("<tr><th>rows</th><td>100</td></tr>"));
+        assert!(html.contains("<h2>Charts</h2>"));
+        assert!(html
+            .contains("<figure>\n<svg></svg>\n<figcaption>cost history</figcaption>\n</figure>"));
+    }
+
+    #[test]
+    fn to_html_leaves_out_empty_sections() {
+        let html = TrainingReport::new("Run").to_html();
+
+        assert!(!html.contains("<h2>"));
+        assert!(!html.contains("<table>"));
+    }
+
+    #[test]
+    fn to_html_escapes_the_title_and_table_cells() {
+        let mut report = TrainingReport::new("<b>cost & \"loss\"</b>");
+        report.add_metric("a<b", "c>d");
+
+        let html = report.to_html();
+
+        let escaped_title = "&lt;b&gt;cost &amp; &quot;loss&quot;&lt;/b&gt;";
+        assert!(html.contains(&format!("<title>{}</title>", escaped_title)));
+        assert!(html.contains(&format!("<h1>{}</h1>", escaped_title)));
+        assert!(html.contains("<tr><th>a&lt;b</th><td>c&gt;d</td></tr>"));
+        assert!(!html.contains("<b>cost"));
+    }
+}
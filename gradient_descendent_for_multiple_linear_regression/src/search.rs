@@ -0,0 +1,467 @@
+use crate::model::EstimationModel;
+use crate::training::{get_cost, train, FeaturesSet, Hyperparameters};
+use nalgebra::{MatrixXx1, RowDVector};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::SeedableRng;
+
+/// Candidate values for every hyperparameter. The grid is their cartesian
+/// product.
+#[derive(Debug, Clone)]
+pub struct SearchSpace {
+    pub learning_rates: Vec<f64>,
+    pub iteration_counts: Vec<usize>,
+    pub regularization_strengths: Vec<f64>,
+    pub batch_sizes: Vec<Option<usize>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SearchStrategy {
+    /// Every combination of the search space.
+    Grid,
+    /// `trial_count` distinct combinations drawn from the grid.
+    Random { trial_count: usize, seed: u64 },
+    /// Draws `candidate_count` combinations, trains them with a fraction of
+    /// their iteration count and keeps the better half for the next round,
+    /// doubling the fraction until the survivors run their full count.
+    SuccessiveHalving { candidate_count: usize, seed: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Trial {
+    pub hyperparameters: Hyperparameters,
+    /// Unregularized validation cost averaged over the folds.
+    pub validation_cost: f64,
+    /// Training cost of each iteration averaged over the folds.
+    pub costs: Vec<f64>,
+    /// Successive halving round the trial ran in, 0 for the other strategies.
+    pub round: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    /// Every trial run, from the last round to the first and within a round
+    /// from the lowest validation cost to the highest. The first trial is the
+    /// best one.
+    pub trials: Vec<Trial>,
+    /// Model trained on the whole training set with the best trial's
+    /// hyperparameters.
+    pub best_estimation_model: EstimationModel,
+}
+
+impl SearchSpace {
+    pub fn get_grid(&self) -> Vec<Hyperparameters> {
+        let mut grid = Vec::new();
+        for learning_rate in &self.learning_rates {
+            for iteration_count in &self.iteration_counts {
+                for regularization_strength in &self.regularization_strengths {
+                    for batch_size in &self.batch_sizes {
+                        grid.push(Hyperparameters {
+                            learning_rate: *learning_rate,
+                            iteration_count: *iteration_count,
+                            regularization_strength: *regularization_strength,
+                            batch_size: *batch_size,
+                            shuffle_seed: None,
+                        });
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    fn sample_grid(&self, count: usize, seed: u64) -> Vec<Hyperparameters> {
+        let grid = self.get_grid();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut indices = sample(&mut rng, grid.len(), count.min(grid.len())).into_vec();
+        indices.sort_unstable();
+        indices.into_iter().map(|index| grid[index]).collect()
+    }
+}
+
+/// Scores hyperparameters with k-fold cross-validation. Row `i` belongs to
+/// fold `i % fold_count`. Every fold needs at least one row, and at least two
+/// folds are needed so that every model trains on some rows.
+pub fn cross_validate<F: FeaturesSet>(
+    features_set: &F,
+    target_set: &MatrixXx1<f64>,
+    hyperparameters: &Hyperparameters,
+    fold_count: usize,
+) -> Result<Trial, Box<dyn std::error::Error>> {
+    if fold_count < 2 {
+        return Err(format!("fold count must be at least 2, got {}", fold_count).into());
+    }
+    if features_set.get_row_count() < fold_count {
+        return Err(format!(
+            "{} rows cannot be split into {} folds",
+            features_set.get_row_count(),
+            fold_count
+        )
+        .into());
+    }
+    let initial = EstimationModel {
+        parameters: RowDVector::zeros(features_set.get_column_count()),
+        b: 0.0,
+    };
+    let mut validation_cost = 0.0;
+    let mut costs = vec![0.0; hyperparameters.iteration_count];
+
+    for fold in 0..fold_count {
+        let (validation_rows, training_rows): (Vec<usize>, Vec<usize>) =
+            (0..features_set.get_row_count()).partition(|index| index % fold_count == fold);
+        let (estimation_model, fold_costs) = train(
+            &features_set.select_rows(&training_rows),
+            &target_set.select_rows(&training_rows),
+            &initial,
+            hyperparameters,
+        );
+        validation_cost += get_cost(
+            &features_set.select_rows(&validation_rows),
+            &target_set.select_rows(&validation_rows),
+            &estimation_model,
+        );
+        for (cost, fold_cost) in costs.iter_mut().zip(fold_costs) {
+            *cost += fold_cost;
+        }
+    }
+
+    Ok(Trial {
+        hyperparameters: *hyperparameters,
+        validation_cost: validation_cost / fold_count as f64,
+        costs: costs.iter().map(|cost| cost / fold_count as f64).collect(),
+        round: 0,
+    })
+}
+
+/// NaN costs from diverged runs sort last.
+fn compare_trials(a: &Trial, b: &Trial) -> std::cmp::Ordering {
+    a.validation_cost
+        .is_nan()
+        .cmp(&b.validation_cost.is_nan())
+        .then(a.validation_cost.total_cmp(&b.validation_cost))
+}
+
+pub fn search<F: FeaturesSet>(
+    features_set: &F,
+    target_set: &MatrixXx1<f64>,
+    search_space: &SearchSpace,
+    strategy: SearchStrategy,
+    fold_count: usize,
+) -> Result<SearchResult, Box<dyn std::error::Error>> {
+    let mut trials = match strategy {
+        SearchStrategy::Grid => search_space
+            .get_grid()
+            .iter()
+            .map(|hyperparameters| {
+                cross_validate(features_set, target_set, hyperparameters, fold_count)
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        SearchStrategy::Random { trial_count, seed } => search_space
+            .sample_grid(trial_count, seed)
+            .iter()
+            .map(|hyperparameters| {
+                cross_validate(features_set, target_set, hyperparameters, fold_count)
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        SearchStrategy::SuccessiveHalving {
+            candidate_count,
+            seed,
+        } => successive_halving(
+            features_set,
+            target_set,
+            search_space.sample_grid(candidate_count, seed),
+            fold_count,
+        )?,
+    };
+    if trials.is_empty() {
+        return Err("search space has no hyperparameter combination".into());
+    }
+    // Earlier successive halving rounds ran fewer iterations, so their costs
+    // are not comparable with those of the final round.
+    trials.sort_by(|a, b| b.round.cmp(&a.round).then(compare_trials(a, b)));
+
+    let (best_estimation_model, _) = train(
+        features_set,
+        target_set,
+        &EstimationModel {
+            parameters: RowDVector::zeros(features_set.get_column_count()),
+            b: 0.0,
+        },
+        &trials[0].hyperparameters,
+    );
+    Ok(SearchResult {
+        trials,
+        best_estimation_model,
+    })
+}
+
+/// Every round is recorded as its own trial with its round and the iteration
+/// count it actually ran. The last round runs the full iteration count.
+fn successive_halving<F: FeaturesSet>(
+    features_set: &F,
+    target_set: &MatrixXx1<f64>,
+    mut candidates: Vec<Hyperparameters>,
+    fold_count: usize,
+) -> Result<Vec<Trial>, Box<dyn std::error::Error>> {
+    let mut trials = Vec::new();
+    let round_count = (candidates.len().max(1) as f64).log2().ceil() as u32 + 1;
+    for round in 0..round_count {
+        let mut round_trials = candidates
+            .iter()
+            .map(|candidate| {
+                let hyperparameters = Hyperparameters {
+                    iteration_count: (candidate.iteration_count >> (round_count - 1 - round))
+                        .max(1),
+                    ..*candidate
+                };
+                let trial = Trial {
+                    round: round as usize,
+                    ..cross_validate(features_set, target_set, &hyperparameters, fold_count)?
+                };
+                Ok((*candidate, trial))
+            })
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+        round_trials.sort_by(|(_, a), (_, b)| compare_trials(a, b));
+
+        candidates = round_trials
+            .iter()
+            .take(candidates.len() - candidates.len() / 2)
+            .map(|(candidate, _)| *candidate)
+            .collect();
+        trials.extend(round_trials.into_iter().map(|(_, trial)| trial));
+    }
+    Ok(trials)
+}
+
+impl SearchResult {
+    pub fn get_best_trial(&self) -> &Trial {
+        &self.trials[0]
+    }
+
+    /// Plain-text table of the trials in the order of `trials`.
+    pub fn get_results_table(&self) -> String {
+        let mut table = format!(
+            "{:>4}  {:>5}  {:>13}  {:>10}  {:>10}  {:>6}  {:>15}\n",
+            "rank", "round", "learning rate", "iterations", "L2", "batch", "validation cost"
+        );
+        for (index, trial) in self.trials.iter().enumerate() {
+            let hyperparameters = &trial.hyperparameters;
+            table.push_str(&format!(
+                "{:>4}  {:>5}  {:>13}  {:>10}  {:>10}  {:>6}  {:>15.6}\n",
+                index + 1,
+                trial.round,
+                hyperparameters.learning_rate,
+                hyperparameters.iteration_count,
+                hyperparameters.regularization_strength,
+                hyperparameters
+                    .batch_size
+                    .map_or("full".to_string(), |batch_size| batch_size.to_string()),
+                trial.validation_cost
+            ));
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::DMatrix;
+
+    fn get_training_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let features_set = DMatrix::from_fn(40, 2, |row, column| {
+            ((row * (column + 2)) % 11) as f64 / 5.0 - 1.0
+        });
+        let target_set = MatrixXx1::from_fn(40, |row, _| {
+            1.5 * features_set[(row, 0)] - 0.5 * features_set[(row, 1)] + 2.0
+        });
+        (features_set, target_set)
+    }
+
+    fn get_search_space() -> SearchSpace {
+        SearchSpace {
+            learning_rates: vec![1.0e-2, 1.0e-1],
+            iteration_counts: vec![16],
+            regularization_strengths: vec![0.0, 1.0e-1],
+            batch_sizes: vec![None, Some(8)],
+        }
+    }
+
+    #[test]
+    fn grid_search_ranks_every_combination() {
+        let (features_set, target_set) = get_training_set();
+        let search_space = get_search_space();
+        let search_result = search(
+            &features_set,
+            &target_set,
+            &search_space,
+            SearchStrategy::Grid,
+            4,
+        )
+        .unwrap();
+
+        let grid = search_space.get_grid();
+        assert_eq!(grid.len(), 8);
+        assert_eq!(search_result.trials.len(), grid.len());
+        for hyperparameters in &grid {
+            let trial = search_result
+                .trials
+                .iter()
+                .find(|trial| trial.hyperparameters == *hyperparameters)
+                .unwrap();
+            assert_eq!(trial.round, 0);
+            assert_eq!(
+                trial.validation_cost,
+                cross_validate(&features_set, &target_set, hyperparameters, 4)
+                    .unwrap()
+                    .validation_cost
+            );
+        }
+        for pair in search_result.trials.windows(2) {
+            assert!(pair[0].validation_cost <= pair[1].validation_cost);
+        }
+
+        let best_hyperparameters = search_result.get_best_trial().hyperparameters;
+        assert_eq!(best_hyperparameters.learning_rate, 1.0e-1);
+        assert_eq!(best_hyperparameters.regularization_strength, 0.0);
+        let (estimation_model, _) = train(
+            &features_set,
+            &target_set,
+            &EstimationModel {
+                parameters: RowDVector::zeros(2),
+                b: 0.0,
+            },
+            &best_hyperparameters,
+        );
+        assert_eq!(
+            search_result.best_estimation_model.parameters,
+            estimation_model.parameters
+        );
+        assert_eq!(search_result.best_estimation_model.b, estimation_model.b);
+    }
+
+    #[test]
+    fn random_search_draws_the_same_trials_for_the_same_seed() {
+        let (features_set, target_set) = get_training_set();
+        let search_space = get_search_space();
+        let strategy = SearchStrategy::Random {
+            trial_count: 3,
+            seed: 7,
+        };
+        let search_result = search(&features_set, &target_set, &search_space, strategy, 4).unwrap();
+        let repeated_search_result =
+            search(&features_set, &target_set, &search_space, strategy, 4).unwrap();
+
+        assert_eq!(search_result.trials.len(), 3);
+        let grid = search_space.get_grid();
+        for (index, trial) in search_result.trials.iter().enumerate() {
+            assert!(grid.contains(&trial.hyperparameters));
+            assert!(search_result.trials[..index]
+                .iter()
+                .all(|earlier_trial| earlier_trial.hyperparameters != trial.hyperparameters));
+            let repeated_trial = &repeated_search_result.trials[index];
+            assert_eq!(trial.hyperparameters, repeated_trial.hyperparameters);
+            assert_eq!(trial.validation_cost, repeated_trial.validation_cost);
+            assert_eq!(trial.costs, repeated_trial.costs);
+        }
+        assert_eq!(
+            search_result.best_estimation_model.parameters,
+            repeated_search_result.best_estimation_model.parameters
+        );
+
+        let whole_grid_result = search(
+            &features_set,
+            &target_set,
+            &search_space,
+            SearchStrategy::Random {
+                trial_count: 100,
+                seed: 7,
+            },
+            4,
+        )
+        .unwrap();
+        assert_eq!(whole_grid_result.trials.len(), grid.len());
+    }
+
+    #[test]
+    fn cross_validation_rejects_folds_without_rows() {
+        let (features_set, target_set) = get_training_set();
+        let hyperparameters = get_search_space().get_grid()[0];
+
+        assert!(cross_validate(&features_set, &target_set, &hyperparameters, 40).is_ok());
+        assert!(cross_validate(&features_set, &target_set, &hyperparameters, 41).is_err());
+        assert!(cross_validate(&features_set, &target_set, &hyperparameters, 1).is_err());
+        let one_row = [0];
+        assert!(cross_validate(
+            &features_set.select_rows(&one_row),
+            &target_set.select_rows(&one_row),
+            &hyperparameters,
+            2
+        )
+        .is_err());
+        assert!(search(
+            &features_set.select_rows(&one_row),
+            &target_set.select_rows(&one_row),
+            &get_search_space(),
+            SearchStrategy::Grid,
+            2
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn successive_halving_picks_the_final_round_survivor() {
+        let (features_set, target_set) = get_training_set();
+        let search_space = SearchSpace {
+            learning_rates: vec![1.0e-3, 1.0e-2, 3.0e-2, 1.0e-1, 3.0e-1],
+            iteration_counts: vec![64],
+            regularization_strengths: vec![0.0],
+            batch_sizes: vec![None],
+        };
+        let search_result = search(
+            &features_set,
+            &target_set,
+            &search_space,
+            SearchStrategy::SuccessiveHalving {
+                candidate_count: 5,
+                seed: 0,
+            },
+            2,
+        )
+        .unwrap();
+
+        // 5, 3, 2 and 1 candidates on 8, 16, 32 and 64 iterations.
+        for (round, (candidate_count, iteration_count)) in
+            [(5, 8), (3, 16), (2, 32), (1, 64)].into_iter().enumerate()
+        {
+            let round_trials = search_result
+                .trials
+                .iter()
+                .filter(|trial| trial.round == round)
+                .collect::<Vec<_>>();
+            assert_eq!(round_trials.len(), candidate_count);
+            for trial in round_trials {
+                assert_eq!(trial.hyperparameters.iteration_count, iteration_count);
+                assert_eq!(trial.costs.len(), iteration_count);
+            }
+        }
+        let best_trial = search_result.get_best_trial();
+        assert_eq!(best_trial.round, 3);
+        assert_eq!(best_trial.hyperparameters.iteration_count, 64);
+        // The survivor ran in every round, and with more iterations it beats
+        // any reduced-budget trial of its own.
+        for round in 0..3 {
+            let earlier_trial = search_result
+                .trials
+                .iter()
+                .find(|trial| {
+                    trial.round == round
+                        && trial.hyperparameters.learning_rate
+                            == best_trial.hyperparameters.learning_rate
+                })
+                .unwrap();
+            assert!(best_trial.validation_cost < earlier_trial.validation_cost);
+        }
+        assert_eq!(search_result.trials[0].round, 3);
+    }
+}
@@ -0,0 +1,175 @@
+//! CSR sparse feature sets, for one-hot encoded or other mostly-zero columns.
+//! `CsrMatrix` implements `training::FeaturesSet`, so the estimates, the cost
+//! and gradient functions, `train` and `search` accept it in place of a
+//! `DMatrix`. Only the stored entries of each row are visited. Dense rows are
+//! summed in column order too and adding a zero product leaves a sum
+//! unchanged, so the dense and sparse paths give identical results.
+//!
+//! Sparse feature sets should not be z-score normalized: subtracting the mean
+//! turns every zero into a non-zero and the matrix becomes dense again.
+//! Normalize the numeric columns before converting instead.
+
+use crate::model::EstimationModel;
+use crate::training::FeaturesSet;
+use nalgebra::{DMatrix, RowDVector};
+use nalgebra_sparse::CsrMatrix;
+
+pub fn to_sparse_features_set(features_set: &DMatrix<f64>) -> CsrMatrix<f64> {
+    CsrMatrix::from(features_set)
+}
+
+impl FeaturesSet for CsrMatrix<f64> {
+    fn get_row_count(&self) -> usize {
+        self.nrows()
+    }
+
+    fn get_column_count(&self) -> usize {
+        self.ncols()
+    }
+
+    fn estimate_row(&self, row: usize, estimation_model: &EstimationModel) -> f64 {
+        let features = self.row(row);
+        features
+            .col_indices()
+            .iter()
+            .zip(features.values())
+            .map(|(column, feature)| estimation_model.parameters[*column] * feature)
+            .sum::<f64>()
+            + estimation_model.b
+    }
+
+    fn add_scaled_row(&self, row: usize, scale: f64, parameter_gradients: &mut RowDVector<f64>) {
+        let features = self.row(row);
+        for (column, feature) in features.col_indices().iter().zip(features.values()) {
+            parameter_gradients[*column] += scale * feature;
+        }
+    }
+
+    fn select_rows(&self, rows: &[usize]) -> Self {
+        let mut row_offsets = vec![0];
+        let mut column_indices = Vec::new();
+        let mut values = Vec::new();
+        for row in rows {
+            let features = self.row(*row);
+            column_indices.extend_from_slice(features.col_indices());
+            values.extend_from_slice(features.values());
+            row_offsets.push(column_indices.len());
+        }
+        CsrMatrix::try_from_csr_data(
+            rows.len(),
+            self.ncols(),
+            row_offsets,
+            column_indices,
+            values,
+        )
+        .expect("rows of a CSR matrix form a valid CSR matrix")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{search, SearchSpace, SearchStrategy};
+    use crate::training::{get_cost, get_estimates, get_gradient, train, Hyperparameters};
+    use nalgebra::MatrixXx1;
+
+    /// One-hot columns of three districts next to a numeric column.
+    fn get_training_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let features_set = DMatrix::from_row_slice(
+            6,
+            4,
+            &[
+                [0.5, 1.0, 0.0, 0.0],
+                [-1.5, 0.0, 1.0, 0.0],
+                [2.0, 0.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0, 1.0],
+                [-0.5, 0.0, 1.0, 0.0],
+            ]
+            .concat(),
+        );
+        let target_set = MatrixXx1::from_vec(vec![3.0, -1.0, 4.5, 2.0, 3.5, 0.0]);
+        (features_set, target_set)
+    }
+
+    #[test]
+    fn sparse_training_matches_dense_training() {
+        let (features_set, target_set) = get_training_set();
+        let sparse_features_set = to_sparse_features_set(&features_set);
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_vec(vec![0.3, -0.2, 1.1, 0.7]),
+            b: 0.4,
+        };
+        assert_eq!(
+            get_estimates(&sparse_features_set, &estimation_model),
+            get_estimates(&features_set, &estimation_model)
+        );
+        assert_eq!(
+            get_cost(&sparse_features_set, &target_set, &estimation_model),
+            get_cost(&features_set, &target_set, &estimation_model)
+        );
+        let sparse_gradient = get_gradient(&sparse_features_set, &target_set, &estimation_model);
+        let dense_gradient = get_gradient(&features_set, &target_set, &estimation_model);
+        assert_eq!(sparse_gradient.parameters, dense_gradient.parameters);
+        assert_eq!(sparse_gradient.b, dense_gradient.b);
+
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(4),
+            b: 0.0,
+        };
+        let hyperparameters = Hyperparameters {
+            learning_rate: 0.1,
+            iteration_count: 200,
+            regularization_strength: 0.01,
+            batch_size: Some(4),
+            shuffle_seed: Some(5),
+        };
+        let (sparse_result, sparse_costs) = train(
+            &sparse_features_set,
+            &target_set,
+            &initial,
+            &hyperparameters,
+        );
+        let (dense_result, dense_costs) =
+            train(&features_set, &target_set, &initial, &hyperparameters);
+        assert_eq!(sparse_result.parameters, dense_result.parameters);
+        assert_eq!(sparse_result.b, dense_result.b);
+        assert_eq!(sparse_costs, dense_costs);
+    }
+
+    #[test]
+    fn sparse_search_matches_dense_search() {
+        let (features_set, target_set) = get_training_set();
+        let search_space = SearchSpace {
+            learning_rates: vec![0.03, 0.1],
+            iteration_counts: vec![50],
+            regularization_strengths: vec![0.0, 0.1],
+            batch_sizes: vec![None, Some(2)],
+        };
+        let sparse_result = search(
+            &to_sparse_features_set(&features_set),
+            &target_set,
+            &search_space,
+            SearchStrategy::Grid,
+            3,
+        )
+        .unwrap();
+        let dense_result = search(
+            &features_set,
+            &target_set,
+            &search_space,
+            SearchStrategy::Grid,
+            3,
+        )
+        .unwrap();
+        for (sparse_trial, dense_trial) in sparse_result.trials.iter().zip(&dense_result.trials) {
+            assert_eq!(sparse_trial.hyperparameters, dense_trial.hyperparameters);
+            assert_eq!(sparse_trial.validation_cost, dense_trial.validation_cost);
+            assert_eq!(sparse_trial.costs, dense_trial.costs);
+        }
+        assert_eq!(
+            sparse_result.best_estimation_model.parameters,
+            dense_result.best_estimation_model.parameters
+        );
+    }
+}
@@ -1,12 +1,27 @@
+use crate::autodiff::{Tape, Tensor};
+use crate::callback::{Callback, CallbackControl, TrainingState};
+use crate::checkpoint::TrainingCheckpoint;
+use crate::data_loader::StreamingTrainingSet;
 use crate::model::{EstimationModel, Gradient};
 use nalgebra::{DMatrix, MatrixXx1, RowDVector};
 use num_traits::pow;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
 use std::ops::{Div, DivAssign};
 
 pub fn estimate(features: &RowDVector<f64>, estimation_model: &EstimationModel) -> f64 {
     estimation_model.parameters.dot(&features) + estimation_model.b
 }
 
+pub fn get_estimates(
+    features_set: &impl FeaturesSet,
+    estimation_model: &EstimationModel,
+) -> Vec<f64> {
+    (0..features_set.get_row_count())
+        .map(|row| features_set.estimate_row(row, estimation_model))
+        .collect()
+}
+
 pub fn get_row_error(
     features: &RowDVector<f64>,
     target: &f64,
@@ -15,47 +30,117 @@ pub fn get_row_error(
     estimate(features, estimation_model) - target
 }
 
-fn get_cost_of_rows_1(
-    features_set: &DMatrix<f64>,
+/// Rows of features that the estimates, the cost and gradient functions,
+/// `train` and `search` visit: a dense `DMatrix`, or with the `sparse` feature
+/// a CSR matrix that only visits the stored entries of each row.
+pub trait FeaturesSet: Sync {
+    fn get_row_count(&self) -> usize;
+
+    fn get_column_count(&self) -> usize;
+
+    /// `estimate` of one row, with the products summed in column order.
+    fn estimate_row(&self, row: usize, estimation_model: &EstimationModel) -> f64;
+
+    /// Adds `scale` times one row to `parameter_gradients`.
+    fn add_scaled_row(&self, row: usize, scale: f64, parameter_gradients: &mut RowDVector<f64>);
+
+    /// The given rows in the given order, for mini-batches and folds.
+    fn select_rows(&self, rows: &[usize]) -> Self
+    where
+        Self: Sized;
+}
+
+impl FeaturesSet for DMatrix<f64> {
+    fn get_row_count(&self) -> usize {
+        self.nrows()
+    }
+
+    fn get_column_count(&self) -> usize {
+        self.ncols()
+    }
+
+    /// Not `estimate`: `dot` sums in an unrolled order, and summing in column
+    /// order keeps the result identical to that of a sparse row.
+    fn estimate_row(&self, row: usize, estimation_model: &EstimationModel) -> f64 {
+        self.row(row)
+            .iter()
+            .zip(estimation_model.parameters.iter())
+            .map(|(feature, parameter)| parameter * feature)
+            .sum::<f64>()
+            + estimation_model.b
+    }
+
+    fn add_scaled_row(&self, row: usize, scale: f64, parameter_gradients: &mut RowDVector<f64>) {
+        *parameter_gradients += scale * self.row(row);
+    }
+
+    fn select_rows(&self, rows: &[usize]) -> Self {
+        DMatrix::select_rows(self, rows)
+    }
+}
+
+/// Rows are summed in chunks of this fixed size and the chunk sums are then
+/// added in chunk order. The serial and the parallel path both do this, so
+/// they give bit-identical results whatever the thread count.
+pub const CHUNK_ROW_COUNT: usize = 1024;
+
+/// `(start, length)` of every chunk of rows.
+pub(crate) fn get_chunk_ranges(row_count: usize) -> Vec<(usize, usize)> {
+    (0..row_count)
+        .step_by(CHUNK_ROW_COUNT)
+        .map(|start| (start, CHUNK_ROW_COUNT.min(row_count - start)))
+        .collect()
+}
+
+/// Sum of the squared errors of the rows of one chunk.
+pub(crate) fn get_chunk_cost(
+    features_set: &impl FeaturesSet,
     target_set: &MatrixXx1<f64>,
     estimation_model: &EstimationModel,
+    (start, length): (usize, usize),
 ) -> f64 {
-    let row_count = features_set.len() / estimation_model.parameters.len();
-    let total_cost_of_rows = features_set
-        .row_iter()
-        .enumerate()
-        .map(|(index, features)| {
+    (start..start + length)
+        .map(|row| {
             pow(
-                get_row_error(
-                    &RowDVector::from(features),
-                    &target_set[index],
-                    estimation_model,
-                ),
+                features_set.estimate_row(row, estimation_model) - target_set[row],
                 2,
             )
         })
-        .sum::<f64>();
-    total_cost_of_rows / (2.0 * row_count as f64)
+        .sum::<f64>()
 }
 
-pub fn get_gradient(
-    features_set: &DMatrix<f64>,
+/// Sum of the gradients of the rows of one chunk.
+pub(crate) fn get_chunk_gradient(
+    features_set: &impl FeaturesSet,
     target_set: &MatrixXx1<f64>,
     estimation_model: &EstimationModel,
+    (start, length): (usize, usize),
 ) -> Gradient {
-    let row_count = features_set.len() / estimation_model.parameters.len();
     let mut parameter_gradients = RowDVector::zeros(estimation_model.parameters.len());
     let mut b_gratient = 0.0;
-
-    for (index, features) in features_set.row_iter().enumerate() {
-        let cost_of_row = get_row_error(
-            &RowDVector::from(features),
-            &target_set[index],
-            estimation_model,
-        );
-        parameter_gradients += cost_of_row * features;
+    for row in start..start + length {
+        let cost_of_row = features_set.estimate_row(row, estimation_model) - target_set[row];
+        features_set.add_scaled_row(row, cost_of_row, &mut parameter_gradients);
         b_gratient += cost_of_row;
     }
+    Gradient {
+        parameters: parameter_gradients,
+        b: b_gratient,
+    }
+}
+
+/// Adds the chunk gradients in chunk order and averages them over the rows.
+pub(crate) fn get_mean_gradient(
+    partial_gradients: Vec<Gradient>,
+    parameter_count: usize,
+    row_count: usize,
+) -> Gradient {
+    let mut parameter_gradients = RowDVector::zeros(parameter_count);
+    let mut b_gratient = 0.0;
+    for partial_gradient in partial_gradients {
+        parameter_gradients += partial_gradient.parameters;
+        b_gratient += partial_gradient.b;
+    }
     parameter_gradients /= row_count as f64;
     b_gratient /= row_count as f64;
 
@@ -65,9 +150,114 @@ pub fn get_gradient(
     }
 }
 
-pub fn gradient_descent(
+pub fn get_cost_of_rows_1(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<f64>,
+    estimation_model: &EstimationModel,
+) -> f64 {
+    let row_count = features_set.get_row_count();
+    let partial_costs = get_chunk_ranges(row_count)
+        .into_iter()
+        .map(|range| get_chunk_cost(features_set, target_set, estimation_model, range))
+        .collect::<Vec<f64>>();
+    let total_cost_of_rows = partial_costs.iter().sum::<f64>();
+    total_cost_of_rows / (2.0 * row_count as f64)
+}
+
+pub fn get_cost(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<f64>,
+    estimation_model: &EstimationModel,
+) -> f64 {
+    #[cfg(feature = "parallel")]
+    return crate::parallel::get_cost_of_rows_1(features_set, target_set, estimation_model);
+    #[cfg(not(feature = "parallel"))]
+    get_cost_of_rows_1(features_set, target_set, estimation_model)
+}
+
+pub fn get_gradient(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<f64>,
+    estimation_model: &EstimationModel,
+) -> Gradient {
+    #[cfg(feature = "parallel")]
+    return crate::parallel::get_gradient(features_set, target_set, estimation_model);
+    #[cfg(not(feature = "parallel"))]
+    get_serial_gradient(features_set, target_set, estimation_model)
+}
+
+pub fn get_serial_gradient(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<f64>,
+    estimation_model: &EstimationModel,
+) -> Gradient {
+    let partial_gradients = get_chunk_ranges(features_set.get_row_count())
+        .into_iter()
+        .map(|range| get_chunk_gradient(features_set, target_set, estimation_model, range))
+        .collect::<Vec<Gradient>>();
+    get_mean_gradient(
+        partial_gradients,
+        estimation_model.parameters.len(),
+        features_set.get_row_count(),
+    )
+}
+
+/// `get_cost` and `get_gradient` re-expressed on an autodiff tape.
+pub fn get_autodiff_cost_and_gradient(
     features_set: &DMatrix<f64>,
     target_set: &MatrixXx1<f64>,
+    estimation_model: &EstimationModel,
+) -> (f64, Gradient) {
+    let tape = Tape::new();
+    let parameters = tape.var(Tensor::from_column_slice(
+        estimation_model.parameters.len(),
+        1,
+        estimation_model.parameters.as_slice(),
+    ));
+    let b = tape.scalar(estimation_model.b);
+    let targets = tape.var(Tensor::from_column_slice(
+        target_set.len(),
+        1,
+        target_set.as_slice(),
+    ));
+    let errors = tape.var(features_set.clone()).matmul(parameters) + b - targets;
+    let cost = errors.component_mul(errors).mean().scale(0.5);
+
+    let gradients = tape.get_gradients(cost);
+    (
+        cost.get_value()[(0, 0)],
+        Gradient {
+            parameters: RowDVector::from_row_slice(gradients.get(parameters).as_slice()),
+            b: gradients.get(b)[(0, 0)],
+        },
+    )
+}
+
+pub fn gradient_descent(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<f64>,
+    initial_estimation_model: &EstimationModel,
+    learning_rate: f64,
+    iteration_count: usize,
+) -> (EstimationModel, Vec<f64>) {
+    gradient_descent_with(
+        |estimation_model| {
+            (
+                get_cost(features_set, target_set, estimation_model),
+                get_gradient(features_set, target_set, estimation_model),
+            )
+        },
+        initial_estimation_model,
+        learning_rate,
+        iteration_count,
+    )
+}
+
+/// `gradient_descent` with any cost and gradient, such as
+/// `get_autodiff_cost_and_gradient` to train on the autodiff tape. Returns the
+/// cost before every iteration.
+pub fn gradient_descent_with(
+    get_cost_and_gradient: impl Fn(&EstimationModel) -> (f64, Gradient),
     initial_estimation_model: &EstimationModel,
     learning_rate: f64,
     iteration_count: usize,
@@ -75,9 +265,8 @@ pub fn gradient_descent(
     let mut costs: Vec<f64> = Vec::new();
     let mut estimation_model = initial_estimation_model.clone();
 
-    for _ in 0..iteration_count.clone() {
-        let gradient = get_gradient(features_set, target_set, &estimation_model);
-        let cost = get_cost_of_rows_1(features_set, target_set, &estimation_model);
+    for _ in 0..iteration_count {
+        let (cost, gradient) = get_cost_and_gradient(&estimation_model);
         costs.push(cost);
 
         estimation_model = EstimationModel {
@@ -89,6 +278,282 @@ pub fn gradient_descent(
     (estimation_model, costs)
 }
 
+/// Settings of one training run, as tuned by `search`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hyperparameters {
+    pub learning_rate: f64,
+    pub iteration_count: usize,
+    /// Strength of the L2 penalty on the parameters. `b` is not penalized.
+    pub regularization_strength: f64,
+    /// Rows per update; `None` updates once per iteration on the whole
+    /// training set.
+    pub batch_size: Option<usize>,
+    /// Shuffles the rows before every iteration with this seed; `None` takes
+    /// them in order.
+    pub shuffle_seed: Option<u64>,
+}
+
+impl std::fmt::Display for Hyperparameters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "learning rate {}, {} iterations, L2 {}, batch {}",
+            self.learning_rate,
+            self.iteration_count,
+            self.regularization_strength,
+            match self.batch_size {
+                Some(batch_size) => batch_size.to_string(),
+                None => "full".to_string(),
+            }
+        )
+    }
+}
+
+/// Cost plus the L2 penalty `regularization_strength / 2 * |parameters|^2`.
+pub fn get_regularized_cost(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<f64>,
+    estimation_model: &EstimationModel,
+    regularization_strength: f64,
+) -> f64 {
+    get_cost(features_set, target_set, estimation_model)
+        + regularization_strength / 2.0 * estimation_model.parameters.norm_squared()
+}
+
+/// Gradient of `get_regularized_cost`.
+pub fn get_regularized_gradient(
+    features_set: &impl FeaturesSet,
+    target_set: &MatrixXx1<f64>,
+    estimation_model: &EstimationModel,
+    regularization_strength: f64,
+) -> Gradient {
+    let gradient = get_gradient(features_set, target_set, estimation_model);
+    Gradient {
+        parameters: gradient.parameters + regularization_strength * &estimation_model.parameters,
+        b: gradient.b,
+    }
+}
+
+/// Gradient descent with L2 regularization and optional mini-batches. One
+/// iteration is one pass over the training set; the returned costs are the
+/// regularized costs on the whole set at the start of each iteration.
+pub fn train<F: FeaturesSet>(
+    features_set: &F,
+    target_set: &MatrixXx1<f64>,
+    initial_estimation_model: &EstimationModel,
+    hyperparameters: &Hyperparameters,
+) -> (EstimationModel, Vec<f64>) {
+    train_with_callbacks(
+        features_set,
+        target_set,
+        initial_estimation_model,
+        hyperparameters,
+        &mut [],
+    )
+    .expect("training without callbacks does not fail")
+}
+
+/// `train` reporting to `callbacks` as it goes. Training ends early when a
+/// callback asks to stop, and the first callback error is returned as is.
+pub fn train_with_callbacks<F: FeaturesSet>(
+    features_set: &F,
+    target_set: &MatrixXx1<f64>,
+    initial_estimation_model: &EstimationModel,
+    hyperparameters: &Hyperparameters,
+    callbacks: &mut [&mut dyn Callback],
+) -> Result<(EstimationModel, Vec<f64>), Box<dyn std::error::Error>> {
+    resume(
+        features_set,
+        target_set,
+        TrainingCheckpoint::new(initial_estimation_model, hyperparameters),
+        callbacks,
+    )
+}
+
+/// Continues training from a checkpoint on the same training set. The
+/// result is bit-identical to a run that was never interrupted.
+///
+/// A stop requested in `on_batch_end` ends training mid-epoch: that epoch is
+/// not counted, `on_epoch_end` is not called for it and `on_train_end` gets
+/// no checkpoint, since a partial epoch cannot be resumed exactly.
+pub fn resume<F: FeaturesSet>(
+    features_set: &F,
+    target_set: &MatrixXx1<f64>,
+    checkpoint: TrainingCheckpoint,
+    callbacks: &mut [&mut dyn Callback],
+) -> Result<(EstimationModel, Vec<f64>), Box<dyn std::error::Error>> {
+    let mut checkpoint = checkpoint;
+    let hyperparameters = checkpoint.hyperparameters;
+    let mut gradient = None;
+    let row_count = features_set.get_row_count();
+    let batch_size = hyperparameters
+        .batch_size
+        .unwrap_or(row_count)
+        .clamp(1, row_count.max(1));
+    let mut cost = get_regularized_cost(
+        features_set,
+        target_set,
+        &checkpoint.estimation_model,
+        hyperparameters.regularization_strength,
+    );
+    let state = TrainingState {
+        epoch: checkpoint.epoch,
+        epoch_count: hyperparameters.iteration_count,
+        batch: 0,
+        estimation_model: &checkpoint.estimation_model,
+        gradient: None,
+        cost,
+        learning_rate: hyperparameters.learning_rate,
+        checkpoint: Some(&checkpoint),
+    };
+    for callback in callbacks.iter_mut() {
+        callback.on_train_begin(&state)?;
+    }
+
+    let mut should_stop = false;
+    let mut is_epoch_complete = true;
+    while checkpoint.epoch < hyperparameters.iteration_count && !should_stop {
+        let epoch = checkpoint.epoch;
+        let epoch_begin_cost = cost;
+        // Each epoch shuffles the rows from their original order, so the RNG
+        // is the only state carried between epochs.
+        let mut row_order = (0..row_count).collect::<Vec<_>>();
+        if hyperparameters.shuffle_seed.is_some() {
+            row_order.shuffle(&mut checkpoint.rng);
+        }
+
+        let batch_count = row_order.chunks(batch_size).len();
+        for (batch, batch_rows) in row_order.chunks(batch_size).enumerate() {
+            let batch_features_set = features_set.select_rows(batch_rows);
+            let batch_target_set = target_set.select_rows(batch_rows);
+            let estimation_model = &checkpoint.estimation_model;
+            let batch_gradient = get_regularized_gradient(
+                &batch_features_set,
+                &batch_target_set,
+                estimation_model,
+                hyperparameters.regularization_strength,
+            );
+            checkpoint.estimation_model = EstimationModel {
+                parameters: &estimation_model.parameters
+                    - hyperparameters.learning_rate * &batch_gradient.parameters,
+                b: estimation_model.b - hyperparameters.learning_rate * batch_gradient.b,
+            };
+            gradient = Some(batch_gradient);
+
+            if !callbacks.is_empty() {
+                let state = TrainingState {
+                    epoch,
+                    epoch_count: hyperparameters.iteration_count,
+                    batch,
+                    estimation_model: &checkpoint.estimation_model,
+                    gradient: gradient.as_ref(),
+                    cost: get_regularized_cost(
+                        &batch_features_set,
+                        &batch_target_set,
+                        &checkpoint.estimation_model,
+                        hyperparameters.regularization_strength,
+                    ),
+                    learning_rate: hyperparameters.learning_rate,
+                    checkpoint: None,
+                };
+                for callback in callbacks.iter_mut() {
+                    should_stop |= callback.on_batch_end(&state)? == CallbackControl::Stop;
+                }
+                if should_stop {
+                    // A stop after the last batch still completes the epoch.
+                    is_epoch_complete = batch + 1 == batch_count;
+                    break;
+                }
+            }
+        }
+
+        cost = get_regularized_cost(
+            features_set,
+            target_set,
+            &checkpoint.estimation_model,
+            hyperparameters.regularization_strength,
+        );
+        if !is_epoch_complete {
+            break;
+        }
+        // Only completed epochs record their cost, so `costs` always has one
+        // entry per counted epoch.
+        checkpoint.costs.push(epoch_begin_cost);
+        checkpoint.epoch += 1;
+        let state = TrainingState {
+            epoch,
+            epoch_count: hyperparameters.iteration_count,
+            batch: 0,
+            estimation_model: &checkpoint.estimation_model,
+            gradient: gradient.as_ref(),
+            cost,
+            learning_rate: hyperparameters.learning_rate,
+            checkpoint: Some(&checkpoint),
+        };
+        for callback in callbacks.iter_mut() {
+            should_stop |= callback.on_epoch_end(&state)? == CallbackControl::Stop;
+        }
+    }
+
+    let state = TrainingState {
+        epoch: checkpoint.epoch,
+        epoch_count: hyperparameters.iteration_count,
+        batch: 0,
+        estimation_model: &checkpoint.estimation_model,
+        gradient: gradient.as_ref(),
+        cost,
+        learning_rate: hyperparameters.learning_rate,
+        checkpoint: is_epoch_complete.then_some(&checkpoint),
+    };
+    for callback in callbacks.iter_mut() {
+        callback.on_train_end(&state)?;
+    }
+    Ok((checkpoint.estimation_model, checkpoint.costs))
+}
+
+/// Gradient descent over a training set that does not fit in memory. The model
+/// is updated once per batch and the file is re-read for every epoch. The
+/// returned costs are the row-weighted mean batch cost of each epoch.
+pub fn mini_batch_gradient_descent(
+    training_set: &StreamingTrainingSet,
+    row_mean: &RowDVector<f64>,
+    row_std_deviation: &RowDVector<f64>,
+    initial_estimation_model: &EstimationModel,
+    learning_rate: f64,
+    epoch_count: usize,
+) -> Result<(EstimationModel, Vec<f64>), Box<dyn std::error::Error>> {
+    if training_set.batch_size == 0 {
+        return Err("batch size must be positive".into());
+    }
+    let mut costs: Vec<f64> = Vec::new();
+    let mut estimation_model = initial_estimation_model.clone();
+
+    for _ in 0..epoch_count {
+        let mut total_cost = 0.0;
+        let mut row_count = 0;
+
+        for (features_set, target_set) in training_set.batches()? {
+            let features_set =
+                zscore_normalize_features_set(&features_set, row_mean, row_std_deviation);
+            let gradient = get_gradient(&features_set, &target_set, &estimation_model);
+            let cost = get_cost(&features_set, &target_set, &estimation_model);
+            total_cost += cost * features_set.nrows() as f64;
+            row_count += features_set.nrows();
+
+            estimation_model = EstimationModel {
+                parameters: estimation_model.parameters - learning_rate * gradient.parameters,
+                b: estimation_model.b - learning_rate * gradient.b,
+            }
+        }
+        if row_count == 0 {
+            return Err(format!("{} has no complete training row", training_set.file_path).into());
+        }
+        costs.push(total_cost / row_count as f64);
+    }
+
+    Ok((estimation_model, costs))
+}
+
 pub fn zscore_normalize_features(
     features: &RowDVector<f64>,
     row_mean: &RowDVector<f64>,
@@ -138,3 +603,312 @@ pub fn get_row_std_deviation(
             .collect::<Vec<_>>(),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn get_features_set() -> DMatrix<f64> {
+        DMatrix::from_row_slice(3, 2, &[1.0, 2.0, 3.0, 4.0, 5.0, 9.0])
+    }
+
+    #[test]
+    fn autodiff_matches_analytic_cost_and_gradient() {
+        let features_set = get_features_set();
+        let target_set = MatrixXx1::from_vec(vec![3.0, -1.0, 7.5]);
+        let estimation_model = EstimationModel {
+            parameters: RowDVector::from_vec(vec![0.7, -0.2]),
+            b: 0.4,
+        };
+        let (cost, gradient) =
+            get_autodiff_cost_and_gradient(&features_set, &target_set, &estimation_model);
+        let analytic_gradient = get_serial_gradient(&features_set, &target_set, &estimation_model);
+        approx::assert_relative_eq!(
+            cost,
+            get_cost_of_rows_1(&features_set, &target_set, &estimation_model),
+            max_relative = 1.0e-12
+        );
+        approx::assert_relative_eq!(
+            gradient.parameters,
+            analytic_gradient.parameters,
+            max_relative = 1.0e-12
+        );
+        approx::assert_relative_eq!(gradient.b, analytic_gradient.b, max_relative = 1.0e-12);
+    }
+
+    #[test]
+    fn training_on_the_autodiff_tape_matches_gradient_descent() {
+        let features_set = DMatrix::from_fn(20, 2, |row, column| {
+            ((row * (column + 3) + column) % 11) as f64 / 5.0 - 1.0
+        });
+        let target_set = MatrixXx1::from_fn(20, |row, _| {
+            2.0 * features_set[(row, 0)] - features_set[(row, 1)] + (row % 3) as f64 / 10.0
+        });
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(2),
+            b: 0.0,
+        };
+        let (estimation_model, costs) =
+            gradient_descent(&features_set, &target_set, &initial, 0.1, 100);
+        let (autodiff_estimation_model, autodiff_costs) = gradient_descent_with(
+            |estimation_model| {
+                get_autodiff_cost_and_gradient(&features_set, &target_set, estimation_model)
+            },
+            &initial,
+            0.1,
+            100,
+        );
+
+        approx::assert_relative_eq!(
+            autodiff_estimation_model.parameters,
+            estimation_model.parameters,
+            max_relative = 1.0e-10
+        );
+        approx::assert_relative_eq!(
+            autodiff_estimation_model.b,
+            estimation_model.b,
+            max_relative = 1.0e-10
+        );
+        for (autodiff_cost, cost) in autodiff_costs.iter().zip(costs.iter()) {
+            approx::assert_relative_eq!(autodiff_cost, cost, max_relative = 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn row_std_deviation_matches_population_std_deviation() {
+        let features_set = get_features_set();
+        let row_mean = get_row_mean(&features_set);
+        let row_std_deviation = get_row_std_deviation(&features_set, &row_mean);
+        assert_eq!(row_mean, RowDVector::from_vec(vec![3.0, 5.0]));
+        // ((1 - 3)^2 + (3 - 3)^2 + (5 - 3)^2) / 3 and ((2 - 5)^2 + (4 - 5)^2 + (9 - 5)^2) / 3
+        approx::assert_relative_eq!(row_std_deviation[0], (8.0_f64 / 3.0).sqrt());
+        approx::assert_relative_eq!(row_std_deviation[1], (26.0_f64 / 3.0).sqrt());
+    }
+
+    #[test]
+    fn zscore_normalize_features_matches_features_set() {
+        let features_set = get_features_set();
+        let row_mean = get_row_mean(&features_set);
+        let row_std_deviation = get_row_std_deviation(&features_set, &row_mean);
+        let normalized_features_set =
+            zscore_normalize_features_set(&features_set, &row_mean, &row_std_deviation);
+        for (features, normalized_features) in features_set
+            .row_iter()
+            .zip(normalized_features_set.row_iter())
+        {
+            assert_eq!(
+                zscore_normalize_features(
+                    &RowDVector::from(features),
+                    &row_mean,
+                    &row_std_deviation
+                ),
+                RowDVector::from(normalized_features)
+            );
+        }
+        let normalized_row_mean = get_row_mean(&normalized_features_set);
+        let normalized_row_std_deviation =
+            get_row_std_deviation(&normalized_features_set, &normalized_row_mean);
+        for column in 0..features_set.ncols() {
+            approx::assert_abs_diff_eq!(normalized_row_mean[column], 0.0, epsilon = 1.0e-12);
+            approx::assert_relative_eq!(normalized_row_std_deviation[column], 1.0);
+        }
+    }
+
+    /// Stops at `epoch`, after `batch` when given and at the epoch end
+    /// otherwise, and records what it was told.
+    struct StopAt {
+        epoch: usize,
+        batch: Option<usize>,
+        ended_epochs: Vec<usize>,
+        checkpoint: Option<TrainingCheckpoint>,
+        train_end: Option<(usize, bool)>,
+    }
+
+    impl StopAt {
+        fn new(epoch: usize, batch: Option<usize>) -> StopAt {
+            StopAt {
+                epoch,
+                batch,
+                ended_epochs: Vec::new(),
+                checkpoint: None,
+                train_end: None,
+            }
+        }
+    }
+
+    impl Callback for StopAt {
+        fn on_train_end(
+            &mut self,
+            state: &TrainingState,
+        ) -> Result<(), Box<dyn std::error::Error>> {
+            self.train_end = Some((state.epoch, state.checkpoint.is_some()));
+            Ok(())
+        }
+
+        fn on_epoch_end(
+            &mut self,
+            state: &TrainingState,
+        ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+            self.ended_epochs.push(state.epoch);
+            self.checkpoint = state.checkpoint.cloned();
+            if self.batch.is_none() && state.epoch == self.epoch {
+                return Ok(CallbackControl::Stop);
+            }
+            Ok(CallbackControl::Continue)
+        }
+
+        fn on_batch_end(
+            &mut self,
+            state: &TrainingState,
+        ) -> Result<CallbackControl, Box<dyn std::error::Error>> {
+            if self.batch == Some(state.batch) && state.epoch == self.epoch {
+                return Ok(CallbackControl::Stop);
+            }
+            Ok(CallbackControl::Continue)
+        }
+    }
+
+    /// 30 rows in mini-batches of 7, so the last batch of each epoch is short.
+    fn get_mini_batch_training_set() -> (DMatrix<f64>, MatrixXx1<f64>, Hyperparameters) {
+        let features_set = DMatrix::from_fn(30, 2, |row, column| {
+            ((row * (column + 3) + column) % 11) as f64 / 5.0 - 1.0
+        });
+        let target_set = MatrixXx1::from_fn(30, |row, _| {
+            2.0 * features_set[(row, 0)] - features_set[(row, 1)] + (row % 3) as f64 / 10.0
+        });
+        let hyperparameters = Hyperparameters {
+            learning_rate: 0.05,
+            iteration_count: 20,
+            regularization_strength: 0.01,
+            batch_size: Some(7),
+            shuffle_seed: Some(3),
+        };
+        (features_set, target_set, hyperparameters)
+    }
+
+    #[test]
+    fn resuming_a_saved_checkpoint_matches_an_uninterrupted_run() {
+        let (features_set, target_set, hyperparameters) = get_mini_batch_training_set();
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(features_set.ncols()),
+            b: 0.0,
+        };
+        let (estimation_model, costs) =
+            train(&features_set, &target_set, &initial, &hyperparameters);
+
+        let mut stop_at = StopAt::new(7, None);
+        train_with_callbacks(
+            &features_set,
+            &target_set,
+            &initial,
+            &hyperparameters,
+            &mut [&mut stop_at],
+        )
+        .unwrap();
+        let checkpoint = stop_at.checkpoint.unwrap();
+        assert_eq!(checkpoint.epoch, 8);
+        assert_eq!(checkpoint.costs.len(), checkpoint.epoch);
+
+        let file_path = std::env::temp_dir().join(format!(
+            "ml_example_training_checkpoint_{}.json",
+            std::process::id()
+        ));
+        checkpoint.save(file_path.to_str().unwrap()).unwrap();
+        let loaded_checkpoint = TrainingCheckpoint::load(file_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&file_path).unwrap();
+        let (resumed_estimation_model, resumed_costs) =
+            resume(&features_set, &target_set, loaded_checkpoint, &mut []).unwrap();
+
+        assert_eq!(
+            resumed_estimation_model.parameters,
+            estimation_model.parameters
+        );
+        assert_eq!(
+            resumed_estimation_model.b.to_bits(),
+            estimation_model.b.to_bits()
+        );
+        assert_eq!(resumed_costs.len(), hyperparameters.iteration_count);
+        assert_eq!(resumed_costs, costs);
+    }
+
+    #[test]
+    fn stopping_mid_epoch_does_not_count_the_epoch() {
+        let (features_set, target_set, hyperparameters) = get_mini_batch_training_set();
+        let initial = EstimationModel {
+            parameters: RowDVector::zeros(features_set.ncols()),
+            b: 0.0,
+        };
+
+        let mut stop_at = StopAt::new(2, Some(1));
+        let (_, costs) = train_with_callbacks(
+            &features_set,
+            &target_set,
+            &initial,
+            &hyperparameters,
+            &mut [&mut stop_at],
+        )
+        .unwrap();
+        assert_eq!(stop_at.ended_epochs, vec![0, 1]);
+        assert_eq!(stop_at.train_end, Some((2, false)));
+        assert_eq!(costs.len(), 2);
+        let checkpoint = stop_at.checkpoint.unwrap();
+        assert_eq!(checkpoint.epoch, 2);
+        assert_eq!(checkpoint.costs.len(), checkpoint.epoch);
+        assert_eq!(checkpoint.costs, costs);
+
+        // 30 rows in batches of 7 make 5 batches, so batch 4 ends the epoch.
+        let mut stop_at = StopAt::new(2, Some(4));
+        let (_, costs) = train_with_callbacks(
+            &features_set,
+            &target_set,
+            &initial,
+            &hyperparameters,
+            &mut [&mut stop_at],
+        )
+        .unwrap();
+        assert_eq!(stop_at.ended_epochs, vec![0, 1, 2]);
+        assert_eq!(stop_at.train_end, Some((3, true)));
+        assert_eq!(costs.len(), 3);
+        assert_eq!(stop_at.checkpoint.unwrap().costs.len(), 3);
+    }
+
+    fn training_set_strategy() -> impl Strategy<Value = (DMatrix<f64>, MatrixXx1<f64>)> {
+        (2..30_usize, 1..4_usize).prop_flat_map(|(row_count, column_count)| {
+            (
+                prop::collection::vec(-100.0..100.0_f64, row_count * column_count),
+                prop::collection::vec(-100.0..100.0_f64, row_count),
+            )
+                .prop_map(move |(features, targets)| {
+                    (
+                        DMatrix::from_row_slice(row_count, column_count, &features),
+                        MatrixXx1::from_vec(targets),
+                    )
+                })
+        })
+    }
+
+    proptest! {
+        /// With z-scored features the Hessian of the cost has a trace of at
+        /// most `column_count + 1`, so a learning rate of 0.1 is small enough
+        /// for every generated set.
+        #[test]
+        fn cost_does_not_increase_with_a_small_learning_rate(
+            (features_set, target_set) in training_set_strategy()
+        ) {
+            let row_mean = get_row_mean(&features_set);
+            let row_std_deviation = get_row_std_deviation(&features_set, &row_mean);
+            prop_assume!(row_std_deviation.iter().all(|std_deviation| *std_deviation > 1.0e-6));
+            let features_set =
+                zscore_normalize_features_set(&features_set, &row_mean, &row_std_deviation);
+            let initial = EstimationModel {
+                parameters: RowDVector::zeros(features_set.ncols()),
+                b: 0.0,
+            };
+            let (_, costs) = gradient_descent(&features_set, &target_set, &initial, 0.1, 50);
+            for pair in costs.windows(2) {
+                prop_assert!(pair[1] <= pair[0] * (1.0 + 1.0e-12), "{:?}", costs);
+            }
+        }
+    }
+}
@@ -0,0 +1,763 @@
+use nalgebra::{DMatrix, MatrixXx1, RowDVector};
+use rand::rngs::StdRng;
+use rand::seq::index::sample;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+/// How the impurity of a node is measured. `Mse` and `Mae` are for
+/// regression, `Gini` and `Entropy` for classification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SplitCriterion {
+    /// Variance around the mean; leaves predict the mean.
+    Mse,
+    /// Mean absolute deviation from the median; leaves predict the median.
+    /// Finding a split costs quadratic time in the node's row count.
+    Mae,
+    Gini,
+    /// In bits.
+    Entropy,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TreeParameters {
+    pub criterion: SplitCriterion,
+    /// `None` grows until the leaves are pure or cannot be split.
+    pub max_depth: Option<usize>,
+    pub min_samples_leaf: usize,
+    /// Number of columns drawn at random as split candidates at every node;
+    /// `None` tries them all.
+    pub max_features: Option<usize>,
+    /// Cost-complexity pruning strength. Subtrees whose impurity decrease
+    /// per extra leaf is at most `ccp_alpha` are collapsed; 0 keeps them all.
+    pub ccp_alpha: f64,
+}
+
+/// Every node keeps the value it would predict as a leaf, so that pruning
+/// can collapse it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TreeNode {
+    /// Rows with `row[column] <= split` go left.
+    Node {
+        column: usize,
+        split: f64,
+        left: Box<TreeNode>,
+        right: Box<TreeNode>,
+        value: f64,
+        impurity: f64,
+        row_count: usize,
+    },
+    Leaf {
+        value: f64,
+        impurity: f64,
+        row_count: usize,
+    },
+}
+
+/// A CART tree. A classifier predicts the fraction of `true` targets in each
+/// leaf.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecisionTree {
+    pub root: TreeNode,
+    pub criterion: SplitCriterion,
+    pub feature_count: usize,
+}
+
+impl SplitCriterion {
+    pub fn is_classification(self) -> bool {
+        matches!(self, SplitCriterion::Gini | SplitCriterion::Entropy)
+    }
+
+    fn get_value(self, targets: &[f64]) -> f64 {
+        match self {
+            SplitCriterion::Mae => get_median(targets),
+            _ => targets.iter().sum::<f64>() / targets.len() as f64,
+        }
+    }
+
+    fn get_impurity(self, targets: &[f64]) -> f64 {
+        match self {
+            SplitCriterion::Mae => {
+                let median = get_median(targets);
+                targets
+                    .iter()
+                    .map(|target| (target - median).abs())
+                    .sum::<f64>()
+                    / targets.len() as f64
+            }
+            _ => self.get_impurity_of_sums(
+                targets.len() as f64,
+                targets.iter().sum(),
+                targets.iter().map(|target| target.powi(2)).sum(),
+            ),
+        }
+    }
+
+    /// Impurity from the row count and the sums of the targets and of their
+    /// squares, so that every split position can be scored in constant time.
+    /// Not used for `Mae`.
+    fn get_impurity_of_sums(self, count: f64, sum: f64, square_sum: f64) -> f64 {
+        let mean = sum / count;
+        match self {
+            SplitCriterion::Mse | SplitCriterion::Mae => {
+                (square_sum / count - mean.powi(2)).max(0.0)
+            }
+            SplitCriterion::Gini => 2.0 * mean * (1.0 - mean),
+            SplitCriterion::Entropy => [mean, 1.0 - mean]
+                .iter()
+                .filter(|p| **p > 0.0)
+                .map(|p| -p * p.log2())
+                .sum(),
+        }
+    }
+
+    fn get_name(self) -> &'static str {
+        match self {
+            SplitCriterion::Mse => "mse",
+            SplitCriterion::Mae => "mae",
+            SplitCriterion::Gini => "gini",
+            SplitCriterion::Entropy => "entropy",
+        }
+    }
+}
+
+fn get_median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let middle = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        sorted[middle]
+    } else {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    }
+}
+
+struct BestSplit {
+    column: usize,
+    split: f64,
+    impurity: f64,
+    left_rows: Vec<usize>,
+    right_rows: Vec<usize>,
+}
+
+impl TreeNode {
+    fn build(
+        features_set: &DMatrix<f64>,
+        targets: &[f64],
+        rows: Vec<usize>,
+        depth: usize,
+        parameters: &TreeParameters,
+        rng: &mut StdRng,
+    ) -> TreeNode {
+        let node_targets = rows.iter().map(|row| targets[*row]).collect::<Vec<_>>();
+        let value = parameters.criterion.get_value(&node_targets);
+        let impurity = parameters.criterion.get_impurity(&node_targets);
+        let leaf = TreeNode::Leaf {
+            value,
+            impurity,
+            row_count: rows.len(),
+        };
+        if parameters
+            .max_depth
+            .is_some_and(|max_depth| depth >= max_depth)
+            || impurity <= 0.0
+            || rows.len() < 2 * parameters.min_samples_leaf.max(1)
+        {
+            return leaf;
+        }
+        match TreeNode::find_best_split(features_set, targets, &rows, parameters, rng) {
+            Some(best) if best.impurity < impurity => TreeNode::Node {
+                column: best.column,
+                split: best.split,
+                left: Box::new(TreeNode::build(
+                    features_set,
+                    targets,
+                    best.left_rows,
+                    depth + 1,
+                    parameters,
+                    rng,
+                )),
+                right: Box::new(TreeNode::build(
+                    features_set,
+                    targets,
+                    best.right_rows,
+                    depth + 1,
+                    parameters,
+                    rng,
+                )),
+                value,
+                impurity,
+                row_count: rows.len(),
+            },
+            _ => leaf,
+        }
+    }
+
+    /// The split with the lowest row-weighted impurity of the two children.
+    /// Candidate splits lie halfway between consecutive distinct values.
+    fn find_best_split(
+        features_set: &DMatrix<f64>,
+        targets: &[f64],
+        rows: &[usize],
+        parameters: &TreeParameters,
+        rng: &mut StdRng,
+    ) -> Option<BestSplit> {
+        let criterion = parameters.criterion;
+        let min_samples_leaf = parameters.min_samples_leaf.max(1);
+        let count = rows.len();
+        let mut best: Option<(usize, f64, f64)> = None;
+
+        let columns = match parameters.max_features {
+            Some(max_features) if max_features < features_set.ncols() => {
+                let mut columns = sample(rng, features_set.ncols(), max_features.max(1)).into_vec();
+                columns.sort_unstable();
+                columns
+            }
+            _ => (0..features_set.ncols()).collect(),
+        };
+        for column in columns {
+            let mut sorted_rows = rows.to_vec();
+            sorted_rows
+                .sort_by(|a, b| features_set[(*a, column)].total_cmp(&features_set[(*b, column)]));
+            let sorted_targets = sorted_rows
+                .iter()
+                .map(|row| targets[*row])
+                .collect::<Vec<_>>();
+            let total_sum = sorted_targets.iter().sum::<f64>();
+            let total_square_sum = sorted_targets.iter().map(|t| t.powi(2)).sum::<f64>();
+            let (mut left_sum, mut left_square_sum) = (0.0, 0.0);
+
+            for left_count in 1..count {
+                let target = sorted_targets[left_count - 1];
+                left_sum += target;
+                left_square_sum += target.powi(2);
+                let left_value = features_set[(sorted_rows[left_count - 1], column)];
+                let right_value = features_set[(sorted_rows[left_count], column)];
+                if left_count < min_samples_leaf
+                    || count - left_count < min_samples_leaf
+                    || left_value >= right_value
+                {
+                    continue;
+                }
+                let right_count = count - left_count;
+                let (left_impurity, right_impurity) = match criterion {
+                    SplitCriterion::Mae => (
+                        criterion.get_impurity(&sorted_targets[..left_count]),
+                        criterion.get_impurity(&sorted_targets[left_count..]),
+                    ),
+                    _ => (
+                        criterion.get_impurity_of_sums(
+                            left_count as f64,
+                            left_sum,
+                            left_square_sum,
+                        ),
+                        criterion.get_impurity_of_sums(
+                            right_count as f64,
+                            total_sum - left_sum,
+                            total_square_sum - left_square_sum,
+                        ),
+                    ),
+                };
+                let impurity = (left_count as f64 * left_impurity
+                    + right_count as f64 * right_impurity)
+                    / count as f64;
+                let is_better = match best {
+                    Some((_, _, best_impurity)) => impurity < best_impurity,
+                    None => true,
+                };
+                if is_better {
+                    best = Some((column, (left_value + right_value) / 2.0, impurity));
+                }
+            }
+        }
+
+        best.map(|(column, split, impurity)| {
+            let (left_rows, right_rows) = rows
+                .iter()
+                .partition(|row| features_set[(**row, column)] <= split);
+            BestSplit {
+                column,
+                split,
+                impurity,
+                left_rows,
+                right_rows,
+            }
+        })
+    }
+
+    pub fn get_value(&self) -> f64 {
+        match self {
+            TreeNode::Node { value, .. } | TreeNode::Leaf { value, .. } => *value,
+        }
+    }
+
+    pub fn get_impurity(&self) -> f64 {
+        match self {
+            TreeNode::Node { impurity, .. } | TreeNode::Leaf { impurity, .. } => *impurity,
+        }
+    }
+
+    pub fn get_row_count(&self) -> usize {
+        match self {
+            TreeNode::Node { row_count, .. } | TreeNode::Leaf { row_count, .. } => *row_count,
+        }
+    }
+
+    fn get_leaf(&self, row: &[f64]) -> &TreeNode {
+        match self {
+            TreeNode::Node {
+                column,
+                split,
+                left,
+                right,
+                ..
+            } => {
+                if row[*column] <= *split {
+                    left.get_leaf(row)
+                } else {
+                    right.get_leaf(row)
+                }
+            }
+            TreeNode::Leaf { .. } => self,
+        }
+    }
+
+    /// Minimal cost-complexity pruning, bottom-up. Returns the summed
+    /// impurity of the remaining leaves, weighted by `row_count / total_row_count`,
+    /// and their count.
+    fn prune(&mut self, ccp_alpha: f64, total_row_count: usize) -> (f64, usize) {
+        let leaf_cost = self.get_impurity() * self.get_row_count() as f64 / total_row_count as f64;
+        let (subtree_cost, leaf_count) = match self {
+            TreeNode::Node { left, right, .. } => {
+                let (left_cost, left_leaf_count) = left.prune(ccp_alpha, total_row_count);
+                let (right_cost, right_leaf_count) = right.prune(ccp_alpha, total_row_count);
+                (left_cost + right_cost, left_leaf_count + right_leaf_count)
+            }
+            TreeNode::Leaf { .. } => return (leaf_cost, 1),
+        };
+        if leaf_cost + ccp_alpha <= subtree_cost + ccp_alpha * leaf_count as f64 {
+            *self = TreeNode::Leaf {
+                value: self.get_value(),
+                impurity: self.get_impurity(),
+                row_count: self.get_row_count(),
+            };
+            (leaf_cost, 1)
+        } else {
+            (subtree_cost, leaf_count)
+        }
+    }
+
+    fn add_importances(&self, importances: &mut [f64]) {
+        if let TreeNode::Node {
+            column,
+            left,
+            right,
+            impurity,
+            row_count,
+            ..
+        } = self
+        {
+            importances[*column] += *row_count as f64 * impurity
+                - left.get_row_count() as f64 * left.get_impurity()
+                - right.get_row_count() as f64 * right.get_impurity();
+            left.add_importances(importances);
+            right.add_importances(importances);
+        }
+    }
+
+    fn get_depth(&self) -> usize {
+        match self {
+            TreeNode::Node { left, right, .. } => 1 + left.get_depth().max(right.get_depth()),
+            TreeNode::Leaf { .. } => 0,
+        }
+    }
+
+    fn get_leaf_count(&self) -> usize {
+        match self {
+            TreeNode::Node { left, right, .. } => left.get_leaf_count() + right.get_leaf_count(),
+            TreeNode::Leaf { .. } => 1,
+        }
+    }
+}
+
+pub(crate) fn check_training_set(
+    features_set: &DMatrix<f64>,
+    targets: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    if features_set.nrows() == 0 {
+        return Err("cannot fit a tree to an empty training set".into());
+    }
+    if features_set.nrows() != targets.len() {
+        return Err(format!(
+            "{} feature rows but {} targets",
+            features_set.nrows(),
+            targets.len()
+        )
+        .into());
+    }
+    if features_set.iter().any(|feature| feature.is_nan()) {
+        return Err("features contain NaN; impute missing values first".into());
+    }
+    Ok(())
+}
+
+fn get_feature_name(feature_names: &[&str], column: usize) -> String {
+    feature_names
+        .get(column)
+        .map_or(format!("feature_{}", column), |name| name.to_string())
+}
+
+impl DecisionTree {
+    pub fn fit_regressor(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<f64>,
+        parameters: &TreeParameters,
+    ) -> Result<DecisionTree, Box<dyn std::error::Error>> {
+        if parameters.criterion.is_classification() {
+            return Err(format!("{:?} is a classification criterion", parameters.criterion).into());
+        }
+        DecisionTree::fit(features_set, target_set.as_slice(), parameters)
+    }
+
+    pub fn fit_classifier(
+        features_set: &DMatrix<f64>,
+        target_set: &MatrixXx1<bool>,
+        parameters: &TreeParameters,
+    ) -> Result<DecisionTree, Box<dyn std::error::Error>> {
+        if !parameters.criterion.is_classification() {
+            return Err(format!("{:?} is a regression criterion", parameters.criterion).into());
+        }
+        let targets = target_set
+            .iter()
+            .map(|target| if *target { 1.0 } else { 0.0 })
+            .collect::<Vec<_>>();
+        DecisionTree::fit(features_set, &targets, parameters)
+    }
+
+    /// Column subsets for `max_features` are drawn with a fixed seed.
+    fn fit(
+        features_set: &DMatrix<f64>,
+        targets: &[f64],
+        parameters: &TreeParameters,
+    ) -> Result<DecisionTree, Box<dyn std::error::Error>> {
+        check_training_set(features_set, targets)?;
+        Ok(DecisionTree::fit_rows(
+            features_set,
+            targets,
+            (0..features_set.nrows()).collect(),
+            parameters,
+            &mut StdRng::seed_from_u64(0),
+        ))
+    }
+
+    /// Fits to the given rows only, which may repeat, as in a bootstrap
+    /// sample. The training set must have passed `check_training_set`.
+    pub(crate) fn fit_rows(
+        features_set: &DMatrix<f64>,
+        targets: &[f64],
+        rows: Vec<usize>,
+        parameters: &TreeParameters,
+        rng: &mut StdRng,
+    ) -> DecisionTree {
+        let row_count = rows.len();
+        let mut root = TreeNode::build(features_set, targets, rows, 0, parameters, rng);
+        if parameters.ccp_alpha > 0.0 {
+            root.prune(parameters.ccp_alpha, row_count);
+        }
+        DecisionTree {
+            root,
+            criterion: parameters.criterion,
+            feature_count: features_set.ncols(),
+        }
+    }
+
+    pub fn predict_row(&self, row: &RowDVector<f64>) -> f64 {
+        self.root.get_leaf(row.as_slice()).get_value()
+    }
+
+    /// Leaf values: the estimate for a regressor, the probability of `true`
+    /// for a classifier.
+    pub fn predict(&self, features_set: &DMatrix<f64>) -> MatrixXx1<f64> {
+        MatrixXx1::from_iterator(
+            features_set.nrows(),
+            features_set
+                .row_iter()
+                .map(|row| self.predict_row(&RowDVector::from(row))),
+        )
+    }
+
+    pub fn predict_class(&self, features_set: &DMatrix<f64>) -> MatrixXx1<bool> {
+        self.predict(features_set)
+            .map(|probability| probability >= 0.5)
+    }
+
+    /// Total impurity decrease of the splits on each feature, normalized to
+    /// sum to 1. All zeros for a tree with no split.
+    pub fn get_feature_importances(&self) -> RowDVector<f64> {
+        let mut importances = vec![0.0; self.feature_count];
+        self.root.add_importances(&mut importances);
+        let total = importances.iter().sum::<f64>();
+        if total > 0.0 {
+            importances
+                .iter_mut()
+                .for_each(|importance| *importance /= total);
+        }
+        RowDVector::from_vec(importances)
+    }
+
+    pub fn get_depth(&self) -> usize {
+        self.root.get_depth()
+    }
+
+    pub fn get_leaf_count(&self) -> usize {
+        self.root.get_leaf_count()
+    }
+
+    /// One line per node, indented by depth. Columns without a name in
+    /// `feature_names` are printed as `feature_{column}`.
+    pub fn to_text(&self, feature_names: &[&str]) -> String {
+        let mut text = String::new();
+        self.write_text(&self.root, feature_names, 0, &mut text);
+        text
+    }
+
+    fn write_text(&self, node: &TreeNode, feature_names: &[&str], depth: usize, text: &mut String) {
+        let indent = "|   ".repeat(depth);
+        match node {
+            TreeNode::Node {
+                column,
+                split,
+                left,
+                right,
+                ..
+            } => {
+                let name = get_feature_name(feature_names, *column);
+                text.push_str(&format!("{}|--- {} <= {}\n", indent, name, split));
+                self.write_text(left, feature_names, depth + 1, text);
+                text.push_str(&format!("{}|--- {} >  {}\n", indent, name, split));
+                self.write_text(right, feature_names, depth + 1, text);
+            }
+            TreeNode::Leaf {
+                value, row_count, ..
+            } => {
+                text.push_str(&format!(
+                    "{}|--- value: {} (samples: {})\n",
+                    indent, value, row_count
+                ));
+            }
+        }
+    }
+
+    /// Graphviz DOT source, e.g. for `dot -Tpng tree.dot -o tree.png`.
+    pub fn to_dot(&self, feature_names: &[&str]) -> String {
+        let mut dot = String::from("digraph tree {\nnode [shape=box];\n");
+        let mut next_id = 0;
+        self.write_dot(&self.root, feature_names, &mut next_id, &mut dot);
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Returns the id given to `node`.
+    fn write_dot(
+        &self,
+        node: &TreeNode,
+        feature_names: &[&str],
+        next_id: &mut usize,
+        dot: &mut String,
+    ) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let statistics = format!(
+            "{} = {}\\nsamples = {}\\nvalue = {}",
+            self.criterion.get_name(),
+            node.get_impurity(),
+            node.get_row_count(),
+            node.get_value()
+        );
+        match node {
+            TreeNode::Node {
+                column,
+                split,
+                left,
+                right,
+                ..
+            } => {
+                let name = get_feature_name(feature_names, *column)
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"");
+                dot.push_str(&format!(
+                    "{} [label=\"{} <= {}\\n{}\"];\n",
+                    id, name, split, statistics
+                ));
+                let left_id = self.write_dot(left, feature_names, next_id, dot);
+                dot.push_str(&format!("{} -> {} [label=\"yes\"];\n", id, left_id));
+                let right_id = self.write_dot(right, feature_names, next_id, dot);
+                dot.push_str(&format!("{} -> {} [label=\"no\"];\n", id, right_id));
+            }
+            TreeNode::Leaf { .. } => {
+                dot.push_str(&format!("{} [label=\"{}\"];\n", id, statistics));
+            }
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARAMETERS: TreeParameters = TreeParameters {
+        criterion: SplitCriterion::Mse,
+        max_depth: None,
+        min_samples_leaf: 1,
+        max_features: None,
+        ccp_alpha: 0.0,
+    };
+
+    /// `y = 1` for `x0 > 2`, `y = 5` otherwise; `x1` is noise.
+    fn get_step_set() -> (DMatrix<f64>, MatrixXx1<f64>) {
+        let features_set = DMatrix::from_row_slice(
+            6,
+            2,
+            &[0.0, 3.0, 1.0, 1.0, 2.0, 2.0, 3.0, 3.0, 4.0, 1.0, 5.0, 2.0],
+        );
+        let target_set = MatrixXx1::from_vec(vec![5.0, 5.0, 5.0, 1.0, 1.0, 1.0]);
+        (features_set, target_set)
+    }
+
+    #[test]
+    fn regressor_recovers_step_function() {
+        let (features_set, target_set) = get_step_set();
+        for criterion in [SplitCriterion::Mse, SplitCriterion::Mae] {
+            let tree = DecisionTree::fit_regressor(
+                &features_set,
+                &target_set,
+                &TreeParameters {
+                    criterion,
+                    ..PARAMETERS
+                },
+            )
+            .unwrap();
+            assert_eq!(tree.predict(&features_set), target_set);
+            assert_eq!(tree.get_leaf_count(), 2);
+            assert_eq!(
+                tree.get_feature_importances(),
+                RowDVector::from_vec(vec![1.0, 0.0])
+            );
+            match tree.root {
+                TreeNode::Node { column, split, .. } => assert_eq!((column, split), (0, 2.5)),
+                TreeNode::Leaf { .. } => panic!("expected a split"),
+            }
+        }
+    }
+
+    #[test]
+    fn classifier_separates_quadrant() {
+        let features_set = DMatrix::from_row_slice(
+            8,
+            2,
+            &[
+                0.0, 0.0, 0.1, 0.2, 1.0, 1.0, 0.9, 1.1, 0.0, 1.0, 0.1, 0.9, 1.0, 0.0, 1.1, 0.1,
+            ],
+        );
+        let target_set =
+            MatrixXx1::from_vec(vec![false, false, true, true, false, false, false, false]);
+        for criterion in [SplitCriterion::Gini, SplitCriterion::Entropy] {
+            let tree = DecisionTree::fit_classifier(
+                &features_set,
+                &target_set,
+                &TreeParameters {
+                    criterion,
+                    ..PARAMETERS
+                },
+            )
+            .unwrap();
+            assert_eq!(tree.predict_class(&features_set), target_set);
+            assert_eq!(tree.get_depth(), 2);
+        }
+    }
+
+    #[test]
+    fn limits_depth_and_leaf_size() {
+        let features_set = DMatrix::from_fn(40, 1, |row, _| row as f64);
+        let target_set = MatrixXx1::from_fn(40, |row, _| ((row * 7) % 11) as f64);
+        let tree = DecisionTree::fit_regressor(
+            &features_set,
+            &target_set,
+            &TreeParameters {
+                max_depth: Some(3),
+                min_samples_leaf: 4,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        assert!(tree.get_depth() <= 3);
+        fn assert_leaf_sizes(node: &TreeNode) {
+            match node {
+                TreeNode::Node { left, right, .. } => {
+                    assert_leaf_sizes(left);
+                    assert_leaf_sizes(right);
+                }
+                TreeNode::Leaf { row_count, .. } => assert!(*row_count >= 4),
+            }
+        }
+        assert_leaf_sizes(&tree.root);
+    }
+
+    #[test]
+    fn pruning_collapses_weak_splits() {
+        let (features_set, mut target_set) = get_step_set();
+        target_set[0] = 5.5;
+        let full = DecisionTree::fit_regressor(&features_set, &target_set, &PARAMETERS).unwrap();
+        let pruned = DecisionTree::fit_regressor(
+            &features_set,
+            &target_set,
+            &TreeParameters {
+                ccp_alpha: 0.1,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        let stump = DecisionTree::fit_regressor(
+            &features_set,
+            &target_set,
+            &TreeParameters {
+                ccp_alpha: 100.0,
+                ..PARAMETERS
+            },
+        )
+        .unwrap();
+        assert!(full.get_leaf_count() > 2);
+        assert_eq!(pruned.get_leaf_count(), 2);
+        assert_eq!(stump.get_leaf_count(), 1);
+    }
+
+    #[test]
+    fn rejects_mismatched_criterion() {
+        let (features_set, target_set) = get_step_set();
+        assert!(DecisionTree::fit_regressor(
+            &features_set,
+            &target_set,
+            &TreeParameters {
+                criterion: SplitCriterion::Gini,
+                ..PARAMETERS
+            }
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn dumps_text_and_dot() {
+        let (features_set, target_set) = get_step_set();
+        let tree = DecisionTree::fit_regressor(&features_set, &target_set, &PARAMETERS).unwrap();
+        assert_eq!(
+            tree.to_text(&["건물면적"]),
+            "|--- 건물면적 <= 2.5\n|   |--- value: 5 (samples: 3)\n\
+             |--- 건물면적 >  2.5\n|   |--- value: 1 (samples: 3)\n"
+        );
+        let dot = tree.to_dot(&[]);
+        assert!(dot.starts_with("digraph tree {"));
+        assert!(dot.contains("0 [label=\"feature_0 <= 2.5\\nmse = 4\\nsamples = 6\\nvalue = 3\"];"));
+        assert!(dot.contains("0 -> 1 [label=\"yes\"];"));
+        assert!(dot.contains("0 -> 2 [label=\"no\"];"));
+    }
+}
@@ -0,0 +1,177 @@
+use ml_example::data_loader::{
+    fit_categorical_training_set, load_records, load_training_set, StreamingTrainingSet,
+};
+use ml_example::encoding::EncoderKind;
+use ml_example::model::{CategoricalColumn, EstimationModel};
+use ml_example::training::{
+    get_row_mean, get_row_std_deviation, gradient_descent, mini_batch_gradient_descent,
+    zscore_normalize_features_set,
+};
+use nalgebra::RowDVector;
+use nalgebra::{DMatrix, MatrixXx1};
+use std::fs;
+use std::path::PathBuf;
+
+fn get_fixture_path(file_name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(file_name)
+}
+
+/// Compares `actual` with a golden file, or rewrites the golden file when
+/// `UPDATE_GOLDEN` is set.
+fn assert_golden(file_name: &str, actual: &str) {
+    let path = get_fixture_path(file_name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected =
+        fs::read_to_string(&path).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+    assert_eq!(actual, expected, "{} differs", path.display());
+}
+
+fn format_training_set(features_set: &DMatrix<f64>, target_set: &MatrixXx1<f64>) -> String {
+    features_set
+        .row_iter()
+        .zip(target_set.iter())
+        .map(|(features, target)| {
+            format!(
+                "{:?} -> {:?}\n",
+                features.iter().collect::<Vec<_>>(),
+                target
+            )
+        })
+        .collect::<String>()
+}
+
+#[test]
+fn load_training_set_matches_golden_file() {
+    let (features_set, target_set) =
+        load_training_set(get_fixture_path("seoul_sample.csv").to_str().unwrap()).unwrap();
+    assert_golden(
+        "seoul_sample.training_set.golden",
+        &format_training_set(&features_set, &target_set),
+    );
+}
+
+/// One-hot columns make a training set of several columns, which catches a
+/// matrix filled column by column instead of row by row.
+#[test]
+fn fit_categorical_training_set_matches_golden_file() {
+    let records = load_records(get_fixture_path("seoul_sample.csv").to_str().unwrap()).unwrap();
+    let (features_set, target_set, _) = fit_categorical_training_set(
+        &records,
+        &[
+            (CategoricalColumn::자치구명, EncoderKind::OneHot),
+            (CategoricalColumn::건물용도, EncoderKind::OneHot),
+        ],
+        1,
+    )
+    .unwrap();
+    assert_golden(
+        "seoul_sample.categorical_training_set.golden",
+        &format_training_set(&features_set, &target_set),
+    );
+}
+
+#[test]
+fn load_records_matches_golden_file() {
+    let records = load_records(get_fixture_path("seoul_sample.csv").to_str().unwrap()).unwrap();
+    let actual = records
+        .iter()
+        .map(|record| format!("{:?}\n", record))
+        .collect::<String>();
+    assert_golden("seoul_sample.records.golden", &actual);
+}
+
+#[test]
+fn streaming_row_statistics_match_in_memory_statistics() {
+    let file_path = get_fixture_path("seoul_sample.csv");
+    let file_path = file_path.to_str().unwrap();
+    let (features_set, _) = load_training_set(file_path).unwrap();
+    let row_mean = get_row_mean(&features_set);
+    let row_std_deviation = get_row_std_deviation(&features_set, &row_mean);
+    for batch_size in [1, 3, 4, 100] {
+        let row_statistics = StreamingTrainingSet::new(file_path, batch_size)
+            .get_row_statistics()
+            .unwrap();
+        assert_eq!(row_statistics.count, features_set.nrows());
+        approx::assert_relative_eq!(
+            row_statistics.get_row_mean(),
+            row_mean,
+            max_relative = 1.0e-12
+        );
+        approx::assert_relative_eq!(
+            row_statistics.get_row_std_deviation(),
+            row_std_deviation,
+            max_relative = 1.0e-12
+        );
+    }
+}
+
+#[test]
+fn one_batch_per_epoch_matches_gradient_descent() {
+    let file_path = get_fixture_path("seoul_sample.csv");
+    let file_path = file_path.to_str().unwrap();
+    let (features_set, target_set) = load_training_set(file_path).unwrap();
+    let row_mean = get_row_mean(&features_set);
+    let row_std_deviation = get_row_std_deviation(&features_set, &row_mean);
+    let initial = EstimationModel {
+        parameters: RowDVector::zeros(features_set.ncols()),
+        b: 0.0,
+    };
+
+    let (expected, expected_costs) = gradient_descent(
+        &zscore_normalize_features_set(&features_set, &row_mean, &row_std_deviation),
+        &target_set,
+        &initial,
+        0.1,
+        10,
+    );
+    let (actual, actual_costs) = mini_batch_gradient_descent(
+        &StreamingTrainingSet::new(file_path, features_set.nrows()),
+        &row_mean,
+        &row_std_deviation,
+        &initial,
+        0.1,
+        10,
+    )
+    .unwrap();
+    assert_eq!(actual.parameters, expected.parameters);
+    assert_eq!(actual.b, expected.b);
+    assert_eq!(actual_costs, expected_costs);
+}
+
+#[test]
+fn zero_batch_size_is_rejected() {
+    let file_path = get_fixture_path("seoul_sample.csv");
+    let training_set = StreamingTrainingSet::new(file_path.to_str().unwrap(), 0);
+    assert!(training_set.batches().is_err());
+    assert!(mini_batch_gradient_descent(
+        &training_set,
+        &RowDVector::zeros(1),
+        &RowDVector::from_element(1, 1.0),
+        &EstimationModel {
+            parameters: RowDVector::zeros(1),
+            b: 0.0,
+        },
+        0.1,
+        1,
+    )
+    .is_err());
+}
+
+#[test]
+fn streaming_counts_skipped_rows() {
+    let file_path = get_fixture_path("seoul_sample.csv");
+    let mut batches = StreamingTrainingSet::new(file_path.to_str().unwrap(), 2)
+        .batches()
+        .unwrap();
+    let row_count = batches
+        .by_ref()
+        .map(|(_, target_set)| target_set.len())
+        .sum::<usize>();
+    assert_eq!(row_count, 3);
+    assert_eq!(batches.skipped_row_count, 4);
+}
@@ -0,0 +1,78 @@
+use ml_example::data_loader::{fit_categorical_training_set, load_records};
+use ml_example::encoding::EncoderKind;
+use ml_example::imputation::{Imputer, ImputerKind};
+use ml_example::model::{CategoricalColumn, EstimationModel, ModelArtifact};
+use ml_example::training::{
+    get_row_mean, get_row_std_deviation, gradient_descent, zscore_normalize_features_set,
+};
+use nalgebra::RowDVector;
+use std::path::PathBuf;
+
+#[test]
+fn saved_artifact_predicts_the_same_after_loading() {
+    let records = load_records(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/seoul_sample.csv")
+            .to_str()
+            .unwrap(),
+    )
+    .unwrap();
+    let (features_set, target_set, column_encoders) = fit_categorical_training_set(
+        &records,
+        &[
+            (CategoricalColumn::자치구명, EncoderKind::OneHot),
+            (
+                CategoricalColumn::건물용도,
+                EncoderKind::Target {
+                    smoothing: 1.0,
+                    fold_count: 2,
+                },
+            ),
+        ],
+        1,
+    )
+    .unwrap();
+    let imputer = Imputer::fit(&features_set, ImputerKind::Median, true);
+    let features_set = imputer.transform(&features_set);
+    let row_mean = get_row_mean(&features_set);
+    let row_std_deviation = get_row_std_deviation(&features_set, &row_mean);
+    let normalized_features_set =
+        zscore_normalize_features_set(&features_set, &row_mean, &row_std_deviation);
+    let (estimation_model, _) = gradient_descent(
+        &normalized_features_set,
+        &target_set,
+        &EstimationModel {
+            parameters: RowDVector::zeros(normalized_features_set.ncols()),
+            b: 0.0,
+        },
+        0.1,
+        100,
+    );
+    let model_artifact = ModelArtifact {
+        estimation_model,
+        row_mean,
+        row_std_deviation,
+        column_encoders,
+        imputer: Some(imputer),
+        target_transformer: None,
+        pca: None,
+        boosted_trees: None,
+    };
+
+    let file_path = std::env::temp_dir().join(format!(
+        "ml_example_model_artifact_{}.json",
+        std::process::id()
+    ));
+    model_artifact.save(file_path.to_str().unwrap()).unwrap();
+    let loaded_model_artifact = ModelArtifact::load(file_path.to_str().unwrap()).unwrap();
+    std::fs::remove_file(&file_path).unwrap();
+
+    for record in &records {
+        let estimated = model_artifact.predict(record);
+        assert!(estimated.is_finite(), "{:?}", record);
+        assert_eq!(
+            loaded_model_artifact.predict(record).to_bits(),
+            estimated.to_bits()
+        );
+    }
+}
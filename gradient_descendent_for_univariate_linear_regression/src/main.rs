@@ -1,4 +1,12 @@
+// The example uses only part of the tape copied from the library crates.
+#[allow(dead_code)]
+mod autodiff;
+
+use autodiff::{Tape, Tensor};
 use num_traits::pow;
+use plotters::coord::ranged1d::ValueFormatter;
+use plotters::coord::types::RangedCoordusize;
+use plotters::coord::Shift;
 use plotters::prelude::*;
 use rand::Rng;
 
@@ -20,6 +28,52 @@ struct Gradient {
     intercept: f64,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlotFormat {
+    Png,
+    Svg,
+}
+
+/// Where and how a plot is written.
+#[derive(Debug, Clone)]
+struct PlotOutput {
+    filename: String,
+    format: PlotFormat,
+    size: (u32, u32),
+}
+
+impl PlotOutput {
+    /// The format follows the extension: `.svg` is written as SVG, anything
+    /// else as PNG.
+    fn new(filename: &str, size: (u32, u32)) -> PlotOutput {
+        let format = if filename.to_lowercase().ends_with(".svg") {
+            PlotFormat::Svg
+        } else {
+            PlotFormat::Png
+        };
+        PlotOutput {
+            filename: filename.to_string(),
+            format,
+            size,
+        }
+    }
+}
+
+macro_rules! draw_on {
+    ($output:expr, $draw:ident($($argument:expr),*)) => {
+        match $output.format {
+            PlotFormat::Png => $draw(
+                BitMapBackend::new(&$output.filename, $output.size).into_drawing_area()
+                $(, $argument)*
+            ),
+            PlotFormat::Svg => $draw(
+                SVGBackend::new(&$output.filename, $output.size).into_drawing_area()
+                $(, $argument)*
+            ),
+        }
+    };
+}
+
 fn estimate(training_row: &TrainingRow, estimation_model: &EstimationModel) -> f64 {
     estimation_model.slope * training_row.feature + estimation_model.intercept
 }
@@ -56,18 +110,70 @@ fn get_gradient(training_rows: &[TrainingRow], estimation_model: &EstimationMode
     }
 }
 
+/// `get_cost_of_rows_1` and `get_gradient` re-expressed on an autodiff tape.
+fn get_autodiff_cost_and_gradient(
+    training_rows: &[TrainingRow],
+    estimation_model: &EstimationModel,
+) -> (f64, Gradient) {
+    let tape = Tape::new();
+    let slope = tape.scalar(estimation_model.slope);
+    let intercept = tape.scalar(estimation_model.intercept);
+    let features = tape.var(Tensor::from_iterator(
+        training_rows.len(),
+        1,
+        training_rows.iter().map(|row| row.feature),
+    ));
+    let targets = tape.var(Tensor::from_iterator(
+        training_rows.len(),
+        1,
+        training_rows.iter().map(|row| row.target),
+    ));
+    let errors = features.matmul(slope) + intercept - targets;
+    let cost = errors.component_mul(errors).mean().scale(0.5);
+
+    let gradients = tape.get_gradients(cost);
+    (
+        cost.get_value()[(0, 0)],
+        Gradient {
+            slope: gradients.get(slope)[(0, 0)],
+            intercept: gradients.get(intercept)[(0, 0)],
+        },
+    )
+}
+
 fn gradient_descent(
     training_rows: &[TrainingRow],
     initial_estimation_model: &EstimationModel,
     learning_rate: f64,
     iteration_count: usize,
+) -> (EstimationModel, Vec<f64>) {
+    gradient_descent_with(
+        |estimation_model| {
+            (
+                get_cost_of_rows_1(training_rows, estimation_model),
+                get_gradient(training_rows, estimation_model),
+            )
+        },
+        initial_estimation_model,
+        learning_rate,
+        iteration_count,
+    )
+}
+
+/// `gradient_descent` with any cost and gradient, such as
+/// `get_autodiff_cost_and_gradient` to train on the autodiff tape. Returns the
+/// cost before every iteration.
+fn gradient_descent_with(
+    get_cost_and_gradient: impl Fn(&EstimationModel) -> (f64, Gradient),
+    initial_estimation_model: &EstimationModel,
+    learning_rate: f64,
+    iteration_count: usize,
 ) -> (EstimationModel, Vec<f64>) {
     let mut costs: Vec<f64> = Vec::new();
-    let mut estimation_model = initial_estimation_model.clone();
+    let mut estimation_model = *initial_estimation_model;
 
-    for _ in 0..iteration_count.clone() {
-        let gradient = get_gradient(training_rows, &estimation_model);
-        let cost = get_cost_of_rows_1(training_rows, &estimation_model);
+    for _ in 0..iteration_count {
+        let (cost, gradient) = get_cost_and_gradient(&estimation_model);
         costs.push(cost);
 
         estimation_model = EstimationModel {
@@ -79,64 +185,235 @@ fn gradient_descent(
     (estimation_model, costs)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CostScale {
+    Linear,
+    /// Useful when the cost falls by orders of magnitude. Only positive costs
+    /// are drawn.
+    Log,
+}
+
 fn draw_costs_plot(
-    filename: &str,
+    output: &PlotOutput,
     learning_rate: f64,
     costs: &[f64],
     iteraction_start: usize,
     iteration_end: usize,
+    scale: CostScale,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Plot
-    let root = BitMapBackend::new(filename, (640, 480)).into_drawing_area();
-    root.fill(&WHITE)?;
-    let mut chart = ChartBuilder::on(&root)
-        .margin(20)
-        .caption(
-            format!(
+    draw_on!(
+        output,
+        draw_costs_series_on(
+            &format!(
                 "Learning Rate: {}, iteration [{}..{}]",
                 learning_rate, iteraction_start, iteration_end
             ),
-            ("sans-serif", 20),
+            &[("cost", costs)],
+            iteraction_start,
+            iteration_end,
+            scale
+        )
+    )
+}
+
+/// Overlays the cost histories of several runs, e.g. one per learning rate,
+/// over the same iterations.
+fn draw_costs_comparison_plot(
+    output: &PlotOutput,
+    series: &[(&str, &[f64])],
+    iteraction_start: usize,
+    iteration_end: usize,
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>> {
+    draw_on!(
+        output,
+        draw_costs_series_on(
+            &format!(
+                "Cost comparison, iteration [{}..{}]",
+                iteraction_start, iteration_end
+            ),
+            series,
+            iteraction_start,
+            iteration_end,
+            scale
         )
+    )
+}
+
+/// NaN and infinite costs, and non-positive ones on a log scale, are left out
+/// of both the axis range and the lines.
+fn draw_costs_series_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    caption: &str,
+    series: &[(&str, &[f64])],
+    iteraction_start: usize,
+    iteration_end: usize,
+    scale: CostScale,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    if series.is_empty() {
+        return Err("no cost to plot".into());
+    }
+    for (label, costs) in series {
+        if iteraction_start >= iteration_end || iteration_end > costs.len() {
+            return Err(format!(
+                "iteration range [{}..{}] is outside the {} recorded costs of {}",
+                iteraction_start,
+                iteration_end,
+                costs.len(),
+                label
+            )
+            .into());
+        }
+    }
+    let is_drawable = |cost: &f64| cost.is_finite() && (scale == CostScale::Linear || *cost > 0.0);
+    let drawable_costs = series
+        .iter()
+        .flat_map(|(_, costs)| costs[iteraction_start..iteration_end].iter())
+        .cloned()
+        .filter(is_drawable)
+        .collect::<Vec<_>>();
+    if drawable_costs.is_empty() {
+        return Err(match scale {
+            CostScale::Linear => "no finite cost to plot",
+            CostScale::Log => "no positive finite cost to plot on a log scale",
+        }
+        .into());
+    }
+
+    root.fill(&WHITE)?;
+    let mut chart_builder = ChartBuilder::on(&root);
+    chart_builder
+        .margin(20)
+        .caption(caption, ("sans-serif", 20))
         .set_label_area_size(LabelAreaPosition::Left, 60)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .x_label_area_size(30)
-        .y_label_area_size(50)
-        .build_cartesian_2d(
-            iteraction_start..iteration_end,
-            0.0..(costs[iteraction_start] * 1.2),
-        )?;
+        .set_label_area_size(LabelAreaPosition::Bottom, 40);
+    let x_range = iteraction_start..iteration_end;
+    match scale {
+        CostScale::Linear => {
+            let chart =
+                chart_builder.build_cartesian_2d(x_range, get_range(drawable_costs.into_iter()))?;
+            draw_costs_series(chart, series, iteraction_start, iteration_end, is_drawable)?;
+        }
+        CostScale::Log => {
+            let (min, max) = drawable_costs
+                .iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), cost| {
+                    (min.min(*cost), max.max(*cost))
+                });
+            let chart = chart_builder
+                .build_cartesian_2d(x_range, ((min * 0.8)..(max * 1.25)).log_scale())?;
+            draw_costs_series(chart, series, iteraction_start, iteration_end, is_drawable)?;
+        }
+    }
+    root.present()?;
+    Ok(())
+}
+
+fn draw_costs_series<'a, DB, Y>(
+    mut chart: ChartContext<'a, DB, Cartesian2d<RangedCoordusize, Y>>,
+    series: &[(&str, &[f64])],
+    iteraction_start: usize,
+    iteration_end: usize,
+    is_drawable: impl Fn(&f64) -> bool,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
     chart
         .configure_mesh()
         .x_desc("iteration")
         .y_desc("cost of function")
         .draw()?;
-    chart.draw_series(LineSeries::new(
-        costs[iteraction_start..iteration_end]
-            .iter()
-            .enumerate()
-            .map(|(index, cost)| (iteraction_start + index, cost.clone())),
-        &RED,
-    ))?;
-    chart.configure_series_labels().draw()?;
-    root.present()?;
+    for (index, (label, costs)) in series.iter().enumerate() {
+        let color = if series.len() == 1 {
+            RED.to_rgba()
+        } else {
+            Palette99::pick(index).to_rgba()
+        };
+        chart
+            .draw_series(LineSeries::new(
+                costs[iteraction_start..iteration_end]
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, cost)| is_drawable(cost))
+                    .map(|(index, cost)| (iteraction_start + index, *cost)),
+                color,
+            ))?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    if series.len() > 1 {
+        chart
+            .configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()?;
+    }
     Ok(())
 }
 
+/// Range covering every finite value with 5% padding on both sides.
+fn get_range(values: impl Iterator<Item = f64>) -> std::ops::Range<f64> {
+    let (min, max) = values
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    if min > max {
+        return 0.0..1.0;
+    }
+    let padding = if max > min {
+        (max - min) * 0.05
+    } else {
+        min.abs().max(1.0) * 0.05
+    };
+    (min - padding)..(max + padding)
+}
+
 fn draw_model_plot(
-    filename: &str,
+    output: &PlotOutput,
     training_rows: &[TrainingRow],
     estimation_model: &EstimationModel,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+    draw_on!(output, draw_model_plot_on(training_rows, estimation_model))
+}
+
+fn draw_model_plot_on<DB: DrawingBackend>(
+    root: DrawingArea<DB, Shift>,
+    training_rows: &[TrainingRow],
+    estimation_model: &EstimationModel,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    let x_range = get_range(training_rows.iter().map(|row| row.feature));
+    let estimation_line = vec![
+        (
+            x_range.start,
+            estimation_model.slope * x_range.start + estimation_model.intercept,
+        ),
+        (
+            x_range.end,
+            estimation_model.slope * x_range.end + estimation_model.intercept,
+        ),
+    ];
+    let y_range = get_range(
+        training_rows
+            .iter()
+            .map(|row| row.target)
+            .chain(estimation_line.iter().map(|(_, y)| *y)),
+    );
+
     root.fill(&WHITE)?;
     let mut chart = ChartBuilder::on(&root)
         .x_label_area_size(40)
         .y_label_area_size(40)
-        .build_cartesian_2d(
-            f64::from(0.0)..f64::from(100.0),
-            f64::from(0.0)..f64::from(100.0),
-        )?;
+        .build_cartesian_2d(x_range, y_range)?;
     chart
         .configure_mesh()
         .x_desc("feature")
@@ -151,13 +428,7 @@ fn draw_model_plot(
         .label("target")
         .legend(|(x, y)| Circle::new((x + 10, y), 2, GREEN.filled()));
     chart
-        .draw_series(LineSeries::new(
-            vec![
-                (0.0, estimation_model.intercept),
-                (100.0, 100.0 * estimation_model.slope),
-            ],
-            &RED,
-        ))?
+        .draw_series(LineSeries::new(estimation_line, &RED))?
         .label("estimation")
         .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
     chart
@@ -186,12 +457,223 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         intercept: 0.0,
     };
 
-    let (result, costs) =
-        gradient_descent(&training_rows, &initial, learning_rate, iteration_count);
+    // Trained on the autodiff tape; `gradient_descent` with the hand-derived
+    // gradient gives the same model.
+    let (result, costs) = gradient_descent_with(
+        |estimation_model| get_autodiff_cost_and_gradient(&training_rows, estimation_model),
+        &initial,
+        learning_rate,
+        iteration_count,
+    );
 
-    draw_model_plot("plot/before_training.png", &training_rows, &initial)?;
-    draw_model_plot("plot/after_training.png", &training_rows, &result)?;
+    draw_model_plot(
+        &PlotOutput::new("plot/before_training.png", (1024, 768)),
+        &training_rows,
+        &initial,
+    )?;
+    draw_model_plot(
+        &PlotOutput::new("plot/after_training.png", (1024, 768)),
+        &training_rows,
+        &result,
+    )?;
 
-    draw_costs_plot("plot/training.png", learning_rate, &costs, 0, 100)?;
+    draw_costs_plot(
+        &PlotOutput::new("plot/training.png", (640, 480)),
+        learning_rate,
+        &costs,
+        0,
+        100,
+        CostScale::Log,
+    )?;
+
+    let learning_rates = [1.0e-5, 3.0e-5, 1.0e-4, 3.0e-4];
+    let learning_rate_costs = learning_rates
+        .iter()
+        .map(|learning_rate| {
+            let (_, costs) =
+                gradient_descent(&training_rows, &initial, *learning_rate, iteration_count);
+            (format!("learning rate {}", learning_rate), costs)
+        })
+        .collect::<Vec<_>>();
+    draw_costs_comparison_plot(
+        &PlotOutput::new("plot/learning_rates.png", (640, 480)),
+        &learning_rate_costs
+            .iter()
+            .map(|(label, costs)| (label.as_str(), costs.as_slice()))
+            .collect::<Vec<_>>(),
+        0,
+        200,
+        CostScale::Log,
+    )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn get_noiseless_training_rows() -> Vec<TrainingRow> {
+        (0..20)
+            .map(|x| TrainingRow {
+                feature: f64::from(x) / 10.0,
+                target: 2.5 * f64::from(x) / 10.0 - 1.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gradient_descent_recovers_slope_and_intercept_of_noiseless_data() {
+        let initial = EstimationModel {
+            slope: 0.0,
+            intercept: 0.0,
+        };
+        let (result, costs) = gradient_descent(&get_noiseless_training_rows(), &initial, 0.5, 5000);
+        assert!((result.slope - 2.5).abs() < 1.0e-9, "{:?}", result);
+        assert!((result.intercept + 1.0).abs() < 1.0e-9, "{:?}", result);
+        assert!(costs[costs.len() - 1] < 1.0e-18);
+    }
+
+    /// Compares `get_gradient` with central differences of
+    /// `get_cost_of_rows_1`, by the relative error of each component.
+    #[test]
+    fn gradient_matches_central_differences() {
+        let training_rows = (0..20)
+            .map(|x| TrainingRow {
+                feature: f64::from(x) / 10.0,
+                target: f64::from((x * 7) % 13) / 4.0 - 1.0,
+            })
+            .collect::<Vec<_>>();
+        let epsilon = 1.0e-5;
+        for estimation_model in [
+            EstimationModel {
+                slope: 0.0,
+                intercept: 0.0,
+            },
+            EstimationModel {
+                slope: -1.3,
+                intercept: 0.8,
+            },
+        ] {
+            let gradient = get_gradient(&training_rows, &estimation_model);
+            let get_difference = |shifted: &dyn Fn(f64) -> EstimationModel| {
+                (get_cost_of_rows_1(&training_rows, &shifted(epsilon))
+                    - get_cost_of_rows_1(&training_rows, &shifted(-epsilon)))
+                    / (2.0 * epsilon)
+            };
+            let numerical_gradient = Gradient {
+                slope: get_difference(&|delta| EstimationModel {
+                    slope: estimation_model.slope + delta,
+                    ..estimation_model
+                }),
+                intercept: get_difference(&|delta| EstimationModel {
+                    intercept: estimation_model.intercept + delta,
+                    ..estimation_model
+                }),
+            };
+            for (analytic, numerical) in [
+                (gradient.slope, numerical_gradient.slope),
+                (gradient.intercept, numerical_gradient.intercept),
+            ] {
+                let relative_error =
+                    (analytic - numerical).abs() / (analytic.abs() + numerical.abs()).max(1.0e-12);
+                assert!(
+                    relative_error < 1.0e-6,
+                    "{:?} {:?}",
+                    gradient,
+                    numerical_gradient
+                );
+            }
+        }
+    }
+
+    fn training_rows_strategy() -> impl Strategy<Value = Vec<TrainingRow>> {
+        prop::collection::vec((-3.0..3.0_f64, -100.0..100.0_f64), 1..30).prop_map(|rows| {
+            rows.into_iter()
+                .map(|(feature, target)| TrainingRow { feature, target })
+                .collect()
+        })
+    }
+
+    proptest! {
+        /// The Hessian of the cost has a trace of `mean(x²) + 1`, at most 10
+        /// for features in [-3, 3], so every learning rate below 2 / 10 is
+        /// small enough.
+        #[test]
+        fn cost_does_not_increase_with_a_small_learning_rate(
+            training_rows in training_rows_strategy(),
+            learning_rate in 1.0e-4..0.19_f64,
+        ) {
+            let initial = EstimationModel {
+                slope: 0.0,
+                intercept: 0.0,
+            };
+            let (_, costs) = gradient_descent(&training_rows, &initial, learning_rate, 50);
+            for pair in costs.windows(2) {
+                prop_assert!(pair[1] <= pair[0] * (1.0 + 1.0e-12), "{:?}", costs);
+            }
+        }
+    }
+
+    #[test]
+    fn training_on_the_autodiff_tape_matches_gradient_descent() {
+        let training_rows = (0..20)
+            .map(|x| TrainingRow {
+                feature: f64::from(x) / 10.0,
+                target: f64::from((x * 7) % 13) / 4.0 - 1.0,
+            })
+            .collect::<Vec<_>>();
+        let initial = EstimationModel {
+            slope: 0.0,
+            intercept: 0.0,
+        };
+        let (result, costs) = gradient_descent(&training_rows, &initial, 0.1, 100);
+        let (autodiff_result, autodiff_costs) = gradient_descent_with(
+            |estimation_model| get_autodiff_cost_and_gradient(&training_rows, estimation_model),
+            &initial,
+            0.1,
+            100,
+        );
+
+        let get_relative_error = |a: f64, b: f64| (a - b).abs() / (a.abs() + b.abs()).max(1.0e-12);
+        assert!(
+            get_relative_error(autodiff_result.slope, result.slope) < 1.0e-10,
+            "{:?} {:?}",
+            autodiff_result,
+            result
+        );
+        assert!(
+            get_relative_error(autodiff_result.intercept, result.intercept) < 1.0e-10,
+            "{:?} {:?}",
+            autodiff_result,
+            result
+        );
+        for (autodiff_cost, cost) in autodiff_costs.iter().zip(costs.iter()) {
+            assert!(get_relative_error(*autodiff_cost, *cost) < 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn cost_plot_rejects_bad_iteration_ranges_and_non_positive_log_costs() {
+        let draw = |series: &[(&str, &[f64])], iteration_end: usize, scale: CostScale| {
+            let mut svg = String::new();
+            draw_costs_series_on(
+                SVGBackend::with_string(&mut svg, (320, 240)).into_drawing_area(),
+                "costs",
+                series,
+                0,
+                iteration_end,
+                scale,
+            )
+        };
+        let costs = [4.0, f64::NAN, 1.0, 0.0];
+        let short_costs = [4.0, 2.0];
+        assert!(draw(&[("cost", &costs)], 4, CostScale::Linear).is_ok());
+        assert!(draw(&[("cost", &costs)], 4, CostScale::Log).is_ok());
+        assert!(draw(&[("cost", &costs)], 5, CostScale::Linear).is_err());
+        assert!(draw(&[("a", &costs), ("b", &short_costs)], 4, CostScale::Linear).is_err());
+        assert!(draw(&[("a", &costs), ("b", &short_costs)], 2, CostScale::Log).is_ok());
+        assert!(draw(&[("cost", &[0.0, -1.0])], 2, CostScale::Log).is_err());
+        assert!(draw(&[], 2, CostScale::Linear).is_err());
+    }
+}